@@ -1,4 +1,4 @@
-use chat_cli::{ChatClient, AnyChatClient, MockLLMClient};
+use chat_cli::{ChatClient, AnyChatClient, MockLLMClient, StreamEvent};
 use anyhow::Result;
 
 #[tokio::main]
@@ -8,7 +8,7 @@ async fn main() -> Result<()> {
 
     // Example 1: Basic mock client
     println!("\n1. Basic Mock Client:");
-    let mut basic_client = AnyChatClient::new_mock();
+    let basic_client = AnyChatClient::new_mock();
     
     let response1 = basic_client.send_message("Hello!").await?;
     println!("User: Hello!");
@@ -25,7 +25,7 @@ async fn main() -> Result<()> {
         "All systems are operational.".to_string(),
         "Test completed successfully.".to_string(),
     ];
-    let mut custom_client = AnyChatClient::new_mock_with_responses(custom_responses);
+    let custom_client = AnyChatClient::new_mock_with_responses(custom_responses);
 
     for i in 1..=4 {
         let response = custom_client.send_message(&format!("Test message {}", i)).await?;
@@ -35,14 +35,18 @@ async fn main() -> Result<()> {
 
     // Example 3: Streaming responses
     println!("\n3. Streaming Response:");
-    let mut streaming_client = AnyChatClient::new_mock();
+    let streaming_client = AnyChatClient::new_mock();
     let mut rx = streaming_client.send_message_stream("Tell me about Rust programming").await?;
     
     print!("User: Tell me about Rust programming\nMock: ");
-    while let Some((chunk, function_call)) = rx.recv().await {
-        print!("{}", chunk);
-        if let Some(fc) = function_call {
-            println!("\n[Function Call: {}]", fc);
+    while let Some(event) = rx.recv().await {
+        match event {
+            StreamEvent::TextDelta(chunk) => print!("{}", chunk),
+            StreamEvent::ToolCallComplete { name, args, .. } => {
+                println!("\n[Function Call: {} {}]", name, args);
+            }
+            StreamEvent::Error(e) => println!("\n[Error: {}]", e),
+            _ => {}
         }
     }
     println!();
@@ -58,7 +62,7 @@ async fn main() -> Result<()> {
     });
     function_client.add_function_call_response("weather", weather_function);
 
-    let mut any_client = AnyChatClient::Mock(function_client);
+    let any_client = AnyChatClient::Mock(function_client);
     let response = any_client.send_message("What's the weather like in San Francisco?").await?;
     println!("User: What's the weather like in San Francisco?");
     println!("Mock: {}", response);
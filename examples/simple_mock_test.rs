@@ -1,4 +1,4 @@
-use chat_cli::{ChatClient, AnyChatClient, MockLLMClient};
+use chat_cli::{ChatClient, AnyChatClient, MockLLMClient, StreamEvent};
 use anyhow::Result;
 
 #[tokio::main]
@@ -34,8 +34,10 @@ async fn main() -> Result<()> {
     let mut rx = client.send_message_stream("Tell me about testing").await?;
     
     print!("User: Tell me about testing\nMock: ");
-    while let Some((chunk, _function_call)) = rx.recv().await {
-        print!("{}", chunk);
+    while let Some(event) = rx.recv().await {
+        if let StreamEvent::TextDelta(chunk) = event {
+            print!("{}", chunk);
+        }
     }
     println!();
 
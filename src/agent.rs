@@ -0,0 +1,521 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::{mpsc, Notify};
+use crate::chat_client::{ChatClient, StreamEvent};
+use crate::context::{CompactionReport, ContextManager};
+use crate::function_calling::{FunctionCall, FunctionExecutor, FunctionResponse, ToolOutput};
+use crate::permissions::{PermissionDecision, PermissionPolicy, PolicyVerdict};
+
+/// Default cap on model requests per user turn
+pub const DEFAULT_MAX_STEPS: usize = 10;
+
+/// Why a turn ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    /// The model answered without asking for more tool calls
+    Completed,
+    /// The turn hit the configured step limit while the model still wanted tools
+    MaxStepsReached,
+    /// The turn was stopped through a `StopHandle`
+    Stopped,
+}
+
+/// One model request within a turn and what came back from it
+#[derive(Debug, Clone, Default)]
+pub struct AgentStep {
+    pub text: String,
+    pub tool_calls: Vec<FunctionCall>,
+    pub errors: Vec<String>,
+    /// The stream was cut short by a `StopHandle`; `text` is what had arrived
+    pub interrupted: bool,
+}
+
+/// Outcome of `Agent::run_turn`
+#[derive(Debug, Clone)]
+pub struct TurnResult {
+    /// Text of the last model response
+    pub text: String,
+    /// Number of model requests made
+    pub steps: usize,
+    pub stop_reason: StopReason,
+}
+
+/// Cloneable flag for stopping a running turn from elsewhere (e.g. a Ctrl+C handler)
+#[derive(Debug, Clone, Default)]
+pub struct StopHandle(Arc<StopState>);
+
+#[derive(Debug, Default)]
+struct StopState {
+    stopped: AtomicBool,
+    notify: Notify,
+}
+
+impl StopHandle {
+    pub fn stop(&self) {
+        self.0.stopped.store(true, Ordering::Relaxed);
+        self.0.notify.notify_waiters();
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.0.stopped.load(Ordering::Relaxed)
+    }
+
+    /// Wait until `stop` is called
+    pub async fn stopped(&self) {
+        let notified = self.0.notify.notified();
+        tokio::pin!(notified);
+        // Register before checking the flag so a concurrent `stop` is not missed
+        notified.as_mut().enable();
+        if !self.is_stopped() {
+            notified.await;
+        }
+    }
+
+    /// Clear an earlier stop; `Agent::run_turn` does this when a turn starts
+    pub fn reset(&self) {
+        self.0.stopped.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Hooks for displaying a turn while it runs. Every method defaults to doing nothing.
+#[async_trait]
+pub trait AgentObserver: Send {
+    /// A model request is about to be sent; `step` starts at 1
+    async fn on_step_start(&mut self, _step: usize) -> Result<()> {
+        Ok(())
+    }
+
+    /// An event arrived on the response stream
+    async fn on_stream_event(&mut self, _event: &StreamEvent) -> Result<()> {
+        Ok(())
+    }
+
+    /// The response stream finished
+    async fn on_step_end(&mut self, _step: &AgentStep) -> Result<()> {
+        Ok(())
+    }
+
+    /// The conversation was compacted to fit the context window before a request
+    async fn on_context_compacted(&mut self, _report: &CompactionReport) -> Result<()> {
+        Ok(())
+    }
+
+    /// The model request could not be sent; the turn ends with this error
+    async fn on_step_failed(&mut self, _error: &anyhow::Error) -> Result<()> {
+        Ok(())
+    }
+
+    /// The policy wants the user to approve `call`; observers that cannot ask deny it
+    async fn on_permission_request(&mut self, _call: &FunctionCall) -> Result<PermissionDecision> {
+        Ok(PermissionDecision::Deny)
+    }
+
+    /// A tool call was refused by the policy or the user and did not run
+    async fn on_tool_denied(&mut self, _call: &FunctionCall, _reason: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// A tool is about to run
+    async fn on_tool_call(&mut self, _call: &FunctionCall) -> Result<()> {
+        Ok(())
+    }
+
+    /// A running tool printed a line of output
+    async fn on_tool_output(&mut self, _call: &FunctionCall, _output: &ToolOutput) -> Result<()> {
+        Ok(())
+    }
+
+    /// A tool finished running
+    async fn on_tool_result(&mut self, _call: &FunctionCall, _result: &Result<FunctionResponse>) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Observer that displays nothing
+pub struct NoopObserver;
+
+impl AgentObserver for NoopObserver {}
+
+/// Runs a user turn to completion: queries the model, executes the tools it
+/// asks for, feeds the results back and repeats until it gives a final answer.
+pub struct Agent {
+    executor: FunctionExecutor,
+    max_steps: usize,
+    stop_handle: StopHandle,
+    permissions: PermissionPolicy,
+    /// Compacts the conversation before requests that would overflow the context window
+    context: Option<ContextManager>,
+}
+
+impl Agent {
+    pub fn new(executor: FunctionExecutor) -> Self {
+        Self {
+            executor,
+            max_steps: DEFAULT_MAX_STEPS,
+            stop_handle: StopHandle::default(),
+            permissions: PermissionPolicy::allow_all(),
+            context: None,
+        }
+    }
+
+    /// Decide which tool calls need approval; by default every call runs
+    pub fn with_permissions(mut self, permissions: PermissionPolicy) -> Self {
+        self.permissions = permissions;
+        self
+    }
+
+    pub fn permissions(&self) -> &PermissionPolicy {
+        &self.permissions
+    }
+
+    /// Keep the conversation within the model's context window; without this it is sent whole
+    pub fn with_context(mut self, context: ContextManager) -> Self {
+        self.context = Some(context);
+        self
+    }
+
+    pub fn context(&self) -> Option<&ContextManager> {
+        self.context.as_ref()
+    }
+
+    pub fn set_context(&mut self, context: ContextManager) {
+        self.context = Some(context);
+    }
+
+    /// Set the maximum number of model requests per turn (at least 1)
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps.max(1);
+        self
+    }
+
+    pub fn max_steps(&self) -> usize {
+        self.max_steps
+    }
+
+    /// Handle that stops the current turn at the next safe point
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop_handle.clone()
+    }
+
+    /// Add `input` to the conversation and run the turn until the model stops calling tools
+    pub async fn run_turn(
+        &self,
+        client: &mut dyn ChatClient,
+        input: &str,
+        observer: &mut dyn AgentObserver,
+    ) -> Result<TurnResult> {
+        self.stop_handle.reset();
+        client.add_user_message(input);
+
+        let mut text = String::new();
+        for step_number in 1..=self.max_steps {
+            if self.stop_handle.is_stopped() {
+                return Ok(TurnResult { text, steps: step_number - 1, stop_reason: StopReason::Stopped });
+            }
+
+            if let Some(context) = &self.context {
+                if let Some(report) = context.fit(&mut *client).await {
+                    observer.on_context_compacted(&report).await?;
+                }
+            }
+
+            observer.on_step_start(step_number).await?;
+            let step = match self.run_step(&*client, observer).await {
+                Ok(step) => step,
+                Err(e) => {
+                    observer.on_step_failed(&e).await?;
+                    return Err(e);
+                }
+            };
+            observer.on_step_end(&step).await?;
+
+            if step.interrupted {
+                // Keep the partial answer so the next message has its context, marked as cut short
+                client.add_model_response(&step.text, &[]);
+                mark_last_interrupted(client);
+                return Ok(TurnResult { text: step.text, steps: step_number, stop_reason: StopReason::Stopped });
+            }
+            client.add_model_response(&step.text, &step.tool_calls);
+            text = step.text;
+
+            if step.tool_calls.is_empty() {
+                let stop_reason = if self.stop_handle.is_stopped() { StopReason::Stopped } else { StopReason::Completed };
+                return Ok(TurnResult { text, steps: step_number, stop_reason });
+            }
+
+            // Every call gets exactly one result, even on failure or when stopped,
+            // because providers reject a history with unanswered tool calls
+            for call in &step.tool_calls {
+                let response = if self.stop_handle.is_stopped() {
+                    FunctionResponse::error(call, "Stopped by user before this tool ran")
+                } else if let Some(reason) = self.check_permission(call, observer).await? {
+                    observer.on_tool_denied(call, &reason).await?;
+                    FunctionResponse::denied(call, &reason)
+                } else {
+                    observer.on_tool_call(call).await?;
+                    let result = self.execute_tool(call, observer).await?;
+                    observer.on_tool_result(call, &result).await?;
+                    result.unwrap_or_else(|e| FunctionResponse::error(call, &e.to_string()))
+                };
+                client.add_function_response(&response);
+            }
+        }
+
+        Ok(TurnResult { text, steps: self.max_steps, stop_reason: StopReason::MaxStepsReached })
+    }
+
+    /// Run one tool, forwarding its live output to the observer. Stopping cancels the tool;
+    /// dropping it kills any process it started.
+    async fn execute_tool(&self, call: &FunctionCall, observer: &mut dyn AgentObserver) -> Result<Result<FunctionResponse>> {
+        let (output_tx, mut output_rx) = mpsc::unbounded_channel();
+        let execution = self.executor.execute_function_with_output(call, output_tx);
+        tokio::pin!(execution);
+
+        let result = loop {
+            tokio::select! {
+                result = &mut execution => break result,
+                Some(output) = output_rx.recv() => observer.on_tool_output(call, &output).await?,
+                _ = self.stop_handle.stopped() => break Err(anyhow::anyhow!("Cancelled by user")),
+            }
+        };
+        while let Ok(output) = output_rx.try_recv() {
+            observer.on_tool_output(call, &output).await?;
+        }
+        Ok(result)
+    }
+
+    /// Apply the permission policy, asking the observer when needed; returns why the call was denied
+    async fn check_permission(&self, call: &FunctionCall, observer: &mut dyn AgentObserver) -> Result<Option<String>> {
+        let read_only = self.executor.registry().get(&call.name).is_some_and(|tool| tool.is_read_only());
+        match self.permissions.check(call, read_only) {
+            PolicyVerdict::Allowed => Ok(None),
+            PolicyVerdict::Denied(reason) => Ok(Some(reason)),
+            PolicyVerdict::Ask => match observer.on_permission_request(call).await? {
+                PermissionDecision::Allow => Ok(None),
+                PermissionDecision::AlwaysAllow => {
+                    self.permissions.remember(call);
+                    Ok(None)
+                }
+                PermissionDecision::Deny => Ok(Some("The user declined this tool call".to_string())),
+            },
+        }
+    }
+
+    /// Send the conversation so far and collect the streamed response
+    async fn run_step(&self, client: &dyn ChatClient, observer: &mut dyn AgentObserver) -> Result<AgentStep> {
+        let mut rx = client.send_message_stream("").await?;
+        let mut step = AgentStep::default();
+
+        loop {
+            let event = tokio::select! {
+                event = rx.recv() => match event {
+                    Some(event) => event,
+                    None => break,
+                },
+                // Dropping the receiver aborts the provider's streaming task
+                _ = self.stop_handle.stopped() => {
+                    step.tool_calls.clear();
+                    step.interrupted = true;
+                    break;
+                }
+            };
+            observer.on_stream_event(&event).await?;
+            match event {
+                StreamEvent::TextDelta(chunk) => step.text.push_str(&chunk),
+                StreamEvent::ToolCallComplete { id, name, args, .. } => {
+                    step.tool_calls.push(FunctionCall { id: Some(id), name, args });
+                }
+                StreamEvent::Error(e) => step.errors.push(e),
+                _ => {}
+            }
+
+        }
+
+        Ok(step)
+    }
+}
+
+/// Flag the conversation's last message, the response just added, as interrupted
+fn mark_last_interrupted(client: &mut dyn ChatClient) {
+    let mut conversation = client.conversation();
+    if let Some(last) = conversation.messages_mut().last_mut() {
+        last.metadata.interrupted = true;
+    }
+    client.set_conversation(conversation);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_llm::MockLLMClient;
+    use crate::conversation::Role;
+
+    fn echo_client() -> MockLLMClient {
+        let mut client = MockLLMClient::with_responses(vec!["All done.".to_string()]).with_delay(1);
+        client.add_function_call_response("greet", serde_json::json!({
+            "name": "shell_command",
+            "args": {"command": "echo hello"}
+        }));
+        client
+    }
+
+    #[tokio::test]
+    async fn test_run_turn_executes_tools_until_final_answer() {
+        let mut client = echo_client();
+        let agent = Agent::new(FunctionExecutor::new());
+
+        let result = agent.run_turn(&mut client, "Please greet me", &mut NoopObserver).await.unwrap();
+
+        assert_eq!(result.stop_reason, StopReason::Completed);
+        assert_eq!(result.steps, 2);
+        assert_eq!(result.text, "Mock LLM: All done.");
+
+        let roles: Vec<_> = client.get_conversation_history().into_iter().map(|m| m.role).collect();
+        assert_eq!(roles, [Role::User, Role::Assistant, Role::Tool, Role::Assistant]);
+    }
+
+    #[tokio::test]
+    async fn test_run_turn_respects_max_steps() {
+        let mut client = echo_client();
+        let agent = Agent::new(FunctionExecutor::new()).with_max_steps(1);
+
+        let result = agent.run_turn(&mut client, "Please greet me", &mut NoopObserver).await.unwrap();
+
+        assert_eq!(result.stop_reason, StopReason::MaxStepsReached);
+        assert_eq!(result.steps, 1);
+    }
+
+    struct StopOnToolCall(StopHandle);
+
+    #[async_trait]
+    impl AgentObserver for StopOnToolCall {
+        async fn on_tool_call(&mut self, _call: &FunctionCall) -> Result<()> {
+            self.0.stop();
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_turn_can_be_stopped() {
+        let mut client = echo_client();
+        let agent = Agent::new(FunctionExecutor::new());
+        let mut observer = StopOnToolCall(agent.stop_handle());
+
+        let result = agent.run_turn(&mut client, "Please greet me", &mut observer).await.unwrap();
+
+        assert_eq!(result.stop_reason, StopReason::Stopped);
+        assert_eq!(result.steps, 1);
+    }
+
+    #[tokio::test]
+    async fn test_denied_call_is_reported_to_the_model() {
+        let mut client = echo_client();
+        let agent = Agent::new(FunctionExecutor::new()).with_permissions(PermissionPolicy::new());
+
+        // NoopObserver cannot ask the user, so the shell command is denied
+        let result = agent.run_turn(&mut client, "Please greet me", &mut NoopObserver).await.unwrap();
+        assert_eq!(result.stop_reason, StopReason::Completed);
+
+        let history = client.get_conversation_history();
+        assert_eq!(history[2].role, Role::Tool);
+        let result = history[2].tool_results().next().unwrap();
+        assert_eq!(result.response["denied"], true, "{}", result.response);
+    }
+
+    struct StopDuringToolCall(StopHandle);
+
+    #[async_trait]
+    impl AgentObserver for StopDuringToolCall {
+        async fn on_tool_call(&mut self, _call: &FunctionCall) -> Result<()> {
+            let handle = self.0.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                handle.stop();
+            });
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stop_cancels_running_tool() {
+        let mut client = MockLLMClient::with_responses(vec!["Done.".to_string()]).with_delay(1);
+        client.add_function_call_response("wait", serde_json::json!({
+            "name": "shell_command",
+            "args": {"command": "sleep 30"}
+        }));
+        let agent = Agent::new(FunctionExecutor::new());
+        let mut observer = StopDuringToolCall(agent.stop_handle());
+
+        let started = std::time::Instant::now();
+        let result = agent.run_turn(&mut client, "Please wait", &mut observer).await.unwrap();
+
+        assert_eq!(result.stop_reason, StopReason::Stopped);
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+        let history = client.get_conversation_history();
+        let result = history[2].tool_results().next().unwrap();
+        assert!(result.response.to_string().contains("Cancelled by user"), "{}", result.response);
+    }
+
+    struct StopOnFirstText(StopHandle);
+
+    #[async_trait]
+    impl AgentObserver for StopOnFirstText {
+        async fn on_stream_event(&mut self, event: &StreamEvent) -> Result<()> {
+            if let StreamEvent::TextDelta(_) = event {
+                self.0.stop();
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stop_interrupts_stream_and_keeps_partial_answer() {
+        let long_answer = "word ".repeat(200);
+        let mut client = MockLLMClient::with_responses(vec![long_answer]).with_delay(1000);
+        let agent = Agent::new(FunctionExecutor::new());
+        let mut observer = StopOnFirstText(agent.stop_handle());
+
+        let started = std::time::Instant::now();
+        let result = agent.run_turn(&mut client, "Talk for a while", &mut observer).await.unwrap();
+
+        assert_eq!(result.stop_reason, StopReason::Stopped);
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        let history = client.get_conversation_history();
+        let answer = history.last().unwrap();
+        assert_eq!(answer.role, Role::Assistant);
+        assert!(answer.metadata.interrupted);
+        assert!(!answer.text().is_empty() && answer.text().len() < 200, "{:?}", answer.text());
+    }
+
+    #[derive(Default)]
+    struct RecordCompactions(Vec<CompactionReport>);
+
+    #[async_trait]
+    impl AgentObserver for RecordCompactions {
+        async fn on_context_compacted(&mut self, report: &CompactionReport) -> Result<()> {
+            self.0.push(report.clone());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_context_manager_drops_old_turns_before_requests() {
+        let mut client = echo_client();
+        for turn in 0..3 {
+            client.add_user_message(&format!("Old question {} {}", turn, "x".repeat(2000)));
+            client.add_model_response("Old answer", &[]);
+        }
+        let context = ContextManager::new(crate::context::CompactionStrategy::DropOldest).with_limit(1000);
+        let agent = Agent::new(FunctionExecutor::new()).with_context(context);
+        let mut observer = RecordCompactions::default();
+
+        let result = agent.run_turn(&mut client, "Please greet me", &mut observer).await.unwrap();
+
+        assert_eq!(result.stop_reason, StopReason::Completed);
+        assert_eq!(observer.0.len(), 1);
+        assert_eq!(observer.0[0].messages_removed, 6);
+        assert_eq!(client.get_conversation_history()[0].text(), "Please greet me");
+    }
+}
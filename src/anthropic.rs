@@ -0,0 +1,684 @@
+use anyhow::Result;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use futures::stream::StreamExt;
+use tokio::sync::mpsc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use crate::chat_client::{spawn_stream, take_until, StreamEvent, TokenUsage};
+use crate::function_calling::ToolRegistry;
+use crate::conversation::{ChatMessage, Conversation, MessagePart, Role};
+use crate::generation::GenerationOptions;
+use crate::retry::RetryPolicy;
+
+/// Version of the Messages API the requests are written against
+pub const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// The API requires a limit on the answer length; this one is used unless max_tokens is set
+pub const DEFAULT_ANTHROPIC_MAX_TOKENS: u32 = 8192;
+
+/// Client for Anthropic's native Messages API (`/v1/messages`)
+#[derive(Clone)]
+pub struct AnthropicClient {
+    client: Client,
+    api_key: String,
+    model: String,
+    base_url: String,
+    conversation: Conversation,
+    tools: ToolRegistry,
+    options: GenerationOptions,
+    last_usage: Arc<Mutex<Option<TokenUsage>>>,
+    retry: RetryPolicy,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Message {
+    pub role: String,
+    pub content: Vec<ContentBlock>,
+}
+
+/// A content block of a message. Tool calls and their results are blocks too.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentBlock {
+    Text {
+        text: String,
+    },
+    Image {
+        source: ImageSource,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        is_error: bool,
+    },
+    /// Blocks this client does not use, such as extended thinking
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ImageSource {
+    #[serde(rename = "type")]
+    pub source_type: String,
+    pub media_type: String,
+    pub data: String,
+}
+
+/// Translate a conversation message to a Messages API message. Tool results are sent
+/// as `tool_result` blocks in a user message; system messages go to the `system` field.
+fn to_wire_message(message: &ChatMessage) -> Option<Message> {
+    let role = match message.role {
+        Role::System => return None,
+        Role::Assistant => "assistant",
+        Role::User | Role::Tool => "user",
+    };
+    let content = message.parts.iter()
+        .filter_map(|part| match part {
+            // The API rejects empty text blocks, which tool-only turns would otherwise produce
+            MessagePart::Text { text } if text.is_empty() => None,
+            MessagePart::Text { text } => Some(ContentBlock::Text { text: text.clone() }),
+            MessagePart::Image { mime_type, data } => Some(ContentBlock::Image {
+                source: ImageSource {
+                    source_type: "base64".to_string(),
+                    media_type: mime_type.clone(),
+                    data: data.clone(),
+                },
+            }),
+            MessagePart::ToolCall(call) => Some(ContentBlock::ToolUse {
+                id: call.call_id(),
+                name: call.name.clone(),
+                input: call.args.clone(),
+            }),
+            MessagePart::ToolResult(result) => Some(ContentBlock::ToolResult {
+                tool_use_id: result.id.clone(),
+                content: serde_json::to_string(&result.response).unwrap_or_else(|_| "Error serializing response".to_string()),
+                is_error: result.response.get("success") == Some(&serde_json::Value::Bool(false)),
+            }),
+        })
+        .collect();
+    Some(Message { role: role.to_string(), content })
+}
+
+#[derive(Debug, Serialize)]
+struct MessagesRequest {
+    model: String,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<Tool>>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Tool {
+    name: String,
+    description: String,
+    input_schema: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessagesResponse {
+    content: Vec<ContentBlock>,
+    #[serde(default)]
+    usage: Usage,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Usage {
+    #[serde(default)]
+    input_tokens: u32,
+    #[serde(default)]
+    output_tokens: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    #[serde(rename = "type")]
+    error_type: String,
+    message: String,
+}
+
+// Streaming event structures
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamingEvent {
+    MessageStart {
+        message: MessageStart,
+    },
+    ContentBlockStart {
+        index: usize,
+        content_block: ContentBlock,
+    },
+    ContentBlockDelta {
+        index: usize,
+        delta: BlockDelta,
+    },
+    ContentBlockStop {
+        index: usize,
+    },
+    MessageDelta {
+        delta: MessageDeltaBody,
+        #[serde(default)]
+        usage: Usage,
+    },
+    MessageStop,
+    Error {
+        error: ApiError,
+    },
+    /// `ping` and event types added after this client was written
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessageStart {
+    #[serde(default)]
+    usage: Usage,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BlockDelta {
+    TextDelta {
+        text: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessageDeltaBody {
+    #[serde(default)]
+    stop_reason: Option<String>,
+}
+
+/// A `tool_use` block whose input is still arriving
+struct PendingToolUse {
+    index: usize,
+    id: String,
+    name: String,
+    input_json: String,
+}
+
+/// Turns Messages API stream events into `StreamEvent`s.
+///
+/// Content blocks are numbered across text and tool use; tool calls are renumbered
+/// from zero so indexes match the other clients. A call is complete at its
+/// `content_block_stop`, once all of its `input_json_delta` fragments have arrived.
+#[derive(Default)]
+struct StreamDecoder {
+    tool_uses: HashMap<usize, PendingToolUse>,
+    tool_count: usize,
+    input_tokens: u32,
+}
+
+impl StreamDecoder {
+    fn decode(&mut self, event: StreamingEvent) -> Vec<StreamEvent> {
+        match event {
+            StreamingEvent::MessageStart { message } => {
+                self.input_tokens = message.usage.input_tokens;
+                Vec::new()
+            }
+            StreamingEvent::ContentBlockStart { index, content_block } => match content_block {
+                ContentBlock::ToolUse { id, name, .. } => {
+                    let tool = PendingToolUse {
+                        index: self.tool_count,
+                        id: id.clone(),
+                        name: name.clone(),
+                        input_json: String::new(),
+                    };
+                    self.tool_count += 1;
+                    let event = StreamEvent::ToolCallStart { index: tool.index, id, name };
+                    self.tool_uses.insert(index, tool);
+                    vec![event]
+                }
+                ContentBlock::Text { text } if !text.is_empty() => vec![StreamEvent::TextDelta(text)],
+                _ => Vec::new(),
+            },
+            StreamingEvent::ContentBlockDelta { index, delta } => match delta {
+                BlockDelta::TextDelta { text } if !text.is_empty() => vec![StreamEvent::TextDelta(text)],
+                BlockDelta::InputJsonDelta { partial_json } if !partial_json.is_empty() => {
+                    match self.tool_uses.get_mut(&index) {
+                        Some(tool) => {
+                            tool.input_json.push_str(&partial_json);
+                            vec![StreamEvent::ToolCallArgsDelta { index: tool.index, delta: partial_json }]
+                        }
+                        None => Vec::new(),
+                    }
+                }
+                _ => Vec::new(),
+            },
+            StreamingEvent::ContentBlockStop { index } => match self.tool_uses.remove(&index) {
+                Some(tool) => {
+                    let args = if tool.input_json.trim().is_empty() {
+                        serde_json::json!({})
+                    } else {
+                        serde_json::from_str(&tool.input_json).unwrap_or_else(|_| serde_json::json!({}))
+                    };
+                    vec![StreamEvent::ToolCallComplete { index: tool.index, id: tool.id, name: tool.name, args }]
+                }
+                None => Vec::new(),
+            },
+            StreamingEvent::MessageDelta { delta, usage } => {
+                let mut events = vec![StreamEvent::Usage(TokenUsage {
+                    prompt_tokens: self.input_tokens,
+                    completion_tokens: usage.output_tokens,
+                    total_tokens: self.input_tokens + usage.output_tokens,
+                })];
+                if let Some(reason) = delta.stop_reason {
+                    events.push(StreamEvent::FinishReason(reason));
+                }
+                events
+            }
+            StreamingEvent::Error { error } => {
+                vec![StreamEvent::Error(format!("{}: {}", error.error_type, error.message))]
+            }
+            StreamingEvent::MessageStop | StreamingEvent::Other => Vec::new(),
+        }
+    }
+}
+
+impl AnthropicClient {
+    pub fn new(api_key: String, model: String) -> Self {
+        Self {
+            client: Client::new(),
+            api_key,
+            model,
+            base_url: "https://api.anthropic.com".to_string(),
+            conversation: Conversation::new(),
+            tools: ToolRegistry::with_builtin_tools(),
+            options: GenerationOptions::default(),
+            last_usage: Arc::new(Mutex::new(None)),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Send requests to another server, such as a proxy or a local mock; `/v1/messages` is appended
+    pub fn with_base_url(mut self, base_url: String) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Set the tools offered to the model
+    pub fn with_tools(mut self, tools: ToolRegistry) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    pub fn set_tools(&mut self, tools: ToolRegistry) {
+        self.tools = tools;
+    }
+
+    /// Token usage of the last non-streaming request
+    pub fn last_usage(&self) -> Option<TokenUsage> {
+        self.last_usage.lock().unwrap().clone()
+    }
+
+    /// Retry failed requests according to `retry`
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Set the sampling and length options sent with each request
+    pub fn with_generation_options(mut self, options: GenerationOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn generation_options(&self) -> &GenerationOptions {
+        &self.options
+    }
+
+    pub fn set_generation_options(&mut self, options: GenerationOptions) {
+        self.options = options;
+    }
+
+    pub fn set_model(&mut self, model: &str) {
+        self.model = model.to_string();
+    }
+
+    pub fn load_system_prompt(&mut self, prompt_content: &str) -> Result<()> {
+        self.conversation.system_prompt = Some(prompt_content.to_string());
+        Ok(())
+    }
+
+    pub fn add_user_message(&mut self, message: &str) {
+        self.conversation.push(ChatMessage::user(message));
+    }
+
+    pub fn add_function_response(&mut self, function_response: &crate::function_calling::FunctionResponse) {
+        self.conversation.push(ChatMessage::tool_result(function_response));
+    }
+
+    pub fn add_model_response(&mut self, response: &str, tool_calls: &[crate::function_calling::FunctionCall]) {
+        self.conversation.push(ChatMessage::assistant(response, tool_calls).with_model(&self.model));
+    }
+
+    pub fn clear_conversation(&mut self) {
+        self.conversation.clear();
+    }
+
+    pub fn conversation(&self) -> &Conversation {
+        &self.conversation
+    }
+
+    pub fn set_conversation(&mut self, conversation: Conversation) {
+        self.conversation = conversation;
+    }
+
+    /// The top-level system field: the system prompt plus any system messages in the history
+    fn build_system(&self) -> Option<String> {
+        let texts: Vec<String> = self.conversation.system_prompt.iter()
+            .cloned()
+            .chain(self.conversation.messages().iter()
+                .filter(|m| m.role == Role::System)
+                .map(|m| m.text()))
+            .collect();
+        if texts.is_empty() { None } else { Some(texts.join("\n\n")) }
+    }
+
+    /// The API expects user and assistant turns to alternate, so consecutive messages
+    /// with the same role (e.g. several tool results) are merged into one
+    fn build_messages(&self, user_message: Option<&str>) -> Vec<Message> {
+        let mut messages: Vec<Message> = Vec::new();
+        let new_message = user_message.map(ChatMessage::user);
+
+        for message in self.conversation.messages().iter().chain(new_message.as_ref()) {
+            let Some(wire) = to_wire_message(message) else { continue };
+            if wire.content.is_empty() {
+                continue;
+            }
+            match messages.last_mut() {
+                Some(last) if last.role == wire.role => last.content.extend(wire.content),
+                _ => messages.push(wire),
+            }
+        }
+
+        messages
+    }
+
+    fn build_tools(&self) -> Option<Vec<Tool>> {
+        if self.tools.is_empty() {
+            return None;
+        }
+
+        Some(
+            self.tools
+                .definitions()
+                .into_iter()
+                .map(|tool| Tool {
+                    name: tool.name,
+                    description: tool.description,
+                    input_schema: tool.parameters,
+                })
+                .collect(),
+        )
+    }
+
+    /// The Messages API has no seed or penalties. Reasoning effort is not mapped: extended
+    /// thinking needs its signed thinking blocks sent back, which the conversation does not keep.
+    fn build_request(&self, message: &str, stream: bool) -> MessagesRequest {
+        MessagesRequest {
+            model: self.model.clone(),
+            max_tokens: self.options.max_tokens.unwrap_or(DEFAULT_ANTHROPIC_MAX_TOKENS),
+            system: self.build_system(),
+            messages: self.build_messages(if message.is_empty() { None } else { Some(message) }),
+            temperature: self.options.temperature,
+            top_p: self.options.top_p,
+            top_k: self.options.top_k,
+            stop_sequences: self.options.stop.clone(),
+            tools: self.build_tools(),
+            stream,
+        }
+    }
+
+    async fn post(&self, request: &MessagesRequest) -> Result<reqwest::Response> {
+        self.retry.send(|| {
+            self.client
+                .post(format!("{}/v1/messages", self.base_url))
+                .header("x-api-key", &self.api_key)
+                .header("anthropic-version", ANTHROPIC_VERSION)
+                .header("Content-Type", "application/json")
+                .json(request)
+        }).await
+    }
+
+    pub async fn send_message(&self, message: &str) -> Result<String> {
+        let response = self.post(&self.build_request(message, false)).await?;
+        let api_response: MessagesResponse = response.json().await?;
+        let usage = &api_response.usage;
+        *self.last_usage.lock().unwrap() = Some(TokenUsage {
+            prompt_tokens: usage.input_tokens,
+            completion_tokens: usage.output_tokens,
+            total_tokens: usage.input_tokens + usage.output_tokens,
+        });
+
+        Ok(api_response.content
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect())
+    }
+
+    pub async fn send_message_stream(&self, message: &str) -> Result<mpsc::Receiver<StreamEvent>> {
+        let response = self.post(&self.build_request(message, true)).await?;
+        let (tx, rx) = mpsc::channel::<StreamEvent>(1000);
+
+        spawn_stream(tx, move |tx| async move {
+            let mut stream = response.bytes_stream();
+            let mut buffer = Vec::new();
+            let mut decoder = StreamDecoder::default();
+
+            while let Some(chunk_result) = stream.next().await {
+                let chunk = match chunk_result {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        let _ = tx.send(StreamEvent::Error(format!("Stream error: {}", e))).await;
+                        return;
+                    }
+                };
+                buffer.extend_from_slice(&chunk);
+
+                // Each SSE event is an `event:` line and a `data:` line; the JSON repeats the type
+                while let Some(event) = take_until(&mut buffer, b"\n\n") {
+
+                    for json_data in event.lines().filter_map(|line| line.trim().strip_prefix("data:")) {
+                        let events = match serde_json::from_str::<StreamingEvent>(json_data.trim()) {
+                            Ok(event) => decoder.decode(event),
+                            Err(e) => vec![StreamEvent::Error(format!("JSON parse error: {} - Data: {}", e, json_data))],
+                        };
+                        for event in events {
+                            if tx.send(event).await.is_err() {
+                                return; // Receiver dropped
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(rx)
+    }
+}
+
+#[async_trait]
+impl crate::chat_client::ChatClient for AnthropicClient {
+    fn load_system_prompt(&mut self, prompt_content: &str) -> Result<()> {
+        self.load_system_prompt(prompt_content)
+    }
+
+    fn add_user_message(&mut self, message: &str) {
+        self.add_user_message(message)
+    }
+
+    fn add_function_response(&mut self, function_response: &crate::function_calling::FunctionResponse) {
+        self.add_function_response(function_response)
+    }
+
+    fn add_model_response(&mut self, response: &str, tool_calls: &[crate::function_calling::FunctionCall]) {
+        self.add_model_response(response, tool_calls)
+    }
+
+    fn clear_conversation(&mut self) {
+        self.clear_conversation()
+    }
+
+    fn set_tools(&mut self, tools: ToolRegistry) {
+        self.set_tools(tools)
+    }
+
+    async fn send_message(&self, message: &str) -> Result<String> {
+        self.send_message(message).await
+    }
+
+    async fn send_message_stream(&self, message: &str) -> Result<mpsc::Receiver<StreamEvent>> {
+        self.send_message_stream(message).await
+    }
+
+    fn client_name(&self) -> &str {
+        "Anthropic"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn set_model(&mut self, model: &str) {
+        self.set_model(model)
+    }
+
+    fn conversation(&self) -> Conversation {
+        self.conversation.clone()
+    }
+
+    fn set_conversation(&mut self, conversation: Conversation) {
+        self.set_conversation(conversation)
+    }
+
+    fn last_usage(&self) -> Option<TokenUsage> {
+        self.last_usage()
+    }
+
+    fn generation_options(&self) -> &GenerationOptions {
+        self.generation_options()
+    }
+
+    fn set_generation_options(&mut self, options: GenerationOptions) {
+        self.set_generation_options(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::function_calling::{FunctionCall, FunctionResponse};
+    use crate::test_support::serve_once;
+
+    fn sse(events: &[serde_json::Value]) -> String {
+        events.iter()
+            .map(|event| format!("event: {}\ndata: {}\n\n", event["type"].as_str().unwrap(), event))
+            .collect()
+    }
+
+    #[test]
+    fn test_conversation_translates_to_messages() {
+        let mut client = AnthropicClient::new("key".to_string(), "claude".to_string());
+        client.load_system_prompt("Be brief.").unwrap();
+        client.add_user_message("Read both files");
+        let calls = [
+            FunctionCall { id: Some("toolu_1".to_string()), name: "read_file".to_string(), args: serde_json::json!({"path": "a"}) },
+            FunctionCall { id: Some("toolu_2".to_string()), name: "read_file".to_string(), args: serde_json::json!({"path": "b"}) },
+        ];
+        client.add_model_response("", &calls);
+        for call in &calls {
+            client.add_function_response(&FunctionResponse {
+                id: call.call_id(),
+                name: call.name.clone(),
+                response: serde_json::json!({"success": true}),
+            });
+        }
+
+        let request = serde_json::to_value(client.build_request("", false)).unwrap();
+        assert_eq!(request["system"], "Be brief.");
+        assert_eq!(request["messages"], serde_json::json!([
+            {"role": "user", "content": [{"type": "text", "text": "Read both files"}]},
+            {"role": "assistant", "content": [
+                {"type": "tool_use", "id": "toolu_1", "name": "read_file", "input": {"path": "a"}},
+                {"type": "tool_use", "id": "toolu_2", "name": "read_file", "input": {"path": "b"}}
+            ]},
+            {"role": "user", "content": [
+                {"type": "tool_result", "tool_use_id": "toolu_1", "content": "{\"success\":true}"},
+                {"type": "tool_result", "tool_use_id": "toolu_2", "content": "{\"success\":true}"}
+            ]}
+        ]));
+        assert!(request.get("stream").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_streams_text_and_tool_use_from_server() {
+        let body = sse(&[
+            serde_json::json!({"type": "message_start", "message": {"id": "msg_1", "type": "message", "role": "assistant", "content": [], "usage": {"input_tokens": 25, "output_tokens": 1}}}),
+            serde_json::json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+            serde_json::json!({"type": "ping"}),
+            serde_json::json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Let me look."}}),
+            serde_json::json!({"type": "content_block_stop", "index": 0}),
+            serde_json::json!({"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "id": "toolu_1", "name": "read_file", "input": {}}}),
+            serde_json::json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"path\":"}}),
+            serde_json::json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": " \"a.txt\"}"}}),
+            serde_json::json!({"type": "content_block_stop", "index": 1}),
+            serde_json::json!({"type": "message_delta", "delta": {"stop_reason": "tool_use"}, "usage": {"output_tokens": 40}}),
+            serde_json::json!({"type": "message_stop"}),
+        ]);
+        let (url, server) = serve_once("text/event-stream", body).await;
+        let mut client = AnthropicClient::new("test-key".to_string(), "claude".to_string()).with_base_url(url);
+        client.load_system_prompt("Be brief.").unwrap();
+
+        let mut rx = client.send_message_stream("What is in a.txt?").await.unwrap();
+        let mut events = Vec::new();
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+
+        assert_eq!(events, vec![
+            StreamEvent::TextDelta("Let me look.".to_string()),
+            StreamEvent::ToolCallStart { index: 0, id: "toolu_1".to_string(), name: "read_file".to_string() },
+            StreamEvent::ToolCallArgsDelta { index: 0, delta: "{\"path\":".to_string() },
+            StreamEvent::ToolCallArgsDelta { index: 0, delta: " \"a.txt\"}".to_string() },
+            StreamEvent::ToolCallComplete { index: 0, id: "toolu_1".to_string(), name: "read_file".to_string(), args: serde_json::json!({"path": "a.txt"}) },
+            StreamEvent::Usage(TokenUsage { prompt_tokens: 25, completion_tokens: 40, total_tokens: 65 }),
+            StreamEvent::FinishReason("tool_use".to_string()),
+        ]);
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with("POST /v1/messages "), "{}", request);
+        assert!(request.contains("x-api-key: test-key"));
+        assert!(request.contains(&format!("anthropic-version: {}", ANTHROPIC_VERSION)));
+        assert!(request.contains("\"system\":\"Be brief.\""));
+        assert!(request.contains("\"stream\":true"));
+    }
+}
@@ -1,7 +1,11 @@
+// Demo binary kept as written; these are the lints its original code trips
+#![allow(dead_code, unused_variables, clippy::useless_format, clippy::len_without_is_empty)]
+
 use std::collections::HashMap;
 use std::error::Error;
 use std::{fmt, time};
 use std::thread::sleep;
+use chat_cli::retry::RetryPolicy;
 
 // Custom error type for RAG operations
 #[derive(Debug)]
@@ -99,6 +103,7 @@ impl VectorStore {
 pub struct GeminiClient {
     api_key: String,
     base_url: String,
+    retry: RetryPolicy,
 }
 
 impl GeminiClient {
@@ -106,9 +111,18 @@ impl GeminiClient {
         Self {
             api_key,
             base_url: "https://generativelanguage.googleapis.com/v1beta".to_string(),
+            retry: RetryPolicy::from_env().unwrap_or_default(),
         }
     }
 
+    // POST a JSON body, retrying rate limits and server errors
+    async fn post(&self, client: &reqwest::Client, url: &str, body: &serde_json::Value) -> Result<reqwest::Response, RagError> {
+        self.retry
+            .send(|| client.post(url).header("Content-Type", "application/json").json(body))
+            .await
+            .map_err(|e| RagError::ApiError(e.to_string()))
+    }
+
     // Generate embedding for text using Gemini API
     pub async fn generate_embedding(&self, text: &str) -> Result<Vec<f32>, RagError> {
         let client = reqwest::Client::new();
@@ -121,20 +135,9 @@ impl GeminiClient {
             }
         });
 
-        let response = client
-            .post(&url)
-            .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
-            .await
-            .map_err(|e| RagError::ApiError(e.to_string()))?;
+        let response = self.post(&client, &url, &request_body).await?;
         sleep(time::Duration::from_millis(15_000));
 
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(RagError::ApiError(format!("API request failed: {}", error_text)));
-        }
-
         let response_json: serde_json::Value = response
             .json()
             .await
@@ -162,18 +165,7 @@ impl GeminiClient {
             }]
         });
 
-        let response = client
-            .post(&url)
-            .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
-            .await
-            .map_err(|e| RagError::ApiError(e.to_string()))?;
-
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(RagError::ApiError(format!("API request failed: {}", error_text)));
-        }
+        let response = self.post(&client, &url, &request_body).await?;
 
         let response_json: serde_json::Value = response
             .json()
@@ -1,7 +1,64 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use tokio::sync::mpsc;
-use crate::function_calling::FunctionResponse;
+use crate::function_calling::{FunctionCall, FunctionResponse, ToolRegistry};
+use crate::conversation::Conversation;
+use crate::generation::GenerationOptions;
+
+/// Token counts reported by a provider for a single request
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
+}
+
+/// A single event produced by a streaming response
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamEvent {
+    /// A chunk of model text
+    TextDelta(String),
+    /// A chunk of the model's reasoning summary; not part of the answer
+    ReasoningDelta(String),
+    /// The model started a tool call
+    ToolCallStart { index: usize, id: String, name: String },
+    /// A fragment of the JSON arguments for the tool call at `index`
+    ToolCallArgsDelta { index: usize, delta: String },
+    /// A fully assembled tool call, ready to execute
+    ToolCallComplete { index: usize, id: String, name: String, args: serde_json::Value },
+    /// Token usage for the request
+    Usage(TokenUsage),
+    /// Why the model stopped generating (e.g. "stop", "tool_calls")
+    FinishReason(String),
+    /// A transport or parse failure; never part of the model's answer
+    Error(String),
+}
+
+/// Spawn the task that feeds a response stream into `tx`. The task is aborted as soon as
+/// the receiver is dropped, closing the connection rather than reading the rest of the response.
+pub fn spawn_stream<F, Fut>(tx: mpsc::Sender<StreamEvent>, task: F)
+where
+    F: FnOnce(mpsc::Sender<StreamEvent>) -> Fut,
+    Fut: std::future::Future<Output = ()> + Send + 'static,
+{
+    let receiver_alive = tx.clone();
+    let task = task(tx);
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = task => {}
+            _ = receiver_alive.closed() => {}
+        }
+    });
+}
+
+/// Remove the bytes before the first `delimiter` from `buffer` and decode them. Network
+/// chunks can end inside a multi-byte character, so streams buffer raw bytes and only
+/// decode complete events or lines.
+pub fn take_until(buffer: &mut Vec<u8>, delimiter: &[u8]) -> Option<String> {
+    let end = buffer.windows(delimiter.len()).position(|window| window == delimiter)?;
+    let taken: Vec<u8> = buffer.drain(..end + delimiter.len()).take(end).collect();
+    Some(String::from_utf8_lossy(&taken).into_owned())
+}
 
 /// Generic trait for chat clients that can communicate with different LLM providers
 #[async_trait]
@@ -15,28 +72,66 @@ pub trait ChatClient {
     /// Add a function response to the conversation history
     fn add_function_response(&mut self, function_response: &FunctionResponse);
     
-    /// Add a model response to the conversation history
-    /// The function_call parameter format may vary between providers but should be JSON
-    fn add_model_response(&mut self, response: &str, function_call: Option<serde_json::Value>);
+    /// Add a model response to the conversation history, along with every tool call it made
+    fn add_model_response(&mut self, response: &str, tool_calls: &[FunctionCall]);
     
     /// Clear the conversation history
     fn clear_conversation(&mut self);
     
+    /// Set the tools offered to the model with each request
+    fn set_tools(&mut self, tools: ToolRegistry);
+    
     /// Send a message and get a non-streaming response
     async fn send_message(&self, message: &str) -> Result<String>;
     
     /// Send a message and get a streaming response
-    /// Returns a receiver that yields (text_chunk, optional_function_call) tuples
-    async fn send_message_stream(&self, message: &str) -> Result<mpsc::Receiver<(String, Option<serde_json::Value>)>>;
+    /// Returns a receiver that yields `StreamEvent`s until the response is complete
+    async fn send_message_stream(&self, message: &str) -> Result<mpsc::Receiver<StreamEvent>>;
     
     /// Get the name of the client (for display purposes)
     fn client_name(&self) -> &str;
+    
+    /// The model requests are sent to
+    fn model(&self) -> &str;
+    
+    /// Send later requests to another model of the same provider
+    fn set_model(&mut self, model: &str);
+    
+    /// A copy of the system prompt and message history
+    fn conversation(&self) -> Conversation;
+    
+    /// Replace the system prompt and message history, e.g. with a saved session
+    fn set_conversation(&mut self, conversation: Conversation);
+    
+    /// Token usage of the last `send_message` call; streaming responses report theirs
+    /// as `StreamEvent::Usage` instead
+    fn last_usage(&self) -> Option<TokenUsage> {
+        None
+    }
+    
+    /// The context window in tokens, when the client knows it better than the model name does
+    fn context_limit(&self) -> Option<usize> {
+        None
+    }
+    
+    /// The sampling and length options sent with each request
+    fn generation_options(&self) -> &GenerationOptions;
+    
+    /// Replace the generation options for later requests
+    fn set_generation_options(&mut self, options: GenerationOptions);
+    
+    /// Names of the models the provider offers
+    async fn list_models(&self) -> Result<Vec<String>> {
+        Err(anyhow!("{} cannot list models", self.client_name()))
+    }
 }
 
 /// Wrapper enum that implements ChatClient for different provider clients
 pub enum AnyChatClient {
     Gemini(crate::gemini::GeminiClient),
     OpenAI(crate::openai::OpenAIClient),
+    Anthropic(crate::anthropic::AnthropicClient),
+    Ollama(crate::ollama::OllamaClient),
     Mock(crate::mock_llm::MockLLMClient),
 }
 
@@ -46,6 +141,8 @@ impl ChatClient for AnyChatClient {
         match self {
             AnyChatClient::Gemini(client) => client.load_system_prompt(prompt_content),
             AnyChatClient::OpenAI(client) => client.load_system_prompt(prompt_content),
+            AnyChatClient::Anthropic(client) => client.load_system_prompt(prompt_content),
+            AnyChatClient::Ollama(client) => client.load_system_prompt(prompt_content),
             AnyChatClient::Mock(client) => client.load_system_prompt(prompt_content),
         }
     }
@@ -54,6 +151,8 @@ impl ChatClient for AnyChatClient {
         match self {
             AnyChatClient::Gemini(client) => client.add_user_message(message),
             AnyChatClient::OpenAI(client) => client.add_user_message(message),
+            AnyChatClient::Anthropic(client) => client.add_user_message(message),
+            AnyChatClient::Ollama(client) => client.add_user_message(message),
             AnyChatClient::Mock(client) => client.add_user_message(message),
         }
     }
@@ -62,28 +161,19 @@ impl ChatClient for AnyChatClient {
         match self {
             AnyChatClient::Gemini(client) => client.add_function_response(function_response),
             AnyChatClient::OpenAI(client) => client.add_function_response(function_response),
+            AnyChatClient::Anthropic(client) => client.add_function_response(function_response),
+            AnyChatClient::Ollama(client) => client.add_function_response(function_response),
             AnyChatClient::Mock(client) => client.add_function_response(function_response),
         }
     }
     
-    fn add_model_response(&mut self, response: &str, function_call: Option<serde_json::Value>) {
+    fn add_model_response(&mut self, response: &str, tool_calls: &[FunctionCall]) {
         match self {
-            AnyChatClient::Gemini(client) => client.add_model_response(response, function_call),
-            AnyChatClient::OpenAI(client) => {
-                // Convert function call format for OpenAI
-                let tool_calls = function_call.map(|fc| {
-                    vec![crate::openai::ToolCall {
-                        id: format!("call_{}", chrono::Utc::now().timestamp_millis()),
-                        call_type: "function".to_string(),
-                        function: crate::openai::FunctionCall {
-                            name: fc.get("name").and_then(|v| v.as_str()).unwrap_or("unknown").to_string(),
-                            arguments: fc.get("args").map(|v| v.to_string()).unwrap_or_default(),
-                        },
-                    }]
-                });
-                client.add_model_response(response, tool_calls);
-            }
-            AnyChatClient::Mock(client) => client.add_model_response(response, function_call),
+            AnyChatClient::Gemini(client) => client.add_model_response(response, tool_calls),
+            AnyChatClient::OpenAI(client) => client.add_model_response(response, tool_calls),
+            AnyChatClient::Anthropic(client) => client.add_model_response(response, tool_calls),
+            AnyChatClient::Ollama(client) => client.add_model_response(response, tool_calls),
+            AnyChatClient::Mock(client) => client.add_model_response(response, tool_calls),
         }
     }
     
@@ -91,36 +181,265 @@ impl ChatClient for AnyChatClient {
         match self {
             AnyChatClient::Gemini(client) => client.clear_conversation(),
             AnyChatClient::OpenAI(client) => client.clear_conversation(),
+            AnyChatClient::Anthropic(client) => client.clear_conversation(),
+            AnyChatClient::Ollama(client) => client.clear_conversation(),
             AnyChatClient::Mock(client) => client.clear_conversation(),
         }
     }
     
+    fn set_tools(&mut self, tools: ToolRegistry) {
+        match self {
+            AnyChatClient::Gemini(client) => client.set_tools(tools),
+            AnyChatClient::OpenAI(client) => client.set_tools(tools),
+            AnyChatClient::Anthropic(client) => client.set_tools(tools),
+            AnyChatClient::Ollama(client) => client.set_tools(tools),
+            AnyChatClient::Mock(client) => ChatClient::set_tools(client, tools),
+        }
+    }
+    
     async fn send_message(&self, message: &str) -> Result<String> {
         match self {
             AnyChatClient::Gemini(client) => client.send_message(message).await,
             AnyChatClient::OpenAI(client) => client.send_message(message).await,
+            AnyChatClient::Anthropic(client) => client.send_message(message).await,
+            AnyChatClient::Ollama(client) => client.send_message(message).await,
             AnyChatClient::Mock(client) => client.send_message(message).await,
         }
     }
     
-    async fn send_message_stream(&self, message: &str) -> Result<mpsc::Receiver<(String, Option<serde_json::Value>)>> {
+    async fn send_message_stream(&self, message: &str) -> Result<mpsc::Receiver<StreamEvent>> {
         match self {
             AnyChatClient::Gemini(client) => client.send_message_stream(message).await,
             AnyChatClient::OpenAI(client) => client.send_message_stream(message).await,
+            AnyChatClient::Anthropic(client) => client.send_message_stream(message).await,
+            AnyChatClient::Ollama(client) => client.send_message_stream(message).await,
             AnyChatClient::Mock(client) => client.send_message_stream(message).await,
         }
     }
     
+    fn model(&self) -> &str {
+        match self {
+            AnyChatClient::Gemini(client) => ChatClient::model(client),
+            AnyChatClient::OpenAI(client) => ChatClient::model(client),
+            AnyChatClient::Anthropic(client) => ChatClient::model(client),
+            AnyChatClient::Ollama(client) => ChatClient::model(client),
+            AnyChatClient::Mock(client) => ChatClient::model(client),
+        }
+    }
+    
+    fn set_model(&mut self, model: &str) {
+        match self {
+            AnyChatClient::Gemini(client) => client.set_model(model),
+            AnyChatClient::OpenAI(client) => client.set_model(model),
+            AnyChatClient::Anthropic(client) => client.set_model(model),
+            AnyChatClient::Ollama(client) => client.set_model(model),
+            AnyChatClient::Mock(client) => client.set_model(model),
+        }
+    }
+    
+    fn conversation(&self) -> Conversation {
+        match self {
+            AnyChatClient::Gemini(client) => client.conversation().clone(),
+            AnyChatClient::OpenAI(client) => client.conversation().clone(),
+            AnyChatClient::Anthropic(client) => client.conversation().clone(),
+            AnyChatClient::Ollama(client) => client.conversation().clone(),
+            AnyChatClient::Mock(client) => client.conversation(),
+        }
+    }
+    
+    fn set_conversation(&mut self, conversation: Conversation) {
+        match self {
+            AnyChatClient::Gemini(client) => client.set_conversation(conversation),
+            AnyChatClient::OpenAI(client) => client.set_conversation(conversation),
+            AnyChatClient::Anthropic(client) => client.set_conversation(conversation),
+            AnyChatClient::Ollama(client) => client.set_conversation(conversation),
+            AnyChatClient::Mock(client) => client.set_conversation(conversation),
+        }
+    }
+    
+    fn last_usage(&self) -> Option<TokenUsage> {
+        match self {
+            AnyChatClient::Gemini(client) => client.last_usage(),
+            AnyChatClient::OpenAI(client) => client.last_usage(),
+            AnyChatClient::Anthropic(client) => client.last_usage(),
+            AnyChatClient::Ollama(client) => client.last_usage(),
+            AnyChatClient::Mock(client) => client.last_usage(),
+        }
+    }
+    
+    fn context_limit(&self) -> Option<usize> {
+        match self {
+            AnyChatClient::Gemini(client) => client.context_limit(),
+            AnyChatClient::OpenAI(client) => client.context_limit(),
+            AnyChatClient::Anthropic(client) => client.context_limit(),
+            AnyChatClient::Ollama(client) => client.context_limit(),
+            AnyChatClient::Mock(client) => client.context_limit(),
+        }
+    }
+    
+    fn generation_options(&self) -> &GenerationOptions {
+        match self {
+            AnyChatClient::Gemini(client) => client.generation_options(),
+            AnyChatClient::OpenAI(client) => client.generation_options(),
+            AnyChatClient::Anthropic(client) => client.generation_options(),
+            AnyChatClient::Ollama(client) => client.generation_options(),
+            AnyChatClient::Mock(client) => client.generation_options(),
+        }
+    }
+    
+    fn set_generation_options(&mut self, options: GenerationOptions) {
+        match self {
+            AnyChatClient::Gemini(client) => client.set_generation_options(options),
+            AnyChatClient::OpenAI(client) => client.set_generation_options(options),
+            AnyChatClient::Anthropic(client) => client.set_generation_options(options),
+            AnyChatClient::Ollama(client) => client.set_generation_options(options),
+            AnyChatClient::Mock(client) => client.set_generation_options(options),
+        }
+    }
+    
+    async fn list_models(&self) -> Result<Vec<String>> {
+        match self {
+            AnyChatClient::Gemini(client) => ChatClient::list_models(client).await,
+            AnyChatClient::OpenAI(client) => ChatClient::list_models(client).await,
+            AnyChatClient::Anthropic(client) => ChatClient::list_models(client).await,
+            AnyChatClient::Ollama(client) => ChatClient::list_models(client).await,
+            AnyChatClient::Mock(client) => ChatClient::list_models(client).await,
+        }
+    }
+    
     fn client_name(&self) -> &str {
         match self {
             AnyChatClient::Gemini(_) => "Gemini",
-            AnyChatClient::OpenAI(_) => "OpenAI",
+            AnyChatClient::OpenAI(client) => ChatClient::client_name(client),
+            AnyChatClient::Anthropic(_) => "Anthropic",
+            AnyChatClient::Ollama(_) => "Ollama",
             AnyChatClient::Mock(_) => "MockLLM",
         }
     }
 }
 
+/// Providers that `AnyChatClient::from_env` can create
+pub const PROVIDERS: &[&str] = &["openai", "azure", "gemini", "anthropic", "ollama", "mock"];
+
 impl AnyChatClient {
+    /// The provider to start with: `CHAT_CLI_PROVIDER` if set, otherwise the first of
+    /// OpenAI, Azure OpenAI, Gemini and Anthropic whose API key is set. Ollama needs no key, so it is only
+    /// used when asked for.
+    pub fn default_provider() -> Option<String> {
+        if let Some(provider) = std::env::var("CHAT_CLI_PROVIDER").ok().filter(|provider| !provider.trim().is_empty()) {
+            return Some(provider);
+        }
+        [
+            ("OPENAI_API_KEY", "openai"),
+            ("AZURE_OPENAI_API_KEY", "azure"),
+            ("GEMINI_API_KEY", "gemini"),
+            ("ANTHROPIC_API_KEY", "anthropic"),
+        ]
+            .into_iter()
+            .find(|(key, _)| std::env::var(key).is_ok())
+            .map(|(_, provider)| provider.to_string())
+    }
+    
+    /// The environment variable naming `provider`'s model (the deployment on Azure)
+    pub fn model_variable(provider: &str) -> Option<&'static str> {
+        match provider.to_lowercase().as_str() {
+            "openai" => Some("OPENAI_MODEL"),
+            "azure" => Some("AZURE_OPENAI_DEPLOYMENT"),
+            "gemini" => Some("GEMINI_MODEL"),
+            "anthropic" => Some("ANTHROPIC_MODEL"),
+            "ollama" => Some("OLLAMA_MODEL"),
+            _ => None,
+        }
+    }
+    
+    /// The environment variable holding `provider`'s API base URL, if it has one
+    pub fn base_url_variable(provider: &str) -> Option<&'static str> {
+        match provider.to_lowercase().as_str() {
+            "openai" => Some("OPENAI_BASE_URL"),
+            "azure" => Some("AZURE_OPENAI_ENDPOINT"),
+            "anthropic" => Some("ANTHROPIC_BASE_URL"),
+            "ollama" => Some("OLLAMA_HOST"),
+            _ => None,
+        }
+    }
+    
+    /// Create a client for `provider` from its environment variables. Without `model`,
+    /// the provider's model variable (`OPENAI_MODEL`, `GEMINI_MODEL`, `ANTHROPIC_MODEL`, `OLLAMA_MODEL`)
+    /// or its default is used.
+    pub fn from_env(provider: &str, model: Option<&str>) -> Result<Self> {
+        let required = |name: &str| std::env::var(name).map_err(|_| anyhow!("{} is not set", name));
+        let model_or = |name: &str, default: &str| {
+            model.map(str::to_string)
+                .or_else(|| std::env::var(name).ok())
+                .unwrap_or_else(|| default.to_string())
+        };
+        let retry = crate::retry::RetryPolicy::from_env()?;
+        
+        match provider.to_lowercase().as_str() {
+            "openai" => {
+                let base_url = std::env::var("OPENAI_BASE_URL").unwrap_or_else(|_| "https://api.openai.com/v1".to_string());
+                let mut client = crate::openai::OpenAIClient::new(required("OPENAI_API_KEY")?, model_or("OPENAI_MODEL", "gpt-4"))
+                    .with_base_url(base_url)
+                    .with_retry_policy(retry);
+                if let Ok(patterns) = std::env::var("OPENAI_RESPONSES_MODELS") {
+                    let patterns: Vec<&str> = patterns.split(',').map(str::trim).filter(|p| !p.is_empty()).collect();
+                    client = client.with_responses_models(&patterns)?;
+                }
+                Ok(AnyChatClient::OpenAI(client))
+            }
+            "azure" => {
+                // On Azure the model is the deployment name
+                let endpoint = required("AZURE_OPENAI_ENDPOINT")?;
+                let api_version = std::env::var("AZURE_OPENAI_API_VERSION")
+                    .unwrap_or_else(|_| crate::openai::DEFAULT_AZURE_API_VERSION.to_string());
+                let deployment = match model {
+                    Some(model) => model.to_string(),
+                    None => required("AZURE_OPENAI_DEPLOYMENT")?,
+                };
+                let client = crate::openai::OpenAIClient::new(required("AZURE_OPENAI_API_KEY")?, deployment)
+                    .with_base_url(endpoint)
+                    .with_azure(api_version)
+                    .with_retry_policy(retry);
+                Ok(AnyChatClient::OpenAI(client))
+            }
+            "gemini" => {
+                let client = crate::gemini::GeminiClient::new(required("GEMINI_API_KEY")?, model_or("GEMINI_MODEL", "gemini-2.0-flash-exp"))
+                    .with_retry_policy(retry);
+                Ok(AnyChatClient::Gemini(client))
+            }
+            "anthropic" => {
+                let mut client = crate::anthropic::AnthropicClient::new(required("ANTHROPIC_API_KEY")?, model_or("ANTHROPIC_MODEL", "claude-sonnet-4-5"))
+                    .with_retry_policy(retry);
+                if let Ok(base_url) = std::env::var("ANTHROPIC_BASE_URL") {
+                    client = client.with_base_url(base_url);
+                }
+                Ok(AnyChatClient::Anthropic(client))
+            }
+            "ollama" => {
+                let mut client = crate::ollama::OllamaClient::new(model_or("OLLAMA_MODEL", "llama3.2"))
+                    .with_retry_policy(retry);
+                if let Ok(host) = std::env::var("OLLAMA_HOST") {
+                    client = client.with_base_url(host);
+                }
+                if let Some(num_ctx) = std::env::var("OLLAMA_NUM_CTX").ok().and_then(|v| v.parse().ok()) {
+                    client = client.with_num_ctx(num_ctx);
+                }
+                if let Ok(keep_alive) = std::env::var("OLLAMA_KEEP_ALIVE") {
+                    client = client.with_keep_alive(keep_alive);
+                }
+                Ok(AnyChatClient::Ollama(client))
+            }
+            "mock" => {
+                let mut client = Self::new_mock();
+                if let Some(model) = model {
+                    client.set_model(model);
+                }
+                Ok(client)
+            }
+            other => Err(anyhow!("Unknown provider '{}'; choose one of: {}", other, PROVIDERS.join(", "))),
+        }
+    }
+    
     /// Create a new Gemini client
     pub fn new_gemini(api_key: String, model: String) -> Self {
         AnyChatClient::Gemini(crate::gemini::GeminiClient::new(api_key, model))
@@ -136,6 +455,16 @@ impl AnyChatClient {
         AnyChatClient::OpenAI(crate::openai::OpenAIClient::new(api_key, model).with_base_url(base_url))
     }
     
+    /// Create a new Anthropic client
+    pub fn new_anthropic(api_key: String, model: String) -> Self {
+        AnyChatClient::Anthropic(crate::anthropic::AnthropicClient::new(api_key, model))
+    }
+    
+    /// Create a new Ollama client for a server on localhost
+    pub fn new_ollama(model: String) -> Self {
+        AnyChatClient::Ollama(crate::ollama::OllamaClient::new(model))
+    }
+    
     /// Create a new Mock LLM client
     pub fn new_mock() -> Self {
         AnyChatClient::Mock(crate::mock_llm::MockLLMClient::new())
@@ -145,4 +474,48 @@ impl AnyChatClient {
     pub fn new_mock_with_responses(responses: Vec<String>) -> Self {
         AnyChatClient::Mock(crate::mock_llm::MockLLMClient::with_responses(responses))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversation::ChatMessage;
+
+    #[test]
+    fn test_take_until_waits_for_split_characters() {
+        let text = "data: héllo\n\n".as_bytes();
+        let split = text.iter().position(|&b| b == 0xC3).unwrap() + 1;
+        let mut buffer = text[..split].to_vec();
+
+        assert_eq!(take_until(&mut buffer, b"\n\n"), None);
+        buffer.extend_from_slice(&text[split..]);
+        assert_eq!(take_until(&mut buffer, b"\n\n").as_deref(), Some("data: héllo"));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_from_env_rejects_unknown_provider() {
+        let error = AnyChatClient::from_env("llama", None).err().unwrap();
+        assert!(error.to_string().contains("openai, azure, gemini, anthropic, ollama, mock"), "{}", error);
+    }
+
+    #[test]
+    fn test_switching_provider_keeps_conversation() {
+        let mut mock = AnyChatClient::from_env("mock", Some("mock-large")).unwrap();
+        mock.load_system_prompt("Be brief.").unwrap();
+        mock.add_user_message("Hello");
+        mock.add_model_response("Hi!", &[]);
+        assert_eq!(mock.model(), "mock-large");
+
+        let mut openai = AnyChatClient::new_openai("key".to_string(), "gpt-4o".to_string());
+        openai.set_conversation(mock.conversation());
+        openai.set_model("gpt-4o-mini");
+        openai.add_user_message("And again");
+
+        let conversation = openai.conversation();
+        assert_eq!(conversation.system_prompt.as_deref(), Some("Be brief."));
+        assert_eq!(conversation.messages()[1].metadata.model.as_deref(), Some("mock-large"));
+        assert_eq!(conversation.last().map(ChatMessage::text).as_deref(), Some("And again"));
+        assert_eq!(openai.model(), "gpt-4o-mini");
+    }
+}
@@ -0,0 +1,301 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use crate::chat_client::AnyChatClient;
+use crate::generation::GenerationOptions;
+
+/// Project config file, read after the user's `~/.config/chat-cli/config.toml`
+pub const PROJECT_CONFIG_FILE: &str = ".chat-cli.toml";
+
+/// System prompt file used when none is configured
+pub const DEFAULT_SYSTEM_PROMPT: &str = "system_prompt.md";
+
+/// Width of the input box and response cards when none is configured
+pub const DEFAULT_WIDTH: usize = 120;
+
+/// Settings from one source: a config file, a profile, the environment or the command line.
+/// Unset fields leave the value of an earlier source in place.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub provider: Option<String>,
+    /// The model, or the deployment on Azure
+    pub model: Option<String>,
+    /// API base URL; the endpoint on Azure and the host for Ollama
+    pub base_url: Option<String>,
+    pub system_prompt: Option<PathBuf>,
+    pub stream: Option<bool>,
+    /// Whether the model is offered tools
+    pub tools: Option<bool>,
+    /// Run tool calls without asking for approval
+    pub yolo: Option<bool>,
+    pub width: Option<usize>,
+    /// Print answers as written instead of rendering their Markdown
+    pub raw: Option<bool>,
+    /// Sampling and length options, e.g. `temperature = 0.2`
+    #[serde(default)]
+    pub generation: GenerationOptions,
+    /// Other environment variables to set when they are not set already, e.g. `CHAT_CLI_TEMPERATURE`
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Named sets of settings selected with `--profile`
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Config>,
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Let the fields `other` sets replace these. A model or base URL belongs to its
+    /// provider, so both are dropped when `other` switches to a different provider.
+    pub fn merge(&mut self, other: Config) {
+        if other.provider.is_some() && other.provider != self.provider {
+            self.model = None;
+            self.base_url = None;
+        }
+        self.provider = other.provider.or(self.provider.take());
+        self.model = other.model.or(self.model.take());
+        self.base_url = other.base_url.or(self.base_url.take());
+        self.system_prompt = other.system_prompt.or(self.system_prompt.take());
+        self.stream = other.stream.or(self.stream);
+        self.tools = other.tools.or(self.tools);
+        self.yolo = other.yolo.or(self.yolo);
+        self.width = other.width.or(self.width);
+        self.raw = other.raw.or(self.raw);
+        self.generation.merge(other.generation);
+        self.env.extend(other.env);
+        for (name, profile) in other.profiles {
+            self.profiles.entry(name).or_default().merge(profile);
+        }
+    }
+
+    /// Add the settings of a TOML config file
+    pub fn merge_toml(mut self, content: &str) -> Result<Self> {
+        let file: Config = toml::from_str(content)?;
+        file.generation.validate()?;
+        for (name, profile) in &file.profiles {
+            profile.generation.validate().with_context(|| format!("Invalid profile '{}'", name))?;
+        }
+        self.merge(file);
+        Ok(self)
+    }
+
+    /// Add the settings of `path` if it exists
+    pub fn merge_file(self, path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => self.merge_toml(&content)
+                .with_context(|| format!("Invalid config file {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(self),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Config files read by `load`: the user's, then the project's
+    pub fn default_paths() -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        if let Some(config_dir) = dirs::config_dir() {
+            paths.push(config_dir.join("chat-cli").join("config.toml"));
+        }
+        paths.push(PathBuf::from(PROJECT_CONFIG_FILE));
+        paths
+    }
+
+    /// The user's config file overridden by the project's
+    pub fn load() -> Result<Self> {
+        Self::default_paths()
+            .iter()
+            .try_fold(Self::new(), |config, path| config.merge_file(path))
+    }
+
+    /// These settings with those of profile `name` on top
+    pub fn with_profile(mut self, name: &str) -> Result<Self> {
+        let profile = self.profiles.get(name).cloned().ok_or_else(|| {
+            let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            if known.is_empty() {
+                anyhow!("Unknown profile '{}'; no config file defines any profiles", name)
+            } else {
+                anyhow!("Unknown profile '{}'; choose one of: {}", name, known.join(", "))
+            }
+        })?;
+        self.merge(profile);
+        Ok(self)
+    }
+
+    /// Settings from environment variables: `CHAT_CLI_PROVIDER`, the model and base URL
+    /// variables of `provider`, `CHAT_CLI_SYSTEM_PROMPT`, `CHAT_CLI_STREAM`, `CHAT_CLI_TOOLS`,
+    /// `CHAT_CLI_YOLO`, `CHAT_CLI_WIDTH`, `CHAT_CLI_RAW` and the generation options' variables
+    pub fn from_env(provider: Option<&str>) -> Result<Self> {
+        let parsed = |name: &str| -> Result<Option<bool>> {
+            env_var(name).map(|value| parse_bool(&value).with_context(|| format!("Invalid {}", name))).transpose()
+        };
+        let provider_var = |variable: fn(&str) -> Option<&'static str>| provider.and_then(variable).and_then(env_var);
+        Ok(Self {
+            provider: env_var("CHAT_CLI_PROVIDER"),
+            model: provider_var(AnyChatClient::model_variable),
+            base_url: provider_var(AnyChatClient::base_url_variable),
+            system_prompt: env_var("CHAT_CLI_SYSTEM_PROMPT").map(PathBuf::from),
+            stream: parsed("CHAT_CLI_STREAM")?,
+            tools: parsed("CHAT_CLI_TOOLS")?,
+            yolo: parsed("CHAT_CLI_YOLO")?,
+            width: env_var("CHAT_CLI_WIDTH")
+                .map(|value| value.parse().map_err(|e| anyhow!("Invalid CHAT_CLI_WIDTH '{}': {}", value, e)))
+                .transpose()?,
+            raw: parsed("CHAT_CLI_RAW")?,
+            generation: GenerationOptions::from_env()?,
+            ..Self::default()
+        })
+    }
+
+    /// Combine the config files' settings, the environment and the command line's, in
+    /// increasing precedence. The provider falls back to the first one with an API key.
+    pub fn resolve(files: Config, flags: Config) -> Result<Self> {
+        let provider = flags.provider.clone()
+            .or_else(|| env_var("CHAT_CLI_PROVIDER"))
+            .or_else(|| files.provider.clone())
+            .or_else(AnyChatClient::default_provider);
+        let mut config = files;
+        config.merge(Self::from_env(provider.as_deref())?);
+        config.merge(flags);
+        config.provider = provider;
+        Ok(config)
+    }
+
+    /// Set the variables of the `env` table that are not set already. Run before `resolve`,
+    /// so the environment layer sees them.
+    pub fn fill_env(&self) {
+        for (name, value) in &self.env {
+            if std::env::var_os(name).is_none() {
+                std::env::set_var(name, value);
+            }
+        }
+    }
+
+    /// Hand the base URL to the code that reads it from the provider's environment variable
+    pub fn export_env(&self) {
+        let Some(provider) = self.provider.as_deref() else { return };
+        if let (Some(base_url), Some(variable)) = (&self.base_url, AnyChatClient::base_url_variable(provider)) {
+            std::env::set_var(variable, base_url);
+        }
+    }
+
+    pub fn system_prompt_path(&self) -> PathBuf {
+        self.system_prompt.clone().unwrap_or_else(|| PathBuf::from(DEFAULT_SYSTEM_PROMPT))
+    }
+
+    pub fn stream_enabled(&self) -> bool {
+        self.stream.unwrap_or(true)
+    }
+
+    pub fn tools_enabled(&self) -> bool {
+        self.tools.unwrap_or(true)
+    }
+
+    pub fn yolo_enabled(&self) -> bool {
+        self.yolo.unwrap_or(false)
+    }
+
+    pub fn display_width(&self) -> usize {
+        self.width.unwrap_or(DEFAULT_WIDTH)
+    }
+
+    pub fn raw_enabled(&self) -> bool {
+        self.raw.unwrap_or(false)
+    }
+}
+
+/// The value of environment variable `name`, unless it is unset or blank
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.trim().is_empty())
+}
+
+/// `true`/`false`, `yes`/`no`, `on`/`off` or `1`/`0`
+pub fn parse_bool(value: &str) -> Result<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        other => Err(anyhow!("'{}' is not true or false", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER_CONFIG: &str = r#"
+provider = "openai"
+model = "gpt-4o"
+stream = false
+
+[generation]
+temperature = 0.2
+stop = ["END"]
+
+[env]
+CHAT_CLI_CONTEXT_STRATEGY = "summarize"
+
+[profiles.work-azure]
+provider = "azure"
+model = "gpt-4o-prod"
+base_url = "https://work.openai.azure.com"
+"#;
+
+    #[test]
+    fn test_project_config_and_profile_override_user_config() {
+        let config = Config::new()
+            .merge_toml(USER_CONFIG).unwrap()
+            .merge_toml("model = \"gpt-4o-mini\"\nwidth = 100\n").unwrap();
+
+        assert_eq!(config.model.as_deref(), Some("gpt-4o-mini"));
+        assert_eq!(config.display_width(), 100);
+        assert!(!config.stream_enabled());
+        assert_eq!(config.env["CHAT_CLI_CONTEXT_STRATEGY"], "summarize");
+        assert_eq!(config.generation.temperature, Some(0.2));
+        assert_eq!(config.generation.stop, ["END"]);
+
+        let work = config.clone().with_profile("work-azure").unwrap();
+        assert_eq!(work.provider.as_deref(), Some("azure"));
+        assert_eq!(work.model.as_deref(), Some("gpt-4o-prod"));
+        assert_eq!(work.display_width(), 100);
+
+        let error = config.with_profile("home").unwrap_err();
+        assert!(error.to_string().contains("work-azure"), "{}", error);
+        assert!(Config::new().merge_toml("modle = \"typo\"").is_err());
+        assert!(Config::new().merge_toml("[generation]\ntemperature = 5.0").is_err());
+        assert!(Config::new().merge_toml("[generation]\nwarmth = 1").is_err());
+    }
+
+    #[test]
+    fn test_flags_override_files() {
+        let files = Config::new().merge_toml(USER_CONFIG).unwrap();
+        let mut flags = Config {
+            provider: Some("mock".to_string()),
+            yolo: Some(true),
+            ..Config::default()
+        };
+        flags.generation.max_tokens = Some(100);
+
+        let config = Config::resolve(files.clone(), flags).unwrap();
+        assert_eq!(config.generation.temperature, Some(0.2));
+        assert_eq!(config.generation.max_tokens, Some(100));
+
+        assert_eq!(config.provider.as_deref(), Some("mock"));
+        assert_eq!(config.model, None, "the file's OpenAI model must not follow another provider");
+        assert!(config.yolo_enabled());
+        assert!(!config.stream_enabled());
+
+        let work = files.with_profile("work-azure").unwrap();
+        let flags = Config { provider: Some("mock".to_string()), ..Config::default() };
+        let config = Config::resolve(work.clone(), flags).unwrap();
+        assert_eq!((config.model, config.base_url), (None, None));
+
+        let flags = Config { model: Some("gpt-4.1".to_string()), ..Config::default() };
+        let config = Config::resolve(work, flags).unwrap();
+        assert_eq!(config.provider.as_deref(), Some("azure"));
+        assert_eq!(config.model.as_deref(), Some("gpt-4.1"));
+        assert_eq!(config.base_url.as_deref(), Some("https://work.openai.azure.com"));
+        assert!(parse_bool("maybe").is_err());
+    }
+}
@@ -0,0 +1,468 @@
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
+use crate::chat_client::ChatClient;
+use crate::conversation::{ChatMessage, Conversation, MessagePart, Role};
+
+/// Tokens kept free for the answer when `max_tokens` is not set
+pub const DEFAULT_RESPONSE_RESERVE: usize = 4096;
+
+/// Tool output strings longer than this are shortened by `CompactionStrategy::TruncateToolOutputs`
+pub const TRUNCATED_TOOL_OUTPUT_CHARS: usize = 500;
+
+/// Context windows in tokens by model glob pattern; the longest matching pattern wins
+const CONTEXT_LIMITS: &[(&str, usize)] = &[
+    ("gpt-4", 8_192),
+    ("gpt-4-32k*", 32_768),
+    ("gpt-4-turbo*", 128_000),
+    ("gpt-4o*", 128_000),
+    ("gpt-4.1*", 1_047_576),
+    ("gpt-5*", 400_000),
+    ("o1*", 200_000),
+    ("o3*", 200_000),
+    ("o4*", 200_000),
+    ("claude-*", 200_000),
+    ("gemini-1.5-pro*", 2_097_152),
+    ("gemini-*", 1_048_576),
+    ("llama3.1*", 128_000),
+    ("llama3.2*", 128_000),
+    ("qwen2.5*", 32_768),
+    ("mistral*", 32_768),
+];
+
+/// Instructions for the request that summarises earlier history
+const SUMMARY_PROMPT: &str = "You compress chat transcripts. Summarise the conversation you are given so that \
+it can replace the original: keep the user's goals and preferences, decisions made, facts learned, file paths, \
+commands and their important results, and any work still open. Be concise, use bullet points, and do not call tools.";
+
+/// Rough token count of `text`: about four characters per token, which errs on the high side for English and code
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Rough token count of a message, including a few tokens of framing
+pub fn estimate_message_tokens(message: &ChatMessage) -> usize {
+    let parts: usize = message.parts.iter()
+        .map(|part| match part {
+            MessagePart::Text { text } => estimate_tokens(text),
+            // Providers bill images by size, not by the length of the base64 data
+            MessagePart::Image { .. } => 1000,
+            MessagePart::ToolCall(call) => estimate_tokens(&call.name) + estimate_tokens(&call.args.to_string()),
+            MessagePart::ToolResult(result) => estimate_tokens(&result.name) + estimate_tokens(&result.response.to_string()),
+        })
+        .sum();
+    parts + 4
+}
+
+/// Rough token count of the system prompt and every message
+pub fn estimate_conversation_tokens(conversation: &Conversation) -> usize {
+    conversation.system_prompt.as_deref().map_or(0, estimate_tokens)
+        + conversation.messages().iter().map(estimate_message_tokens).sum::<usize>()
+}
+
+/// The context window of `model` in tokens, from the built-in table; `None` for unknown models
+pub fn context_limit(model: &str) -> Option<usize> {
+    CONTEXT_LIMITS.iter()
+        .filter(|(pattern, _)| glob::Pattern::new(pattern).is_ok_and(|p| p.matches(model)))
+        .max_by_key(|(pattern, _)| pattern.len())
+        .map(|(_, limit)| *limit)
+}
+
+/// What to do when the conversation no longer fits the context window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompactionStrategy {
+    /// Send everything and let the provider refuse
+    Off,
+    /// Remove the oldest turns
+    DropOldest,
+    /// Shorten the output of older tool calls, then remove the oldest turns if that is not enough
+    TruncateToolOutputs,
+    /// Have the model summarise the earlier turns
+    Summarize,
+}
+
+impl CompactionStrategy {
+    pub const NAMES: &'static [&'static str] = &["off", "drop-oldest", "truncate-tool-output", "summarize"];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompactionStrategy::Off => "off",
+            CompactionStrategy::DropOldest => "drop-oldest",
+            CompactionStrategy::TruncateToolOutputs => "truncate-tool-output",
+            CompactionStrategy::Summarize => "summarize",
+        }
+    }
+}
+
+impl FromStr for CompactionStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().replace('_', "-").as_str() {
+            "off" | "none" => Ok(CompactionStrategy::Off),
+            "drop-oldest" => Ok(CompactionStrategy::DropOldest),
+            "truncate-tool-output" | "truncate-tool-outputs" => Ok(CompactionStrategy::TruncateToolOutputs),
+            "summarize" | "summarise" => Ok(CompactionStrategy::Summarize),
+            other => Err(anyhow!("Unknown context strategy '{}'; use {}", other, CompactionStrategy::NAMES.join(", "))),
+        }
+    }
+}
+
+/// What a compaction did, for display
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactionReport {
+    pub strategy: CompactionStrategy,
+    pub tokens_before: usize,
+    pub tokens_after: usize,
+    pub messages_removed: usize,
+    pub tool_outputs_truncated: usize,
+}
+
+impl std::fmt::Display for CompactionReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Context compacted ({}): ~{} -> ~{} tokens",
+            self.strategy.as_str(),
+            crate::usage::format_tokens(self.tokens_before as u64),
+            crate::usage::format_tokens(self.tokens_after as u64)
+        )?;
+        if self.messages_removed > 0 {
+            write!(f, ", {} messages removed", self.messages_removed)?;
+        }
+        if self.tool_outputs_truncated > 0 {
+            write!(f, ", {} tool outputs shortened", self.tool_outputs_truncated)?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps the conversation within the model's context window before each request
+#[derive(Debug, Clone)]
+pub struct ContextManager {
+    strategy: CompactionStrategy,
+    /// Overrides the per-model limit
+    limit: Option<usize>,
+}
+
+impl ContextManager {
+    pub fn new(strategy: CompactionStrategy) -> Self {
+        Self { strategy, limit: None }
+    }
+
+    /// Use `limit` tokens as the context window of every model
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Settings from `CHAT_CLI_CONTEXT_STRATEGY` (default `drop-oldest`) and `CHAT_CLI_CONTEXT_LIMIT`
+    pub fn from_env() -> Result<Self> {
+        let strategy = match std::env::var("CHAT_CLI_CONTEXT_STRATEGY") {
+            Ok(value) => value.parse()?,
+            Err(_) => CompactionStrategy::DropOldest,
+        };
+        let mut manager = Self::new(strategy);
+        if let Ok(value) = std::env::var("CHAT_CLI_CONTEXT_LIMIT") {
+            let limit = value.parse().map_err(|e| anyhow!("Invalid CHAT_CLI_CONTEXT_LIMIT '{}': {}", value, e))?;
+            manager = manager.with_limit(limit);
+        }
+        Ok(manager)
+    }
+
+    pub fn strategy(&self) -> CompactionStrategy {
+        self.strategy
+    }
+
+    pub fn set_strategy(&mut self, strategy: CompactionStrategy) {
+        self.strategy = strategy;
+    }
+
+    /// The context window of the client's model: the configured limit, the client's own, or the
+    /// table's. `None` when none of them knows the model.
+    pub fn limit_for(&self, client: &dyn ChatClient) -> Option<usize> {
+        self.limit
+            .or_else(|| client.context_limit())
+            .or_else(|| context_limit(client.model()))
+    }
+
+    /// Tokens the conversation may use, leaving room for the answer
+    pub fn budget_for(&self, client: &dyn ChatClient) -> Option<usize> {
+        let limit = self.limit_for(client)?;
+        let reserve = client.generation_options().max_tokens.map_or(DEFAULT_RESPONSE_RESERVE, |tokens| tokens as usize);
+        Some(limit.saturating_sub(reserve.min(limit / 2)))
+    }
+
+    /// Compact the client's conversation if it is over budget; `None` when nothing had to change.
+    /// Nothing is compacted for a model whose context window is unknown, since guessing too
+    /// small would throw away history the model could still read.
+    /// A failed summary falls back to dropping the oldest turns.
+    pub async fn fit(&self, client: &mut dyn ChatClient) -> Option<CompactionReport> {
+        let budget = self.budget_for(client)?;
+        let mut conversation = client.conversation();
+        if self.strategy == CompactionStrategy::Off || estimate_conversation_tokens(&conversation) <= budget {
+            return None;
+        }
+
+        let mut strategy = self.strategy;
+        if strategy == CompactionStrategy::Summarize {
+            if let Ok(report) = summarize(client, 1).await {
+                if estimate_conversation_tokens(&client.conversation()) <= budget {
+                    return Some(report);
+                }
+                conversation = client.conversation();
+            }
+            strategy = CompactionStrategy::DropOldest;
+        }
+
+        let report = compact(&mut conversation, strategy, budget);
+        client.set_conversation(conversation);
+        Some(report)
+    }
+}
+
+/// Indices of the messages that start a turn, i.e. the user messages
+fn turn_starts(conversation: &Conversation) -> Vec<usize> {
+    conversation.messages().iter()
+        .enumerate()
+        .filter(|(_, message)| message.role == Role::User)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Shrink `conversation` below `budget` without a model. Whole turns are removed so every
+/// tool call keeps its result; the last turn is always kept.
+pub fn compact(conversation: &mut Conversation, strategy: CompactionStrategy, budget: usize) -> CompactionReport {
+    let tokens_before = estimate_conversation_tokens(conversation);
+    let mut report = CompactionReport {
+        strategy,
+        tokens_before,
+        tokens_after: tokens_before,
+        messages_removed: 0,
+        tool_outputs_truncated: 0,
+    };
+
+    if strategy == CompactionStrategy::TruncateToolOutputs {
+        // Oldest first, sparing the latest tool results, which the model is about to read
+        let last_tool = conversation.messages().iter().rposition(|message| message.role == Role::Tool);
+        for i in 0..conversation.len() {
+            if estimate_conversation_tokens(conversation) <= budget {
+                break;
+            }
+            if Some(i) == last_tool {
+                continue;
+            }
+            for part in &mut conversation.messages_mut()[i].parts {
+                if let MessagePart::ToolResult(result) = part {
+                    if truncate_strings(&mut result.response) {
+                        report.tool_outputs_truncated += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    if strategy != CompactionStrategy::Off {
+        let starts = turn_starts(conversation);
+        let mut removed = 0;
+        for &next_start in starts.iter().skip(1) {
+            if estimate_conversation_tokens(conversation) <= budget {
+                break;
+            }
+            conversation.drop_oldest(next_start - removed);
+            removed = next_start;
+        }
+        report.messages_removed = removed;
+    }
+
+    report.tokens_after = estimate_conversation_tokens(conversation);
+    report
+}
+
+/// Shorten every long string in a tool response; returns whether anything changed
+fn truncate_strings(value: &mut serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::String(text) if text.chars().count() > TRUNCATED_TOOL_OUTPUT_CHARS => {
+            let removed = text.chars().count() - TRUNCATED_TOOL_OUTPUT_CHARS;
+            let kept: String = text.chars().take(TRUNCATED_TOOL_OUTPUT_CHARS).collect();
+            *text = format!("{}\n... [{} characters removed to save context]", kept, removed);
+            true
+        }
+        // Every element is visited; `any` would stop at the first change
+        serde_json::Value::Array(items) => items.iter_mut().map(truncate_strings).filter(|changed| *changed).count() > 0,
+        serde_json::Value::Object(fields) => fields.values_mut().map(truncate_strings).filter(|changed| *changed).count() > 0,
+        _ => false,
+    }
+}
+
+/// Plain-text rendering of messages for the summary request
+fn transcript(messages: &[ChatMessage]) -> String {
+    let mut text = String::new();
+    for message in messages {
+        let role = match message.role {
+            Role::System => "System",
+            Role::User => "User",
+            Role::Assistant => "Assistant",
+            Role::Tool => "Tool result",
+        };
+        for part in &message.parts {
+            let content = match part {
+                MessagePart::Text { text } => text.clone(),
+                MessagePart::Image { mime_type, .. } => format!("[{} image]", mime_type),
+                MessagePart::ToolCall(call) => format!("[calls {} with {}]", call.name, call.args),
+                MessagePart::ToolResult(result) => {
+                    let mut response = result.response.clone();
+                    truncate_strings(&mut response);
+                    format!("[{} returned {}]", result.name, response)
+                }
+            };
+            text.push_str(&format!("{}: {}\n\n", role, content));
+        }
+    }
+    text
+}
+
+/// Replace all but the last `keep_turns` turns with a summary written by the client's model.
+/// The conversation is left as it was if the request fails.
+pub async fn summarize(client: &mut dyn ChatClient, keep_turns: usize) -> Result<CompactionReport> {
+    let original = client.conversation();
+    let starts = turn_starts(&original);
+    let split = match starts.len().checked_sub(keep_turns) {
+        Some(0) | None => return Err(anyhow!("Nothing to compact yet")),
+        Some(turn) => starts.get(turn).copied().unwrap_or(original.len()),
+    };
+    let (earlier, kept) = original.messages().split_at(split);
+
+    let mut request = Conversation::new();
+    request.system_prompt = Some(SUMMARY_PROMPT.to_string());
+    client.set_conversation(request);
+    let summary = client.send_message(&transcript(earlier)).await;
+    let summary = match summary {
+        Ok(summary) if !summary.trim().is_empty() => summary,
+        Ok(_) => {
+            client.set_conversation(original);
+            return Err(anyhow!("The model returned an empty summary"));
+        }
+        Err(e) => {
+            client.set_conversation(original);
+            return Err(e);
+        }
+    };
+
+    let mut compacted = Conversation::new();
+    compacted.system_prompt = original.system_prompt.clone();
+    compacted.push(ChatMessage::user(&format!("Summary of our conversation so far:\n\n{}", summary.trim())));
+    compacted.push(ChatMessage::assistant("Thanks, I have the context and will continue from there.", &[]));
+    for message in kept {
+        compacted.push(message.clone());
+    }
+
+    let report = CompactionReport {
+        strategy: CompactionStrategy::Summarize,
+        tokens_before: estimate_conversation_tokens(&original),
+        tokens_after: estimate_conversation_tokens(&compacted),
+        messages_removed: earlier.len(),
+        tool_outputs_truncated: 0,
+    };
+    client.set_conversation(compacted);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::function_calling::{FunctionCall, FunctionResponse};
+    use crate::mock_llm::MockLLMClient;
+
+    /// `turns` user turns, each with a tool call whose output is `output_chars` long
+    fn conversation(turns: usize, output_chars: usize) -> Conversation {
+        let mut conversation = Conversation::new();
+        for turn in 0..turns {
+            let call = FunctionCall {
+                id: Some(format!("call-{}", turn)),
+                name: "shell_command".to_string(),
+                args: serde_json::json!({"command": "ls"}),
+            };
+            conversation.push(ChatMessage::user(&format!("Question {}", turn)));
+            conversation.push(ChatMessage::assistant("", &[call]));
+            conversation.push(ChatMessage::tool_result(&FunctionResponse {
+                id: format!("call-{}", turn),
+                name: "shell_command".to_string(),
+                response: serde_json::json!({"success": true, "output": "x".repeat(output_chars)}),
+            }));
+            conversation.push(ChatMessage::assistant(&format!("Answer {}", turn), &[]));
+        }
+        conversation
+    }
+
+    #[test]
+    fn test_limits_and_estimates() {
+        assert_eq!(context_limit("gpt-4o-mini"), Some(128_000));
+        assert_eq!(context_limit("gpt-4"), Some(8_192));
+        assert_eq!(context_limit("gemini-1.5-pro-002"), Some(2_097_152));
+        assert_eq!(context_limit("something-else"), None);
+        assert_eq!(estimate_tokens("abcdefgh"), 2);
+        assert_eq!("truncate_tool_output".parse::<CompactionStrategy>().unwrap(), CompactionStrategy::TruncateToolOutputs);
+    }
+
+    #[test]
+    fn test_drop_oldest_removes_whole_turns_and_keeps_the_last() {
+        let mut history = conversation(3, 4000);
+        let report = compact(&mut history, CompactionStrategy::DropOldest, 1500);
+
+        assert_eq!(report.messages_removed, 8);
+        assert_eq!(history.messages()[0].text(), "Question 2");
+        assert!(report.tokens_after < report.tokens_before);
+
+        // The last turn stays even when it alone is over budget
+        compact(&mut history, CompactionStrategy::DropOldest, 10);
+        assert_eq!(history.len(), 4);
+    }
+
+    #[test]
+    fn test_truncate_tool_output_spares_the_latest_result() {
+        let mut history = conversation(3, 4000);
+        let report = compact(&mut history, CompactionStrategy::TruncateToolOutputs, 1500);
+
+        assert_eq!(report.tool_outputs_truncated, 2);
+        assert_eq!(report.messages_removed, 0);
+        let outputs: Vec<usize> = history.messages().iter()
+            .flat_map(|message| message.tool_results())
+            .map(|result| result.response["output"].as_str().unwrap().len())
+            .collect();
+        assert!(outputs[0] < 600 && outputs[1] < 600);
+        assert_eq!(outputs[2], 4000);
+    }
+
+    #[tokio::test]
+    async fn test_summarize_replaces_earlier_turns() {
+        let mut client = MockLLMClient::with_responses(vec!["- the user asked three questions".to_string()]).with_delay(0);
+        client.set_conversation({
+            let mut history = conversation(3, 10);
+            history.system_prompt = Some("Be brief.".to_string());
+            history
+        });
+
+        let report = summarize(&mut client, 1).await.unwrap();
+        let history = client.conversation();
+
+        assert_eq!(report.messages_removed, 8);
+        assert_eq!(history.system_prompt.as_deref(), Some("Be brief."));
+        assert_eq!(history.len(), 6);
+        assert!(history.messages()[0].text().contains("the user asked three questions"));
+        assert_eq!(history.messages()[2].text(), "Question 2");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_context_window_is_never_compacted() {
+        let mut client = MockLLMClient::new().with_delay(0);
+        client.set_conversation(conversation(3, 40_000));
+        let manager = ContextManager::new(CompactionStrategy::DropOldest);
+
+        assert_eq!(manager.limit_for(&client), None);
+        assert_eq!(manager.fit(&mut client).await, None);
+        assert_eq!(client.conversation().len(), 12);
+
+        let report = manager.with_limit(16_000).fit(&mut client).await.unwrap();
+        assert_eq!(report.messages_removed, 8);
+    }
+}
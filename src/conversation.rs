@@ -0,0 +1,190 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::function_calling::{FunctionCall, FunctionResponse};
+
+/// Who a message comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+    /// Results of tool calls, sent back to the model
+    Tool,
+}
+
+/// One piece of a message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MessagePart {
+    Text { text: String },
+    /// Base64-encoded image data
+    Image { mime_type: String, data: String },
+    ToolCall(FunctionCall),
+    ToolResult(FunctionResponse),
+}
+
+/// Information about a message that is not sent to the model
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MessageMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    /// The model that wrote an assistant message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// The user stopped the response before it was finished
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub interrupted: bool,
+}
+
+/// A message in provider-neutral form. Clients translate it to their wire format per request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub parts: Vec<MessagePart>,
+    #[serde(default)]
+    pub metadata: MessageMetadata,
+}
+
+impl ChatMessage {
+    pub fn new(role: Role, parts: Vec<MessagePart>) -> Self {
+        Self {
+            role,
+            parts,
+            metadata: MessageMetadata {
+                created_at: Some(Utc::now()),
+                ..MessageMetadata::default()
+            },
+        }
+    }
+
+    pub fn system(text: &str) -> Self {
+        Self::new(Role::System, vec![MessagePart::Text { text: text.to_string() }])
+    }
+
+    pub fn user(text: &str) -> Self {
+        Self::new(Role::User, vec![MessagePart::Text { text: text.to_string() }])
+    }
+
+    /// A model response; empty text is left out so tool-only turns have no text part
+    pub fn assistant(text: &str, tool_calls: &[FunctionCall]) -> Self {
+        let mut parts = Vec::new();
+        if !text.is_empty() {
+            parts.push(MessagePart::Text { text: text.to_string() });
+        }
+        parts.extend(tool_calls.iter().cloned().map(MessagePart::ToolCall));
+        Self::new(Role::Assistant, parts)
+    }
+
+    pub fn tool_result(response: &FunctionResponse) -> Self {
+        Self::new(Role::Tool, vec![MessagePart::ToolResult(response.clone())])
+    }
+
+    /// Record which model wrote the message
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.metadata.model = Some(model.to_string());
+        self
+    }
+
+    /// All text parts joined together
+    pub fn text(&self) -> String {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                MessagePart::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn tool_calls(&self) -> impl Iterator<Item = &FunctionCall> {
+        self.parts.iter().filter_map(|part| match part {
+            MessagePart::ToolCall(call) => Some(call),
+            _ => None,
+        })
+    }
+
+    pub fn tool_results(&self) -> impl Iterator<Item = &FunctionResponse> {
+        self.parts.iter().filter_map(|part| match part {
+            MessagePart::ToolResult(response) => Some(response),
+            _ => None,
+        })
+    }
+}
+
+/// The system prompt and message history shared by every client
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Conversation {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    #[serde(default)]
+    messages: Vec<ChatMessage>,
+}
+
+impl Conversation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn messages(&self) -> &[ChatMessage] {
+        &self.messages
+    }
+
+    /// The messages for editing in place, e.g. to shorten old tool output
+    pub fn messages_mut(&mut self) -> &mut [ChatMessage] {
+        &mut self.messages
+    }
+
+    /// Remove the first `count` messages
+    pub fn drop_oldest(&mut self, count: usize) {
+        self.messages.drain(..count.min(self.messages.len()));
+    }
+
+    pub fn push(&mut self, message: ChatMessage) {
+        self.messages.push(message);
+    }
+
+    pub fn last(&self) -> Option<&ChatMessage> {
+        self.messages.last()
+    }
+
+    /// Remove every message but keep the system prompt
+    pub fn clear(&mut self) {
+        self.messages.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_serialization() {
+        let call = FunctionCall {
+            id: Some("call-1".to_string()),
+            name: "read_file".to_string(),
+            args: serde_json::json!({"path": "Cargo.toml"}),
+        };
+        let mut message = ChatMessage::assistant("Let me look.", &[call]).with_model("gpt-4");
+        message.metadata.created_at = None;
+
+        assert_eq!(serde_json::to_value(&message).unwrap(), serde_json::json!({
+            "role": "assistant",
+            "parts": [
+                {"type": "text", "text": "Let me look."},
+                {"type": "tool_call", "id": "call-1", "name": "read_file", "args": {"path": "Cargo.toml"}}
+            ],
+            "metadata": {"model": "gpt-4"}
+        }));
+        let parsed: ChatMessage = serde_json::from_value(serde_json::to_value(&message).unwrap()).unwrap();
+        assert_eq!(parsed, message);
+    }
+}
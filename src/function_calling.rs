@@ -1,115 +1,1592 @@
 use anyhow::{anyhow, Result};
+use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::process::Command as AsyncCommand;
+use tokio::sync::mpsc;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Lines returned by `read_file` when no end line is given
+const DEFAULT_READ_LINES: usize = 2000;
+/// Maximum paths returned by `glob`
+const MAX_GLOB_RESULTS: usize = 500;
+/// Maximum matching lines returned by `grep`
+const MAX_GREP_MATCHES: usize = 200;
+/// Matching lines longer than this are cut short in `grep` results
+const MAX_GREP_LINE_CHARS: usize = 500;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FunctionCall {
+    /// Provider-assigned call id, echoed back with the result so the two can be matched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     pub name: String,
     pub args: serde_json::Value,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FunctionResponse {
     pub id: String,
     pub name: String,
     pub response: serde_json::Value,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl FunctionCall {
+    /// Id to report the result under: the provider's id, or a generated one
+    pub fn call_id(&self) -> String {
+        self.id.clone().unwrap_or_else(|| {
+            format!("{}-{}", self.name, chrono::Utc::now().timestamp_millis())
+        })
+    }
+}
+
+impl FunctionResponse {
+    /// Build the response reported back to the model when a call could not be executed
+    pub fn error(function_call: &FunctionCall, message: &str) -> Self {
+        Self {
+            id: function_call.call_id(),
+            name: function_call.name.clone(),
+            response: serde_json::json!({
+                "success": false,
+                "error": message,
+            }),
+        }
+    }
+
+    /// Build the response reported back to the model when a call was not permitted to run
+    pub fn denied(function_call: &FunctionCall, reason: &str) -> Self {
+        Self {
+            id: function_call.call_id(),
+            name: function_call.name.clone(),
+            response: serde_json::json!({
+                "success": false,
+                "denied": true,
+                "error": format!("Permission denied: {}", reason),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolDefinition {
     pub name: String,
     pub description: String,
     pub parameters: serde_json::Value,
 }
 
-pub struct FunctionExecutor;
+/// A line of output printed by a tool while it runs
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolOutput {
+    Stdout(String),
+    Stderr(String),
+}
+
+/// Where a tool sends its live output; sending fails quietly once nobody is listening
+pub type ToolOutputSender = mpsc::UnboundedSender<ToolOutput>;
+
+/// A function the model can call
+#[async_trait]
+pub trait Tool: Send + Sync {
+    /// Name the model uses to call the tool
+    fn name(&self) -> &str;
+
+    /// What the tool does, shown to the model
+    fn description(&self) -> &str;
+
+    /// JSON schema for the tool's arguments
+    fn parameters(&self) -> serde_json::Value;
+
+    /// Run the tool and return its result as JSON
+    async fn execute(&self, args: &serde_json::Value) -> Result<serde_json::Value>;
+
+    /// Run the tool, reporting output lines as they appear. The returned value still
+    /// carries the complete result; tools without live output just call `execute`.
+    async fn execute_with_output(&self, args: &serde_json::Value, _output: ToolOutputSender) -> Result<serde_json::Value> {
+        self.execute(args).await
+    }
+
+    /// Whether the tool only reads; read-only tools run without asking for approval
+    fn is_read_only(&self) -> bool {
+        false
+    }
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name().to_string(),
+            description: self.description().to_string(),
+            parameters: self.parameters(),
+        }
+    }
+}
+
+/// The set of tools offered to the model, in registration order.
+/// Clones share the same tool instances.
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    tools: Vec<Arc<dyn Tool>>,
+}
+
+impl ToolRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a registry holding the tools that ship with the CLI, rooted at the current directory
+    pub fn with_builtin_tools() -> Self {
+        Self::with_builtin_tools_in(Workspace::current_dir())
+    }
+
+    /// Create a registry holding the tools that ship with the CLI, confined to `workspace`
+    pub fn with_builtin_tools_in(workspace: Workspace) -> Self {
+        let mut registry = Self::new();
+        registry.register(ShellCommandTool::new(workspace.clone()));
+        registry.register(ReadFileTool { workspace: workspace.clone() });
+        registry.register(WriteFileTool { workspace: workspace.clone() });
+        registry.register(ListDirectoryTool { workspace: workspace.clone() });
+        registry.register(GlobTool { workspace: workspace.clone() });
+        registry.register(GrepTool { workspace: workspace.clone() });
+        registry.register(ApplyEditTool { workspace });
+        registry
+    }
+
+    /// Add a tool, replacing any tool already registered under the same name
+    pub fn register<T: Tool + 'static>(&mut self, tool: T) {
+        self.register_arc(Arc::new(tool));
+    }
+
+    pub fn register_arc(&mut self, tool: Arc<dyn Tool>) {
+        match self.tools.iter().position(|t| t.name() == tool.name()) {
+            Some(index) => self.tools[index] = tool,
+            None => self.tools.push(tool),
+        }
+    }
+
+    /// Remove a tool by name, returning whether it was registered
+    pub fn unregister(&mut self, name: &str) -> bool {
+        let before = self.tools.len();
+        self.tools.retain(|t| t.name() != name);
+        self.tools.len() != before
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Tool>> {
+        self.tools.iter().find(|t| t.name() == name).cloned()
+    }
+
+    pub fn definitions(&self) -> Vec<ToolDefinition> {
+        self.tools.iter().map(|t| t.definition()).collect()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.tools.iter().map(|t| t.name()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tools.len()
+    }
+}
+
+/// Executes model function calls against a `ToolRegistry`
+pub struct FunctionExecutor {
+    registry: ToolRegistry,
+}
 
 impl FunctionExecutor {
+    /// Create an executor with the built-in tools
     pub fn new() -> Self {
-        Self
+        Self::with_registry(ToolRegistry::with_builtin_tools())
     }
 
-    pub fn get_available_tools() -> Vec<ToolDefinition> {
-        vec![
-            ToolDefinition {
-                name: "shell_command".to_string(),
-                description: "Execute a shell command in the current working directory".to_string(),
-                parameters: serde_json::json!({
-                    "type": "object",
-                    "properties": {
-                        "command": {
-                            "type": "string",
-                            "description": "The shell command to execute"
-                        }
-                    },
-                    "required": ["command"]
-                }),
-            }
-        ]
+    pub fn with_registry(registry: ToolRegistry) -> Self {
+        Self { registry }
+    }
+
+    pub fn registry(&self) -> &ToolRegistry {
+        &self.registry
     }
 
     pub async fn execute_function(&self, function_call: &FunctionCall) -> Result<FunctionResponse> {
-        let function_id = format!("{}-{}", function_call.name, chrono::Utc::now().timestamp_millis());
-        
-        match function_call.name.as_str() {
-            "shell_command" => {
-                let command = function_call.args.get("command")
-                    .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow!("Missing 'command' parameter"))?;
-                
-                self.execute_shell_command(command, &function_id).await
+        let (output, _) = mpsc::unbounded_channel();
+        self.execute_function_with_output(function_call, output).await
+    }
+
+    /// Execute a call, sending the tool's live output to `output` as it runs
+    pub async fn execute_function_with_output(&self, function_call: &FunctionCall, output: ToolOutputSender) -> Result<FunctionResponse> {
+        let tool = self.registry.get(&function_call.name)
+            .ok_or_else(|| anyhow!("Unknown function: {}", function_call.name))?;
+
+        let response = tool.execute_with_output(&function_call.args, output).await?;
+
+        Ok(FunctionResponse {
+            id: function_call.call_id(),
+            name: function_call.name.clone(),
+            response,
+        })
+    }
+}
+
+impl Default for FunctionExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Default time a shell command may run before it is killed
+pub const DEFAULT_SHELL_TIMEOUT_SECS: u64 = 120;
+/// Upper bound for the per-call `timeout_secs` argument
+const MAX_SHELL_TIMEOUT_SECS: u64 = 600;
+/// Default cap on stdout/stderr bytes sent back to the model, per stream
+pub const DEFAULT_SHELL_MAX_BYTES: usize = 30_000;
+/// Default cap on stdout/stderr lines sent back to the model, per stream
+pub const DEFAULT_SHELL_MAX_LINES: usize = 500;
+/// How long to keep reading output after the shell exits, in case background jobs hold the pipes
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Runs a command through the platform shell, with a timeout and capped output
+pub struct ShellCommandTool {
+    workspace: Workspace,
+    timeout: Duration,
+    max_bytes: usize,
+    max_lines: usize,
+}
+
+impl ShellCommandTool {
+    pub fn new(workspace: Workspace) -> Self {
+        Self {
+            workspace,
+            timeout: Duration::from_secs(DEFAULT_SHELL_TIMEOUT_SECS),
+            max_bytes: DEFAULT_SHELL_MAX_BYTES,
+            max_lines: DEFAULT_SHELL_MAX_LINES,
+        }
+    }
+
+    /// Time a command may run when the call does not set `timeout_secs`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Cap the stdout and stderr returned to the model; longer output keeps its head and tail
+    pub fn with_output_limits(mut self, max_bytes: usize, max_lines: usize) -> Self {
+        self.max_bytes = max_bytes.max(1);
+        self.max_lines = max_lines.max(1);
+        self
+    }
+}
+
+impl Default for ShellCommandTool {
+    fn default() -> Self {
+        Self::new(Workspace::current_dir())
+    }
+}
+
+/// Keep the first and last parts of `text` within `max_bytes` and `max_lines`, with a marker
+/// in between saying how much was cut. Returns the text and whether anything was cut.
+pub fn truncate_output(text: &str, max_bytes: usize, max_lines: usize) -> (String, bool) {
+    if text.len() <= max_bytes && text.lines().count() <= max_lines {
+        return (text.to_string(), false);
+    }
+
+    let head_bytes = max_bytes / 2;
+    let head_lines = max_lines / 2;
+    let mut head_end = 0;
+    for (i, line) in text.split_inclusive('\n').enumerate() {
+        if i == head_lines {
+            break;
+        }
+        if head_end + line.len() > head_bytes {
+            if i == 0 {
+                // The first line alone is over the byte budget; cut inside it
+                head_end = head_bytes.min(text.len());
+                while !text.is_char_boundary(head_end) {
+                    head_end -= 1;
+                }
+            }
+            break;
+        }
+        head_end += line.len();
+    }
+
+    let tail_bytes = max_bytes - head_bytes;
+    let tail_lines = max_lines - head_lines;
+    let mut tail_start = text.len();
+    for (i, line) in text[head_end..].split_inclusive('\n').rev().enumerate() {
+        if i == tail_lines {
+            break;
+        }
+        if text.len() - tail_start + line.len() > tail_bytes {
+            if i == 0 {
+                // The last line alone is over the byte budget; cut inside it
+                tail_start = text.len().saturating_sub(tail_bytes).max(head_end);
+                while !text.is_char_boundary(tail_start) {
+                    tail_start += 1;
+                }
             }
-            _ => Err(anyhow!("Unknown function: {}", function_call.name))
+            break;
+        }
+        tail_start -= line.len();
+    }
+
+    let omitted = &text[head_end..tail_start];
+    let mut head = text[..head_end].to_string();
+    if !head.is_empty() && !head.ends_with('\n') {
+        head.push('\n');
+    }
+    (
+        format!(
+            "{}[... {} lines, {} bytes truncated ...]\n{}",
+            head,
+            omitted.lines().count(),
+            omitted.len(),
+            &text[tail_start..]
+        ),
+        true,
+    )
+}
+
+/// Kills the command's whole process group if the command is cancelled or times out,
+/// so children such as dev servers do not outlive it
+struct ProcessGroupGuard {
+    pid: Option<u32>,
+}
+
+impl ProcessGroupGuard {
+    fn kill(&mut self) {
+        #[cfg(unix)]
+        if let Some(pid) = self.pid.take() {
+            // SAFETY: killpg only sends a signal; the group was created for this command
+            unsafe {
+                libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+            }
+        }
+        self.pid = None;
+    }
+
+    fn disarm(&mut self) {
+        self.pid = None;
+    }
+}
+
+impl Drop for ProcessGroupGuard {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+/// Read a child's pipe to the end, forwarding each line as it arrives
+async fn read_lines<R: tokio::io::AsyncRead + Unpin>(
+    reader: Option<R>,
+    output: ToolOutputSender,
+    wrap: fn(String) -> ToolOutput,
+) -> String {
+    use tokio::io::AsyncBufReadExt;
+
+    let mut collected = String::new();
+    let Some(reader) = reader else {
+        return collected;
+    };
+    let mut reader = tokio::io::BufReader::new(reader);
+    let mut line = Vec::new();
+    while let Ok(n) = reader.read_until(b'\n', &mut line).await {
+        if n == 0 {
+            break;
         }
+        let text = String::from_utf8_lossy(&line);
+        let _ = output.send(wrap(text.trim_end_matches(['\n', '\r']).to_string()));
+        collected.push_str(&text);
+        line.clear();
+    }
+    collected
+}
+
+#[async_trait]
+impl Tool for ShellCommandTool {
+    fn name(&self) -> &str {
+        "shell_command"
+    }
+
+    fn description(&self) -> &str {
+        "Execute a shell command in the working directory. Long-running commands are killed after a timeout, and long output is truncated in the middle."
     }
 
-    async fn execute_shell_command(&self, command: &str, function_id: &str) -> Result<FunctionResponse> {
-        println!("🔧 Executing shell command: {}", command);
-        
+    fn parameters(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "command": {
+                    "type": "string",
+                    "description": "The shell command to execute"
+                },
+                "cwd": {
+                    "type": "string",
+                    "description": "Directory to run in, relative to the working directory (default \".\")"
+                },
+                "env": {
+                    "type": "object",
+                    "description": "Extra environment variables for the command",
+                    "additionalProperties": { "type": "string" }
+                },
+                "timeout_secs": {
+                    "type": "integer",
+                    "description": format!("Seconds before the command is killed (default {}, max {})", self.timeout.as_secs(), MAX_SHELL_TIMEOUT_SECS.max(self.timeout.as_secs()))
+                }
+            },
+            "required": ["command"]
+        })
+    }
+
+    async fn execute(&self, args: &serde_json::Value) -> Result<serde_json::Value> {
+        let (output, _) = mpsc::unbounded_channel();
+        self.execute_with_output(args, output).await
+    }
+
+    async fn execute_with_output(&self, args: &serde_json::Value, output: ToolOutputSender) -> Result<serde_json::Value> {
+        let command = required_str(args, "command")?;
+        let cwd = self.workspace.resolve(args.get("cwd").and_then(|v| v.as_str()).unwrap_or("."))?;
+        if !cwd.is_dir() {
+            return Err(anyhow!("cwd {} is not a directory", self.workspace.relative(&cwd)));
+        }
+        let timeout = match optional_usize(args, "timeout_secs")? {
+            Some(secs) => Duration::from_secs((secs as u64).clamp(1, MAX_SHELL_TIMEOUT_SECS.max(self.timeout.as_secs()))),
+            None => self.timeout,
+        };
+
         // Use shell to execute the command properly
-        let output = if cfg!(target_os = "windows") {
-            AsyncCommand::new("cmd")
-                .args(["/C", command])
-                .output()
-                .await?
+        let mut cmd = if cfg!(target_os = "windows") {
+            let mut cmd = AsyncCommand::new("cmd");
+            cmd.args(["/C", command]);
+            cmd
         } else {
-            AsyncCommand::new("sh")
-                .args(["-c", command])
-                .output()
-                .await?
+            let mut cmd = AsyncCommand::new("sh");
+            cmd.args(["-c", command]);
+            cmd
         };
+        cmd.current_dir(&cwd)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(env) = args.get("env").and_then(|v| v.as_object()) {
+            for (key, value) in env {
+                let value = value.as_str().ok_or_else(|| anyhow!("env value for '{}' must be a string", key))?;
+                cmd.env(key, value);
+            }
+        }
+        // Own process group, so Ctrl+C in the terminal reaches the CLI rather than the command
+        #[cfg(unix)]
+        cmd.process_group(0);
+
+        let mut child = cmd.spawn()?;
+        let mut guard = ProcessGroupGuard { pid: child.id() };
+        let stdout_task = tokio::spawn(read_lines(child.stdout.take(), output.clone(), ToolOutput::Stdout));
+        let stderr_task = tokio::spawn(read_lines(child.stderr.take(), output, ToolOutput::Stderr));
 
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let success = output.status.success();
+        let status = match tokio::time::timeout(timeout, child.wait()).await {
+            Ok(status) => {
+                guard.disarm();
+                Some(status?)
+            }
+            Err(_) => {
+                guard.kill();
+                let _ = child.kill().await;
+                None
+            }
+        };
 
-        let response_data = serde_json::json!({
+        let collect = |task: tokio::task::JoinHandle<String>| async move {
+            tokio::time::timeout(OUTPUT_DRAIN_TIMEOUT, task).await
+                .ok()
+                .and_then(|joined| joined.ok())
+                .unwrap_or_default()
+        };
+        let (stdout, stdout_truncated) = truncate_output(&collect(stdout_task).await, self.max_bytes, self.max_lines);
+        let (stderr, stderr_truncated) = truncate_output(&collect(stderr_task).await, self.max_bytes, self.max_lines);
+        let success = status.is_some_and(|s| s.success());
+        let exit_code = status.and_then(|s| s.code());
+
+        let output = match status {
+            None => format!("Command timed out after {}s and was killed.\nStdout:\n{}\nStderr:\n{}",
+                timeout.as_secs(), stdout, stderr),
+            Some(_) if success => format!("Command executed successfully.\nOutput:\n{}", stdout),
+            Some(_) => format!("Command failed with exit code {:?}.\nStdout:\n{}\nStderr:\n{}", 
+                exit_code, stdout, stderr),
+        };
+
+        Ok(serde_json::json!({
             "success": success,
-            "exit_code": output.status.code(),
-            "stdout": stdout.to_string(),
-            "stderr": stderr.to_string(),
-            "output": if success {
-                format!("Command executed successfully.\nOutput:\n{}", stdout)
+            "exit_code": exit_code,
+            "timed_out": status.is_none(),
+            "truncated": stdout_truncated || stderr_truncated,
+            "cwd": self.workspace.relative(&cwd),
+            "stdout": stdout,
+            "stderr": stderr,
+            "output": output,
+        }))
+    }
+}
+
+/// The directory the file tools work in. Paths are resolved against it, and
+/// anything that lands outside it (through `..`, absolute paths or symlinks) is refused.
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    root: PathBuf,
+}
+
+impl Workspace {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        Self {
+            root: root.canonicalize().unwrap_or(root),
+        }
+    }
+
+    pub fn current_dir() -> Self {
+        Self::new(std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Resolve `path` to an absolute path inside the workspace. The path does not need to exist yet.
+    pub fn resolve(&self, path: &str) -> Result<PathBuf> {
+        let candidate = if Path::new(path).is_absolute() {
+            PathBuf::from(path)
+        } else {
+            self.root.join(path)
+        };
+        let resolved = resolve_symlinks(normalize(&candidate))?;
+
+        if !resolved.starts_with(&self.root) {
+            return Err(anyhow!("Path '{}' is outside the working directory {}", path, self.root.display()));
+        }
+        Ok(resolved)
+    }
+
+    /// Path relative to the workspace root, for reporting back to the model
+    pub fn relative(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        if relative.as_os_str().is_empty() {
+            ".".to_string()
+        } else {
+            relative.to_string_lossy().replace('\\', "/")
+        }
+    }
+}
+
+/// Remove `.` and `..` without touching the file system
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Symbolic links followed before giving up, as the OS does for loops
+const MAX_SYMLINKS: usize = 40;
+
+/// Resolve the symlinks in the part of `path` that exists, keeping the missing rest as is.
+/// A dangling link is followed to its target too, since writing through it creates the target.
+fn resolve_symlinks(mut path: PathBuf) -> Result<PathBuf> {
+    for _ in 0..MAX_SYMLINKS {
+        // `symlink_metadata` finds links even when their target is missing, unlike `exists`
+        let mut existing = path.as_path();
+        let mut missing = Vec::new();
+        while existing.symlink_metadata().is_err() {
+            match (existing.file_name(), existing.parent()) {
+                (Some(name), Some(parent)) => {
+                    missing.push(name.to_os_string());
+                    existing = parent;
+                }
+                _ => break,
+            }
+        }
+
+        let mut resolved = match existing.canonicalize() {
+            Ok(resolved) => resolved,
+            Err(_) => match std::fs::read_link(existing) {
+                Ok(target) => {
+                    let parent = existing.parent().unwrap_or(Path::new("/"));
+                    let mut target = normalize(&parent.join(target));
+                    target.extend(missing.iter().rev());
+                    path = target;
+                    continue;
+                }
+                Err(_) => existing.to_path_buf(),
+            },
+        };
+        resolved.extend(missing.iter().rev());
+        return Ok(resolved);
+    }
+    Err(anyhow!("Too many levels of symbolic links in '{}'", path.display()))
+}
+
+fn required_str<'a>(args: &'a serde_json::Value, name: &str) -> Result<&'a str> {
+    args.get(name)
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow!("Missing '{}' parameter", name))
+}
+
+fn optional_usize(args: &serde_json::Value, name: &str) -> Result<Option<usize>> {
+    match args.get(name) {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(value) => value
+            .as_u64()
+            .map(|n| Some(n as usize))
+            .ok_or_else(|| anyhow!("'{}' must be a non-negative integer", name)),
+    }
+}
+
+/// Directories `grep` never descends into
+fn is_skipped_dir(name: &str) -> bool {
+    name.starts_with('.') || name == "target" || name == "node_modules"
+}
+
+/// Reads a text file, optionally limited to a range of lines
+pub struct ReadFileTool {
+    workspace: Workspace,
+}
+
+#[async_trait]
+impl Tool for ReadFileTool {
+    fn name(&self) -> &str {
+        "read_file"
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &str {
+        "Read a text file in the working directory. Use start_line/end_line (1-based, inclusive) to read part of a large file."
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "path": {
+                    "type": "string",
+                    "description": "Path of the file, relative to the working directory"
+                },
+                "start_line": {
+                    "type": "integer",
+                    "description": "First line to return (1-based, default 1)"
+                },
+                "end_line": {
+                    "type": "integer",
+                    "description": "Last line to return (inclusive)"
+                }
+            },
+            "required": ["path"]
+        })
+    }
+
+    async fn execute(&self, args: &serde_json::Value) -> Result<serde_json::Value> {
+        let path = self.workspace.resolve(required_str(args, "path")?)?;
+        let start_line = optional_usize(args, "start_line")?.unwrap_or(1).max(1);
+        let end_line = optional_usize(args, "end_line")?;
+
+        let content = tokio::fs::read_to_string(&path).await
+            .map_err(|e| anyhow!("Failed to read {}: {}", self.workspace.relative(&path), e))?;
+        let lines: Vec<&str> = content.lines().collect();
+        let total_lines = lines.len();
+
+        let last_line = match end_line {
+            Some(end) if end < start_line => {
+                return Err(anyhow!("end_line {} is before start_line {}", end, start_line));
+            }
+            Some(end) => end.min(total_lines),
+            None => (start_line + DEFAULT_READ_LINES - 1).min(total_lines),
+        };
+        let selected = if start_line > total_lines {
+            String::new()
+        } else {
+            lines[start_line - 1..last_line].join("\n")
+        };
+
+        Ok(serde_json::json!({
+            "success": true,
+            "path": self.workspace.relative(&path),
+            "content": selected,
+            "start_line": start_line,
+            "end_line": last_line,
+            "total_lines": total_lines,
+            "truncated": end_line.is_none() && last_line < total_lines,
+        }))
+    }
+}
+
+/// Writes a whole file, creating parent directories as needed
+pub struct WriteFileTool {
+    workspace: Workspace,
+}
+
+#[async_trait]
+impl Tool for WriteFileTool {
+    fn name(&self) -> &str {
+        "write_file"
+    }
+
+    fn description(&self) -> &str {
+        "Create or overwrite a file in the working directory with the given content. Parent directories are created as needed."
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "path": {
+                    "type": "string",
+                    "description": "Path of the file, relative to the working directory"
+                },
+                "content": {
+                    "type": "string",
+                    "description": "The complete new content of the file"
+                }
+            },
+            "required": ["path", "content"]
+        })
+    }
+
+    async fn execute(&self, args: &serde_json::Value) -> Result<serde_json::Value> {
+        let path = self.workspace.resolve(required_str(args, "path")?)?;
+        let content = required_str(args, "content")?;
+
+        if path.is_dir() {
+            return Err(anyhow!("{} is a directory", self.workspace.relative(&path)));
+        }
+        let created = !path.exists();
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&path, content).await
+            .map_err(|e| anyhow!("Failed to write {}: {}", self.workspace.relative(&path), e))?;
+
+        Ok(serde_json::json!({
+            "success": true,
+            "path": self.workspace.relative(&path),
+            "bytes_written": content.len(),
+            "created": created,
+        }))
+    }
+}
+
+/// Lists the entries of a directory
+pub struct ListDirectoryTool {
+    workspace: Workspace,
+}
+
+#[async_trait]
+impl Tool for ListDirectoryTool {
+    fn name(&self) -> &str {
+        "list_directory"
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &str {
+        "List the files and subdirectories of a directory in the working directory"
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "path": {
+                    "type": "string",
+                    "description": "Directory to list, relative to the working directory (default \".\")"
+                }
+            }
+        })
+    }
+
+    async fn execute(&self, args: &serde_json::Value) -> Result<serde_json::Value> {
+        let path = self.workspace.resolve(args.get("path").and_then(|v| v.as_str()).unwrap_or("."))?;
+
+        let mut entries = Vec::new();
+        let mut dir = tokio::fs::read_dir(&path).await
+            .map_err(|e| anyhow!("Failed to list {}: {}", self.workspace.relative(&path), e))?;
+        while let Some(entry) = dir.next_entry().await? {
+            let file_type = entry.file_type().await?;
+            let kind = if file_type.is_dir() {
+                "directory"
+            } else if file_type.is_symlink() {
+                "symlink"
+            } else {
+                "file"
+            };
+            let size = if file_type.is_file() {
+                entry.metadata().await.map(|m| m.len()).ok()
             } else {
-                format!("Command failed with exit code {:?}.\nStdout:\n{}\nStderr:\n{}", 
-                    output.status.code(), stdout, stderr)
+                None
+            };
+            entries.push(serde_json::json!({
+                "name": entry.file_name().to_string_lossy(),
+                "type": kind,
+                "size": size,
+            }));
+        }
+        entries.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+
+        Ok(serde_json::json!({
+            "success": true,
+            "path": self.workspace.relative(&path),
+            "entries": entries,
+        }))
+    }
+}
+
+/// Finds files by glob pattern
+pub struct GlobTool {
+    workspace: Workspace,
+}
+
+#[async_trait]
+impl Tool for GlobTool {
+    fn name(&self) -> &str {
+        "glob"
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &str {
+        "Find files in the working directory matching a glob pattern such as \"src/**/*.rs\""
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "pattern": {
+                    "type": "string",
+                    "description": "Glob pattern relative to the working directory"
+                }
+            },
+            "required": ["pattern"]
+        })
+    }
+
+    async fn execute(&self, args: &serde_json::Value) -> Result<serde_json::Value> {
+        let pattern = required_str(args, "pattern")?.to_string();
+        if Path::new(&pattern).is_absolute() || Path::new(&pattern).components().any(|c| c == Component::ParentDir) {
+            return Err(anyhow!("Pattern '{}' must stay inside the working directory", pattern));
+        }
+
+        let workspace = self.workspace.clone();
+        let full_pattern = format!("{}/{}", glob::Pattern::escape(&workspace.root().to_string_lossy()), pattern);
+        let (matches, truncated) = tokio::task::spawn_blocking(move || -> Result<(Vec<String>, bool)> {
+            let mut matches = Vec::new();
+            for entry in glob::glob(&full_pattern)?.flatten() {
+                if matches.len() == MAX_GLOB_RESULTS {
+                    return Ok((matches, true));
+                }
+                // Skip symlinks that point out of the workspace
+                if let Ok(resolved) = workspace.resolve(&entry.to_string_lossy()) {
+                    matches.push(workspace.relative(&resolved));
+                }
             }
-        });
+            Ok((matches, false))
+        }).await??;
 
-        Ok(FunctionResponse {
-            id: function_id.to_string(),
-            name: "shell_command".to_string(),
-            response: response_data,
+        Ok(serde_json::json!({
+            "success": true,
+            "pattern": pattern,
+            "matches": matches,
+            "truncated": truncated,
+        }))
+    }
+}
+
+/// Searches file contents with a regular expression
+pub struct GrepTool {
+    workspace: Workspace,
+}
+
+impl GrepTool {
+    fn search_file(&self, regex: &regex::Regex, path: &Path, matches: &mut Vec<serde_json::Value>) -> bool {
+        // Binary and non-UTF-8 files are skipped
+        let Ok(content) = std::fs::read_to_string(path) else {
+            return true;
+        };
+        for (i, line) in content.lines().enumerate() {
+            if regex.is_match(line) {
+                if matches.len() == MAX_GREP_MATCHES {
+                    return false;
+                }
+                matches.push(serde_json::json!({
+                    "path": self.workspace.relative(path),
+                    "line": i + 1,
+                    "text": line.chars().take(MAX_GREP_LINE_CHARS).collect::<String>(),
+                }));
+            }
+        }
+        true
+    }
+
+    /// Search `dir` recursively; returns false once the match limit is hit
+    fn search_dir(&self, regex: &regex::Regex, dir: &Path, include: Option<&glob::Pattern>, matches: &mut Vec<serde_json::Value>) -> bool {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return true;
+        };
+        let mut entries: Vec<_> = entries.flatten().collect();
+        entries.sort_by_key(|e| e.file_name());
+
+        for entry in entries {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let keep_going = if file_type.is_dir() {
+                is_skipped_dir(&name) || self.search_dir(regex, &path, include, matches)
+            } else if file_type.is_file() {
+                let included = include.is_none_or(|pattern| {
+                    pattern.matches(&name) || pattern.matches(&self.workspace.relative(&path))
+                });
+                !included || self.search_file(regex, &path, matches)
+            } else {
+                true
+            };
+            if !keep_going {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[async_trait]
+impl Tool for GrepTool {
+    fn name(&self) -> &str {
+        "grep"
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &str {
+        "Search file contents in the working directory with a regular expression. Returns matching lines with their file and line number."
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "pattern": {
+                    "type": "string",
+                    "description": "Regular expression to search for"
+                },
+                "path": {
+                    "type": "string",
+                    "description": "File or directory to search, relative to the working directory (default \".\")"
+                },
+                "include": {
+                    "type": "string",
+                    "description": "Only search files matching this glob, e.g. \"*.rs\""
+                },
+                "case_insensitive": {
+                    "type": "boolean",
+                    "description": "Ignore case when matching (default false)"
+                }
+            },
+            "required": ["pattern"]
         })
     }
+
+    async fn execute(&self, args: &serde_json::Value) -> Result<serde_json::Value> {
+        let pattern = required_str(args, "pattern")?.to_string();
+        let regex = regex::RegexBuilder::new(&pattern)
+            .case_insensitive(args.get("case_insensitive").and_then(|v| v.as_bool()).unwrap_or(false))
+            .build()
+            .map_err(|e| anyhow!("Invalid regular expression: {}", e))?;
+        let include = args.get("include")
+            .and_then(|v| v.as_str())
+            .map(glob::Pattern::new)
+            .transpose()
+            .map_err(|e| anyhow!("Invalid include pattern: {}", e))?;
+        let path = self.workspace.resolve(args.get("path").and_then(|v| v.as_str()).unwrap_or("."))?;
+
+        let tool = GrepTool { workspace: self.workspace.clone() };
+        let (matches, truncated) = tokio::task::spawn_blocking(move || {
+            let mut matches = Vec::new();
+            let complete = if path.is_dir() {
+                tool.search_dir(&regex, &path, include.as_ref(), &mut matches)
+            } else {
+                tool.search_file(&regex, &path, &mut matches)
+            };
+            (matches, !complete)
+        }).await?;
+
+        Ok(serde_json::json!({
+            "success": true,
+            "pattern": pattern,
+            "matches": matches,
+            "truncated": truncated,
+        }))
+    }
+}
+
+/// Replace `path` with `content` by writing a sibling temp file and renaming it over the original
+async fn write_atomic(path: &Path, content: &str) -> Result<()> {
+    let file_name = path.file_name()
+        .ok_or_else(|| anyhow!("{} is not a file path", path.display()))?
+        .to_string_lossy();
+    let temp_path = path.with_file_name(format!(".{}.chat-cli-{}.tmp", file_name, std::process::id()));
+
+    tokio::fs::write(&temp_path, content).await?;
+    // Keep the original file's permissions (e.g. executable scripts)
+    if let Ok(metadata) = tokio::fs::metadata(path).await {
+        tokio::fs::set_permissions(&temp_path, metadata.permissions()).await?;
+    }
+    if let Err(e) = tokio::fs::rename(&temp_path, path).await {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// 1-based line number of the byte offset `index` in `text`
+fn line_number_at(text: &str, index: usize) -> usize {
+    text[..index].matches('\n').count() + 1
+}
+
+/// Replace `old` with `new` in `content`; `old` must match exactly once unless `replace_all` is set
+fn search_replace(content: &str, old: &str, new: &str, replace_all: bool) -> Result<(String, usize)> {
+    if old.is_empty() {
+        return Err(anyhow!("old_string must not be empty; use write_file to create a file"));
+    }
+    if old == new {
+        return Err(anyhow!("old_string and new_string are identical; nothing to change"));
+    }
+
+    let positions: Vec<usize> = content.match_indices(old).map(|(i, _)| i).collect();
+    match positions.len() {
+        0 => Err(anyhow!(search_mismatch_hint(content, old))),
+        1 => Ok((content.replacen(old, new, 1), 1)),
+        n if replace_all => Ok((content.replace(old, new), n)),
+        n => {
+            let lines: Vec<String> = positions.iter().map(|&i| line_number_at(content, i).to_string()).collect();
+            Err(anyhow!(
+                "old_string matches {} times (at lines {}); include more surrounding lines to make it unique, or set replace_all",
+                n,
+                lines.join(", ")
+            ))
+        }
+    }
+}
+
+/// Explain why `old` was not found, pointing at the closest candidate when there is one
+fn search_mismatch_hint(content: &str, old: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let file_lines: Vec<&str> = content.lines().collect();
+    let first = old_lines.first().map(|l| l.trim()).unwrap_or_default();
+
+    for (i, window) in file_lines.windows(old_lines.len().max(1)).enumerate() {
+        if window.iter().map(|l| l.trim()).eq(old_lines.iter().map(|l| l.trim())) {
+            return format!(
+                "old_string not found exactly, but lines {}-{} match when whitespace is ignored; copy the exact indentation from the file",
+                i + 1,
+                i + old_lines.len()
+            );
+        }
+    }
+
+    if !first.is_empty() {
+        if let Some(i) = file_lines.iter().position(|l| l.trim() == first) {
+            let differing = old_lines.iter()
+                .zip(&file_lines[i..])
+                .position(|(o, f)| o.trim() != f.trim())
+                .unwrap_or(file_lines.len() - i);
+            if let Some(expected) = old_lines.get(differing) {
+                return format!(
+                    "old_string not found. Its first line matches line {}, but line {} differs: expected {:?}, found {:?}",
+                    i + 1,
+                    i + differing + 1,
+                    expected,
+                    file_lines.get(i + differing).copied().unwrap_or("<end of file>")
+                );
+            }
+        }
+    }
+
+    "old_string not found in the file; read the file again and copy the text exactly".to_string()
+}
+
+/// Apply every hunk of a unified diff to `content`. Each hunk must match at its stated
+/// line, or at exactly one other place in the file.
+fn apply_unified_diff(content: &str, diff: &str) -> Result<(String, usize)> {
+    let patch = diffy::Patch::from_str(diff).map_err(|e| anyhow!("Invalid unified diff: {}", e))?;
+    if patch.hunks().is_empty() {
+        return Err(anyhow!("The diff contains no hunks"));
+    }
+
+    let file_lines: Vec<&str> = content.split_inclusive('\n').collect();
+    // (start, old line count, replacement lines) per hunk, in file order
+    let mut edits: Vec<(usize, usize, Vec<&str>)> = Vec::new();
+
+    for (n, hunk) in patch.hunks().iter().enumerate() {
+        let mut old = Vec::new();
+        let mut new = Vec::new();
+        for line in hunk.lines() {
+            match line {
+                diffy::Line::Context(l) => {
+                    old.push(*l);
+                    new.push(*l);
+                }
+                diffy::Line::Delete(l) => old.push(*l),
+                diffy::Line::Insert(l) => new.push(*l),
+            }
+        }
+
+        let range = hunk.old_range();
+        // A hunk with no old lines inserts after line `start`
+        let expected = if old.is_empty() { range.start() } else { range.start().saturating_sub(1) };
+        let matches_at = |pos: usize| {
+            file_lines.get(pos..pos + old.len())
+                .is_some_and(|window| window.iter().map(|l| l.trim_end_matches('\n')).eq(old.iter().map(|l| l.trim_end_matches('\n'))))
+        };
+
+        let start = if matches_at(expected) {
+            expected
+        } else {
+            let candidates: Vec<usize> = (0..=file_lines.len().saturating_sub(old.len())).filter(|&pos| matches_at(pos)).collect();
+            match candidates.as_slice() {
+                [pos] => *pos,
+                [] => return Err(anyhow!(hunk_mismatch(n + 1, &range.to_string(), &old, &file_lines, expected))),
+                _ => {
+                    let lines: Vec<String> = candidates.iter().map(|p| (p + 1).to_string()).collect();
+                    return Err(anyhow!(
+                        "Hunk #{} (@@ -{} @@) does not match at line {}, and its context matches at lines {}; add context lines or fix the line numbers",
+                        n + 1,
+                        range,
+                        expected + 1,
+                        lines.join(", ")
+                    ));
+                }
+            }
+        };
+
+        if let Some((prev_start, prev_len, _)) = edits.iter().find(|(s, l, _)| start < s + l && *s < start + old.len().max(1)) {
+            return Err(anyhow!(
+                "Hunk #{} overlaps lines {}-{} changed by an earlier hunk",
+                n + 1,
+                prev_start + 1,
+                prev_start + prev_len
+            ));
+        }
+        edits.push((start, old.len(), new));
+    }
+
+    edits.sort_by_key(|(start, _, _)| *start);
+    let mut result = String::with_capacity(content.len());
+    // A line that ended a file without a newline may no longer be last after the edit
+    let mut push_line = |line: &str| {
+        if !result.is_empty() && !result.ends_with('\n') {
+            result.push('\n');
+        }
+        result.push_str(line);
+    };
+    let mut cursor = 0;
+    for (start, len, new) in &edits {
+        file_lines[cursor..*start].iter().chain(new).for_each(|line| push_line(line));
+        cursor = start + len;
+    }
+    file_lines[cursor..].iter().for_each(|line| push_line(line));
+
+    Ok((result, edits.len()))
+}
+
+/// Describe the first line where a hunk disagrees with the file at its stated position
+fn hunk_mismatch(number: usize, range: &str, old: &[&str], file_lines: &[&str], expected: usize) -> String {
+    for (offset, wanted) in old.iter().enumerate() {
+        let found = file_lines.get(expected + offset).map(|l| l.trim_end_matches('\n'));
+        if found != Some(wanted.trim_end_matches('\n')) {
+            return format!(
+                "Hunk #{} (@@ -{} @@) does not apply: line {} should be {:?} but the file has {}",
+                number,
+                range,
+                expected + offset + 1,
+                wanted.trim_end_matches('\n'),
+                found.map(|l| format!("{:?}", l)).unwrap_or_else(|| "no such line".to_string())
+            );
+        }
+    }
+    format!("Hunk #{} (@@ -{} @@) does not apply", number, range)
+}
+
+/// Edits an existing file with a search/replace block or a unified diff
+pub struct ApplyEditTool {
+    workspace: Workspace,
+}
+
+#[async_trait]
+impl Tool for ApplyEditTool {
+    fn name(&self) -> &str {
+        "apply_edit"
+    }
+
+    fn description(&self) -> &str {
+        "Edit an existing file. Either give old_string/new_string to replace one exact, unique occurrence of old_string, or give diff with a unified diff for the file. Returns the applied change as a diff."
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "path": {
+                    "type": "string",
+                    "description": "Path of the file, relative to the working directory"
+                },
+                "old_string": {
+                    "type": "string",
+                    "description": "Exact text to replace, including indentation. Must occur exactly once unless replace_all is set."
+                },
+                "new_string": {
+                    "type": "string",
+                    "description": "Text to put in place of old_string"
+                },
+                "replace_all": {
+                    "type": "boolean",
+                    "description": "Replace every occurrence of old_string (default false)"
+                },
+                "diff": {
+                    "type": "string",
+                    "description": "Unified diff (with @@ hunk headers) to apply instead of old_string/new_string"
+                }
+            },
+            "required": ["path"]
+        })
+    }
+
+    async fn execute(&self, args: &serde_json::Value) -> Result<serde_json::Value> {
+        let path = self.workspace.resolve(required_str(args, "path")?)?;
+        let relative = self.workspace.relative(&path);
+        let original = tokio::fs::read_to_string(&path).await
+            .map_err(|e| anyhow!("Failed to read {}: {}", relative, e))?;
+
+        let (mode, (updated, changes)) = match args.get("diff").and_then(|v| v.as_str()) {
+            Some(diff) => ("diff", apply_unified_diff(&original, diff)?),
+            None => {
+                let old = required_str(args, "old_string")?;
+                let new = required_str(args, "new_string")?;
+                let replace_all = args.get("replace_all").and_then(|v| v.as_bool()).unwrap_or(false);
+                ("search_replace", search_replace(&original, old, new, replace_all)?)
+            }
+        };
+
+        if updated == original {
+            return Err(anyhow!("The edit leaves {} unchanged", relative));
+        }
+        write_atomic(&path, &updated).await
+            .map_err(|e| anyhow!("Failed to write {}: {}", relative, e))?;
+
+        let diff = diffy::DiffOptions::new()
+            .set_original_filename(format!("a/{}", relative))
+            .set_modified_filename(format!("b/{}", relative))
+            .create_patch(&original, &updated)
+            .to_string();
+
+        Ok(serde_json::json!({
+            "success": true,
+            "path": relative,
+            "mode": mode,
+            "changes": changes,
+            "diff": diff,
+        }))
+    }
 }
 
 pub fn parse_function_call_from_text(text: &str) -> Option<FunctionCall> {
     // Look for function call patterns in the text
     // This is a simple implementation - in practice, you might want more sophisticated parsing
-    if let Ok(parsed) = serde_json::from_str::<FunctionCall>(text) {
-        Some(parsed)
-    } else {
-        None
+    serde_json::from_str::<FunctionCall>(text).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct EchoTool;
+
+    #[async_trait]
+    impl Tool for EchoTool {
+        fn name(&self) -> &str {
+            "echo"
+        }
+
+        fn description(&self) -> &str {
+            "Return the arguments unchanged"
+        }
+
+        fn parameters(&self) -> serde_json::Value {
+            serde_json::json!({"type": "object", "properties": {}})
+        }
+
+        async fn execute(&self, args: &serde_json::Value) -> Result<serde_json::Value> {
+            Ok(args.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registry_dispatches_custom_tools() {
+        let mut registry = ToolRegistry::new();
+        registry.register(ShellCommandTool::default());
+        registry.register(EchoTool);
+        assert_eq!(registry.names(), ["shell_command", "echo"]);
+
+        let executor = FunctionExecutor::with_registry(registry);
+        let response = executor.execute_function(&FunctionCall {
+            id: Some("call_1".to_string()),
+            name: "echo".to_string(),
+            args: serde_json::json!({"value": 42}),
+        }).await.unwrap();
+
+        assert_eq!(response.id, "call_1");
+        assert_eq!(response.response["value"], 42);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_function_is_an_error() {
+        let executor = FunctionExecutor::with_registry(ToolRegistry::new());
+        let result = executor.execute_function(&FunctionCall {
+            id: None,
+            name: "shell_command".to_string(),
+            args: serde_json::json!({"command": "echo hi"}),
+        }).await;
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_register_replaces_tool_with_same_name() {
+        let mut registry = ToolRegistry::new();
+        registry.register(EchoTool);
+        registry.register(EchoTool);
+        assert_eq!(registry.len(), 1);
+        assert!(registry.unregister("echo"));
+        assert!(registry.is_empty());
+    }
+
+    fn workspace_with_files() -> (tempfile::TempDir, ToolRegistry) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/main.rs"), "fn main() {\n    println!(\"hi\");\n}\n").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "one\ntwo\nthree\nfour\n").unwrap();
+        let registry = ToolRegistry::with_builtin_tools_in(Workspace::new(dir.path()));
+        (dir, registry)
+    }
+
+    async fn run(registry: &ToolRegistry, name: &str, args: serde_json::Value) -> Result<serde_json::Value> {
+        registry.get(name).unwrap().execute(&args).await
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_read_file_line_range() {
+        let (_dir, registry) = workspace_with_files();
+        let result = run(&registry, "read_file", serde_json::json!({
+            "path": "notes.txt", "start_line": 2, "end_line": 3
+        })).await.unwrap();
+
+        assert_eq!(result["content"], "two\nthree");
+        assert_eq!(result["total_lines"], 4);
+    }
+
+    #[tokio::test]
+    async fn test_write_then_list_and_glob() {
+        let (_dir, registry) = workspace_with_files();
+        let result = run(&registry, "write_file", serde_json::json!({
+            "path": "src/lib.rs", "content": "pub fn f() {}\n"
+        })).await.unwrap();
+        assert_eq!(result["created"], true);
+
+        let listing = run(&registry, "list_directory", serde_json::json!({"path": "src"})).await.unwrap();
+        let names: Vec<_> = listing["entries"].as_array().unwrap().iter().map(|e| e["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["lib.rs", "main.rs"]);
+
+        let globbed = run(&registry, "glob", serde_json::json!({"pattern": "**/*.rs"})).await.unwrap();
+        assert_eq!(globbed["matches"], serde_json::json!(["src/lib.rs", "src/main.rs"]));
+    }
+
+    #[tokio::test]
+    async fn test_grep_reports_file_and_line() {
+        let (_dir, registry) = workspace_with_files();
+        let result = run(&registry, "grep", serde_json::json!({
+            "pattern": "PRINTLN", "case_insensitive": true, "include": "*.rs"
+        })).await.unwrap();
+
+        assert_eq!(result["matches"], serde_json::json!([
+            {"path": "src/main.rs", "line": 2, "text": "    println!(\"hi\");"}
+        ]));
+    }
+
+    #[tokio::test]
+    async fn test_paths_outside_workspace_are_refused() {
+        let (_dir, registry) = workspace_with_files();
+        for (tool, args) in [
+            ("read_file", serde_json::json!({"path": "../secret.txt"})),
+            ("read_file", serde_json::json!({"path": "/etc/passwd"})),
+            ("write_file", serde_json::json!({"path": "src/../../escape.txt", "content": "x"})),
+            ("list_directory", serde_json::json!({"path": ".."})),
+            ("glob", serde_json::json!({"pattern": "../*"})),
+        ] {
+            let error = run(&registry, tool, args).await.unwrap_err();
+            assert!(error.to_string().contains("working directory"), "{}: {}", tool, error);
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_dangling_symlinks_are_followed_to_their_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink(outside.path().join("cron"), dir.path().join("out")).unwrap();
+        std::os::unix::fs::symlink("notes/today.md", dir.path().join("today")).unwrap();
+        let workspace = Workspace::new(dir.path());
+        let registry = ToolRegistry::with_builtin_tools_in(Workspace::new(dir.path()));
+
+        let error = run(&registry, "write_file", serde_json::json!({"path": "out", "content": "x"})).await.unwrap_err();
+        assert!(error.to_string().contains("working directory"), "{}", error);
+        assert!(!outside.path().join("cron").exists());
+        assert!(workspace.resolve("out/nested").is_err());
+
+        assert_eq!(workspace.resolve("today").unwrap(), workspace.root().join("notes/today.md"));
+    }
+
+    #[tokio::test]
+    async fn test_apply_edit_search_replace_requires_unique_match() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("lib.rs"), "let a = 1;\nlet b = 1;\n").unwrap();
+        let registry = ToolRegistry::with_builtin_tools_in(Workspace::new(dir.path()));
+
+        let error = run(&registry, "apply_edit", serde_json::json!({
+            "path": "lib.rs", "old_string": "= 1;", "new_string": "= 2;"
+        })).await.unwrap_err();
+        assert!(error.to_string().contains("matches 2 times (at lines 1, 2)"), "{}", error);
+
+        let result = run(&registry, "apply_edit", serde_json::json!({
+            "path": "lib.rs", "old_string": "let b = 1;", "new_string": "let b = 2;"
+        })).await.unwrap();
+        assert_eq!(result["changes"], 1);
+        assert!(result["diff"].as_str().unwrap().contains("-let b = 1;\n+let b = 2;\n"));
+        assert_eq!(std::fs::read_to_string(dir.path().join("lib.rs")).unwrap(), "let a = 1;\nlet b = 2;\n");
+    }
+
+    #[test]
+    fn test_search_replace_mismatch_points_at_differing_line() {
+        let content = "fn main() {\n    run();\n}\n";
+
+        let error = search_replace(content, "fn main() {\n    start();\n}", "", false).unwrap_err();
+        assert!(error.to_string().contains("line 2 differs"), "{}", error);
+
+        let error = search_replace(content, "fn main() {\n  run();\n}", "", false).unwrap_err();
+        assert!(error.to_string().contains("whitespace is ignored"), "{}", error);
+    }
+
+    #[test]
+    fn test_apply_unified_diff() {
+        let content = "one\ntwo\nthree\nfour\nfive\n";
+        let diff = "--- a/f\n+++ b/f\n@@ -2,3 +2,3 @@\n two\n-three\n+THREE\n four\n";
+
+        let (updated, hunks) = apply_unified_diff(content, diff).unwrap();
+        assert_eq!(updated, "one\ntwo\nTHREE\nfour\nfive\n");
+        assert_eq!(hunks, 1);
+
+        // Wrong line numbers are tolerated when the context is unique
+        let shifted = diff.replace("@@ -2,3 +2,3 @@", "@@ -1,3 +1,3 @@");
+        assert_eq!(apply_unified_diff(content, &shifted).unwrap().0, updated);
+
+        let stale = diff.replace(" four\n", " 4\n");
+        let error = apply_unified_diff(content, &stale).unwrap_err();
+        assert!(error.to_string().contains("line 4 should be \"4\" but the file has \"four\""), "{}", error);
+    }
+
+    #[test]
+    fn test_truncate_output_keeps_head_and_tail() {
+        let text: String = (1..=100).map(|i| format!("line {}\n", i)).collect();
+
+        assert_eq!(truncate_output(&text, 10_000, 100), (text.clone(), false));
+
+        let (truncated, was_truncated) = truncate_output(&text, 10_000, 4);
+        assert!(was_truncated);
+        assert_eq!(truncated, "line 1\nline 2\n[... 96 lines, 761 bytes truncated ...]\nline 99\nline 100\n");
+
+        let (truncated, _) = truncate_output(&"x".repeat(100), 10, 10);
+        assert_eq!(truncated, "xxxxx\n[... 1 lines, 90 bytes truncated ...]\nxxxxx");
+    }
+
+    #[test]
+    fn test_truncate_output_with_one_line_allowed() {
+        assert_eq!(truncate_output("a\nb\n", 10_000, 1), ("[... 1 lines, 2 bytes truncated ...]\nb\n".to_string(), true));
+
+        let (truncated, _) = truncate_output(&"y".repeat(100), 10, 1);
+        assert_eq!(truncated, "[... 1 lines, 95 bytes truncated ...]\nyyyyy");
+    }
+
+    #[test]
+    fn test_truncate_output_over_line_limit_keeps_whole_lines() {
+        let (truncated, was_truncated) = truncate_output("a\nb\nc\nd\ne\n", 10_000, 3);
+        assert!(was_truncated);
+        assert_eq!(truncated, "a\n[... 2 lines, 4 bytes truncated ...]\nd\ne\n");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_shell_command_cwd_env_and_timeout() {
+        let (dir, registry) = workspace_with_files();
+
+        let result = run(&registry, "shell_command", serde_json::json!({
+            "command": "pwd && echo $GREETING", "cwd": "src", "env": {"GREETING": "hello"}
+        })).await.unwrap();
+        let expected_dir = dir.path().canonicalize().unwrap().join("src");
+        assert_eq!(result["stdout"], format!("{}\nhello\n", expected_dir.display()));
+
+        let started = std::time::Instant::now();
+        let result = run(&registry, "shell_command", serde_json::json!({
+            "command": "echo started; sleep 30", "timeout_secs": 1
+        })).await.unwrap();
+        assert_eq!(result["timed_out"], true);
+        assert_eq!(result["stdout"], "started\n");
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_shell_command_streams_output_lines() {
+        let executor = FunctionExecutor::new();
+        let call = FunctionCall {
+            id: None,
+            name: "shell_command".to_string(),
+            args: serde_json::json!({"command": "echo one; echo two >&2; echo three"}),
+        };
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        let response = executor.execute_function_with_output(&call, tx).await.unwrap();
+
+        let mut lines = Vec::new();
+        while let Ok(line) = rx.try_recv() {
+            lines.push(line);
+        }
+        assert_eq!(lines.len(), 3);
+        assert!(lines.contains(&ToolOutput::Stderr("two".to_string())));
+        assert_eq!(response.response["stdout"], "one\nthree\n");
+    }
+}
@@ -5,7 +5,13 @@ use futures::stream::StreamExt;
 use tokio::sync::mpsc;
 use std::fs::OpenOptions;
 use std::io::Write;
+use std::sync::{Arc, Mutex};
 use async_trait::async_trait;
+use crate::chat_client::{spawn_stream, StreamEvent, TokenUsage};
+use crate::function_calling::ToolRegistry;
+use crate::conversation::{ChatMessage, Conversation, MessagePart, Role};
+use crate::generation::GenerationOptions;
+use crate::retry::RetryPolicy;
 
 #[derive(Clone)]
 pub struct GeminiClient {
@@ -13,8 +19,11 @@ pub struct GeminiClient {
     api_key: String,
     model: String,
     base_url: String,
-    conversation_history: Vec<Content>,
-    system_instruction: Option<SystemInstruction>,
+    conversation: Conversation,
+    tools: ToolRegistry,
+    options: GenerationOptions,
+    last_usage: Arc<Mutex<Option<TokenUsage>>>,
+    retry: RetryPolicy,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -23,16 +32,72 @@ struct Content {
     role: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 struct Part {
     #[serde(skip_serializing_if = "Option::is_none")]
     text: Option<String>,
+    #[serde(rename = "inlineData", skip_serializing_if = "Option::is_none")]
+    inline_data: Option<serde_json::Value>,
     #[serde(rename = "functionCall", skip_serializing_if = "Option::is_none")]
     function_call: Option<serde_json::Value>,
     #[serde(rename = "functionResponse", skip_serializing_if = "Option::is_none")]
     function_response: Option<serde_json::Value>,
 }
 
+impl Part {
+    fn text(text: &str) -> Self {
+        Self {
+            text: Some(text.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Translate a conversation message to a Gemini part list. Tool results go back as user turns.
+fn to_content(message: &ChatMessage) -> Option<Content> {
+    let role = match message.role {
+        Role::Assistant => "model",
+        Role::User | Role::Tool => "user",
+        // System messages are folded into the system instruction
+        Role::System => return None,
+    };
+    let parts: Vec<Part> = message.parts.iter()
+        .map(|part| match part {
+            MessagePart::Text { text } => Part::text(text),
+            MessagePart::Image { mime_type, data } => Part {
+                inline_data: Some(serde_json::json!({ "mimeType": mime_type, "data": data })),
+                ..Default::default()
+            },
+            MessagePart::ToolCall(fc) => {
+                let mut function_call = serde_json::json!({
+                    "name": fc.name,
+                    "args": fc.args
+                });
+                if let Some(id) = &fc.id {
+                    function_call["id"] = serde_json::json!(id);
+                }
+                Part {
+                    function_call: Some(function_call),
+                    ..Default::default()
+                }
+            }
+            MessagePart::ToolResult(result) => Part {
+                function_response: Some(serde_json::json!({
+                    "id": result.id,
+                    "name": result.name,
+                    "response": result.response
+                })),
+                ..Default::default()
+            },
+        })
+        .collect();
+
+    if parts.is_empty() {
+        return None;
+    }
+    Some(Content { role: role.to_string(), parts })
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct GenerateContentRequest {
     contents: Vec<Content>,
@@ -62,22 +127,60 @@ struct FunctionDeclaration {
     parameters: serde_json::Value,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct GenerationConfig {
-    temperature: f32,
-    #[serde(rename = "topP")]
-    top_p: f32,
-    #[serde(rename = "topK")]
-    top_k: i32,
-    #[serde(rename = "maxOutputTokens")]
-    max_output_tokens: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking_config: Option<ThinkingConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ThinkingConfig {
+    thinking_budget: u32,
+}
+
+impl GenerationConfig {
+    /// Map the generation options; reasoning effort becomes a thinking budget
+    fn from_options(options: &GenerationOptions) -> Option<Self> {
+        if *options == GenerationOptions::default() {
+            return None;
+        }
+        Some(Self {
+            temperature: options.temperature,
+            top_p: options.top_p,
+            top_k: options.top_k,
+            max_output_tokens: options.max_tokens,
+            stop_sequences: options.stop.clone(),
+            seed: options.seed,
+            presence_penalty: options.presence_penalty,
+            frequency_penalty: options.frequency_penalty,
+            thinking_config: options.reasoning_effort.map(|effort| ThinkingConfig { thinking_budget: effort.thinking_budget() }),
+        })
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct GenerateContentResponse {
     candidates: Vec<Candidate>,
     #[serde(rename = "usageMetadata", skip_serializing_if = "Option::is_none")]
-    usage_metadata: Option<serde_json::Value>,
+    usage_metadata: Option<UsageMetadata>,
     #[serde(rename = "modelVersion", skip_serializing_if = "Option::is_none")]
     model_version: Option<String>,
     #[serde(rename = "responseId", skip_serializing_if = "Option::is_none")]
@@ -93,6 +196,30 @@ struct Candidate {
     index: Option<i32>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    #[serde(default)]
+    prompt_token_count: u32,
+    #[serde(default)]
+    candidates_token_count: u32,
+    /// Thinking tokens, billed as output
+    #[serde(default)]
+    thoughts_token_count: u32,
+    #[serde(default)]
+    total_token_count: u32,
+}
+
+impl From<&UsageMetadata> for TokenUsage {
+    fn from(usage: &UsageMetadata) -> Self {
+        TokenUsage {
+            prompt_tokens: usage.prompt_token_count,
+            completion_tokens: usage.candidates_token_count + usage.thoughts_token_count,
+            total_tokens: usage.total_token_count,
+        }
+    }
+}
+
 impl GeminiClient {
     pub fn new(api_key: String, model: String) -> Self {
         Self {
@@ -100,75 +227,129 @@ impl GeminiClient {
             api_key,
             model,
             base_url: "https://generativelanguage.googleapis.com/v1beta/models".to_string(),
-            conversation_history: Vec::new(),
-            system_instruction: None,
+            conversation: Conversation::new(),
+            tools: ToolRegistry::with_builtin_tools(),
+            options: GenerationOptions::default(),
+            last_usage: Arc::new(Mutex::new(None)),
+            retry: RetryPolicy::default(),
         }
     }
 
+    /// Set the tools offered to the model
+    pub fn with_tools(mut self, tools: ToolRegistry) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    pub fn set_tools(&mut self, tools: ToolRegistry) {
+        self.tools = tools;
+    }
+
+    /// Token usage of the last non-streaming request
+    pub fn last_usage(&self) -> Option<TokenUsage> {
+        self.last_usage.lock().unwrap().clone()
+    }
+
+    /// Retry failed requests according to `retry`
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Set the sampling and length options sent with each request
+    pub fn with_generation_options(mut self, options: GenerationOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn generation_options(&self) -> &GenerationOptions {
+        &self.options
+    }
+
+    pub fn set_generation_options(&mut self, options: GenerationOptions) {
+        self.options = options;
+    }
+
+    pub fn set_model(&mut self, model: &str) {
+        self.model = model.to_string();
+    }
+
+    fn build_tools(&self) -> Option<Vec<Tool>> {
+        if self.tools.is_empty() {
+            return None;
+        }
+
+        Some(vec![Tool {
+            function_declarations: self.tools
+                .definitions()
+                .into_iter()
+                .map(|tool| FunctionDeclaration {
+                    name: tool.name,
+                    description: tool.description,
+                    parameters: tool.parameters,
+                })
+                .collect(),
+        }])
+    }
+
     pub fn load_system_prompt(&mut self, prompt_content: &str) -> Result<()> {
-        self.system_instruction = Some(SystemInstruction {
-            parts: vec![Part {
-                text: Some(prompt_content.to_string()),
-                function_call: None,
-                function_response: None,
-            }],
-        });
+        self.conversation.system_prompt = Some(prompt_content.to_string());
         Ok(())
     }
 
     pub fn add_user_message(&mut self, message: &str) {
-        self.conversation_history.push(Content {
-            role: "user".to_string(),
-            parts: vec![Part {
-                text: Some(message.to_string()),
-                function_call: None,
-                function_response: None,
-            }],
-        });
+        self.conversation.push(ChatMessage::user(message));
     }
 
     pub fn add_function_response(&mut self, function_response: &crate::function_calling::FunctionResponse) {
-        self.conversation_history.push(Content {
-            role: "user".to_string(),
-            parts: vec![Part {
-                text: None,
-                function_call: None,
-                function_response: Some(serde_json::json!({
-                    "id": function_response.id,
-                    "name": function_response.name,
-                    "response": function_response.response
-                })),
-            }],
-        });
+        self.conversation.push(ChatMessage::tool_result(function_response));
     }
 
-    pub fn add_model_response(&mut self, response: &str, function_call: Option<serde_json::Value>) {
-        let mut parts = Vec::new();
-        
-        if !response.is_empty() {
-            parts.push(Part {
-                text: Some(response.to_string()),
-                function_call: None,
-                function_response: None,
-            });
-        }
-        
-        if let Some(fc) = function_call {
-            parts.push(Part {
-                text: None,
-                function_call: Some(fc),
-                function_response: None,
-            });
-        }
-
-        self.conversation_history.push(Content {
-            role: "model".to_string(),
-            parts,
-        });
+    pub fn add_model_response(&mut self, response: &str, tool_calls: &[crate::function_calling::FunctionCall]) {
+        self.conversation.push(ChatMessage::assistant(response, tool_calls).with_model(&self.model));
     }
 
     pub fn clear_conversation(&mut self) {
-        self.conversation_history.clear();
+        self.conversation.clear();
+    }
+
+    pub fn conversation(&self) -> &Conversation {
+        &self.conversation
+    }
+
+    pub fn set_conversation(&mut self, conversation: Conversation) {
+        self.conversation = conversation;
+    }
+
+    /// The system instruction: the system prompt plus any system messages in the history
+    fn build_system_instruction(&self) -> Option<SystemInstruction> {
+        let parts: Vec<Part> = self.conversation.system_prompt.iter()
+            .cloned()
+            .chain(self.conversation.messages().iter().filter(|m| m.role == Role::System).map(|m| m.text()))
+            .map(|text| Part::text(&text))
+            .collect();
+        if parts.is_empty() {
+            None
+        } else {
+            Some(SystemInstruction { parts })
+        }
+    }
+
+    /// Gemini expects every function response of a turn in the content that follows
+    /// the calls, so consecutive contents with the same role are merged into one
+    fn build_contents(&self, user_message: &str) -> Vec<Content> {
+        let mut contents: Vec<Content> = Vec::new();
+        let new_message = (!user_message.is_empty()).then(|| ChatMessage::user(user_message));
+
+        for message in self.conversation.messages().iter().chain(new_message.as_ref()) {
+            let Some(content) = to_content(message) else { continue };
+            match contents.last_mut() {
+                Some(last) if last.role == content.role => last.parts.extend(content.parts),
+                _ => contents.push(content),
+            }
+        }
+
+        contents
     }
 
     #[allow(dead_code)]
@@ -178,41 +359,14 @@ impl GeminiClient {
             self.base_url, self.model, self.api_key
         );
 
-        let mut contents = self.conversation_history.clone();
-        
-        if !message.is_empty()
-        {
-            contents.push(Content {
-                role: "user".to_string(),
-                parts: vec![Part {
-                    text: Some(message.to_string()),
-                    function_call: None,
-                    function_response: None,
-                }],
-            });
-
-        }
+        let contents = self.build_contents(message);
         
-        let tools = Some(vec![Tool {
-            function_declarations: crate::function_calling::FunctionExecutor::get_available_tools()
-                .into_iter()
-                .map(|tool| FunctionDeclaration {
-                    name: tool.name,
-                    description: tool.description,
-                    parameters: tool.parameters,
-                })
-                .collect(),
-        }]);
+        let tools = self.build_tools();
 
         let request = GenerateContentRequest {
             contents,
-            generation_config: Some(GenerationConfig {
-                temperature: 0.7,
-                top_p: 0.95,
-                top_k: 40,
-                max_output_tokens: 2048,
-            }),
-            system_instruction: self.system_instruction.clone(),
+            generation_config: GenerationConfig::from_options(&self.options),
+            system_instruction: self.build_system_instruction(),
             tools,
         };
 
@@ -230,19 +384,10 @@ impl GeminiClient {
             }
         }
 
-        let response = self
-            .client
-            .post(&url)
-            .json(&request)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(anyhow!("API request failed: {}", error_text));
-        }
+        let response = self.retry.send(|| self.client.post(&url).json(&request)).await?;
 
         let api_response: GenerateContentResponse = response.json().await?;
+        *self.last_usage.lock().unwrap() = api_response.usage_metadata.as_ref().map(TokenUsage::from);
 
         if api_response.candidates.is_empty() {
             return Err(anyhow!("No response from API"));
@@ -263,45 +408,20 @@ impl GeminiClient {
         Err(anyhow!("No text content in response"))
     }
 
-    pub async fn send_message_stream(&self, message: &str) -> Result<mpsc::Receiver<(String, Option<serde_json::Value>)>> {
+    pub async fn send_message_stream(&self, message: &str) -> Result<mpsc::Receiver<StreamEvent>> {
         let url = format!(
             "{}/{}:streamGenerateContent?alt=sse&key={}",
             self.base_url, self.model, self.api_key
         );
 
-        let mut contents = self.conversation_history.clone();
-        if !message.is_empty()
-        {
-            contents.push(Content {
-                role: "user".to_string(),
-                parts: vec![Part {
-                    text: Some(message.to_string()),
-                    function_call: None,
-                    function_response: None,
-                }],
-            });
-        }
+        let contents = self.build_contents(message);
 
-        let tools = Some(vec![Tool {
-            function_declarations: crate::function_calling::FunctionExecutor::get_available_tools()
-                .into_iter()
-                .map(|tool| FunctionDeclaration {
-                    name: tool.name,
-                    description: tool.description,
-                    parameters: tool.parameters,
-                })
-                .collect(),
-        }]);
+        let tools = self.build_tools();
 
         let request = GenerateContentRequest {
             contents,
-            generation_config: Some(GenerationConfig {
-                temperature: 0.7,
-                top_p: 0.95,
-                top_k: 40,
-                max_output_tokens: 2048,
-            }),
-            system_instruction: self.system_instruction.clone(),
+            generation_config: GenerationConfig::from_options(&self.options),
+            system_instruction: self.build_system_instruction(),
             tools,
         };
 
@@ -319,20 +439,14 @@ impl GeminiClient {
             }
         }
 
-        let response = self
-            .client
-            .post(&url)
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(anyhow!("API request failed: {}", error_text));
-        }
+        let response = self.retry.send(|| {
+            self.client
+                .post(&url)
+                .header("Content-Type", "application/json")
+                .json(&request)
+        }).await?;
 
-        let (tx, rx) = mpsc::channel::<(String, Option<serde_json::Value>)>(1000);
+        let (tx, rx) = mpsc::channel::<StreamEvent>(1000);
         
         // Add logging function
         let log_debug = |msg: &str| {
@@ -348,10 +462,12 @@ impl GeminiClient {
         
         log_debug("Starting streaming task");
         
-        tokio::spawn(async move {
+        spawn_stream(tx, move |tx| async move {
             let mut stream = response.bytes_stream();
             let mut buffer = String::new();
             let mut chunk_count = 0;
+            let mut tool_call_count = 0;
+            let mut last_usage = None;
             
             log_debug("Stream created, starting to read chunks");
             
@@ -377,8 +493,7 @@ impl GeminiClient {
                                 // Parse SSE event - look for data lines
                                 for line in event.lines() {
                                     let line = line.trim(); // Remove any whitespace/carriage returns
-                                    if line.starts_with("data: ") {
-                                        let json_data = &line[6..]; // Remove "data: " prefix
+                                    if let Some(json_data) = line.strip_prefix("data: ") {
                                         log_debug(&format!("Found data line: {:?}", json_data));
                                         
                                         // Skip empty data or [DONE] messages
@@ -392,46 +507,65 @@ impl GeminiClient {
                                         match serde_json::from_str::<GenerateContentResponse>(json_data) {
                                             Ok(response_data) => {
                                                 log_debug(&format!("Successfully parsed JSON, candidates: {}", response_data.candidates.len()));
+                                                let mut events = Vec::new();
+                                                
                                                 if !response_data.candidates.is_empty() {
                                                     let candidate = &response_data.candidates[0];
                                                     if !candidate.content.parts.is_empty() {
                                                         for part in &candidate.content.parts {
-                                                            let mut text_content = String::new();
-                                                            let mut function_call = None;
-                                                            
                                                             if let Some(text) = &part.text {
-                                                                text_content = text.clone();
+                                                                if !text.is_empty() {
+                                                                    events.push(StreamEvent::TextDelta(text.clone()));
+                                                                }
                                                             }
                                                             
                                                             if let Some(fc) = &part.function_call {
-                                                                function_call = Some(fc.clone());
-                                                            }
-                                                            
-                                                            if !text_content.is_empty() || function_call.is_some() {
-                                                                log_debug(&format!("GEMINI: Sending content to channel: text={:?}, function_call={:?}", text_content, function_call));
-                                                                match tx.send((text_content, function_call)).await {
-                                                                    Ok(_) => {
-                                                                        log_debug("GEMINI: Content sent successfully to channel");
-                                                                    }
-                                                                    Err(e) => {
-                                                                        log_debug(&format!("GEMINI: Receiver dropped, stopping stream: {}", e));
-                                                                        return; // Receiver dropped
-                                                                    }
-                                                                }
+                                                                // Gemini delivers each function call whole, in a single part
+                                                                let index = tool_call_count;
+                                                                tool_call_count += 1;
+                                                                let name = fc.get("name").and_then(|v| v.as_str()).unwrap_or_default().to_string();
+                                                                let id = fc.get("id")
+                                                                    .and_then(|v| v.as_str())
+                                                                    .map(|id| id.to_string())
+                                                                    .unwrap_or_else(|| format!("{}-{}-{}", name, chrono::Utc::now().timestamp_millis(), index));
+                                                                let args = fc.get("args").cloned().unwrap_or_else(|| serde_json::json!({}));
+                                                                events.push(StreamEvent::ToolCallStart {
+                                                                    index,
+                                                                    id: id.clone(),
+                                                                    name: name.clone(),
+                                                                });
+                                                                events.push(StreamEvent::ToolCallComplete { index, id, name, args });
                                                             }
                                                         }
                                                     } else {
                                                         log_debug("No parts in candidate content");
                                                     }
+                                                    
+                                                    if let Some(reason) = &candidate.finish_reason {
+                                                        events.push(StreamEvent::FinishReason(reason.clone()));
+                                                    }
                                                 } else {
                                                     log_debug("No candidates in response");
                                                 }
+                                                
+                                                // Every chunk repeats the running totals, so only the last one counts
+                                                if let Some(usage) = &response_data.usage_metadata {
+                                                    last_usage = Some(TokenUsage::from(usage));
+                                                }
+                                                
+                                                for event in events {
+                                                    log_debug(&format!("GEMINI: Sending event to channel: {:?}", event));
+                                                    if let Err(e) = tx.send(event).await {
+                                                        log_debug(&format!("GEMINI: Receiver dropped, stopping stream: {}", e));
+                                                        return; // Receiver dropped
+                                                    }
+                                                }
                                             }
                                             Err(e) => {
                                                 // Send error message for debugging
                                                 let error_msg = format!("JSON parse error: {} - Data: {}", e, json_data);
                                                 log_debug(&format!("JSON parse error: {}", error_msg));
-                                                if tx.send((error_msg, None)).await.is_err() {
+                                                if tx.send(StreamEvent::Error(error_msg)).await.is_err() {
                                                     log_debug("Failed to send error message, receiver dropped");
                                                     return;
                                                 }
@@ -446,12 +580,16 @@ impl GeminiClient {
                         // Send error message
                         let error_msg = format!("Stream error: {}", e);
                         log_debug(&format!("Stream error: {}", error_msg));
-                        let _ = tx.send((error_msg, None)).await;
+                        let _ = tx.send(StreamEvent::Error(error_msg)).await;
                         break;
                     }
                 }
             }
             
+            if let Some(usage) = last_usage {
+                let _ = tx.send(StreamEvent::Usage(usage)).await;
+            }
+            
             log_debug("Stream processing completed");
             // Explicitly drop the sender to signal completion
             drop(tx);
@@ -475,23 +613,130 @@ impl crate::chat_client::ChatClient for GeminiClient {
         self.add_function_response(function_response)
     }
     
-    fn add_model_response(&mut self, response: &str, function_call: Option<serde_json::Value>) {
-        self.add_model_response(response, function_call)
+    fn add_model_response(&mut self, response: &str, tool_calls: &[crate::function_calling::FunctionCall]) {
+        self.add_model_response(response, tool_calls)
     }
     
     fn clear_conversation(&mut self) {
         self.clear_conversation()
     }
     
+    fn set_tools(&mut self, tools: ToolRegistry) {
+        self.set_tools(tools)
+    }
+    
     async fn send_message(&self, message: &str) -> Result<String> {
         self.send_message(message).await
     }
     
-    async fn send_message_stream(&self, message: &str) -> Result<mpsc::Receiver<(String, Option<serde_json::Value>)>> {
+    async fn send_message_stream(&self, message: &str) -> Result<mpsc::Receiver<StreamEvent>> {
         self.send_message_stream(message).await
     }
     
     fn client_name(&self) -> &str {
         "Gemini"
     }
-}
\ No newline at end of file
+    
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn set_model(&mut self, model: &str) {
+        self.set_model(model)
+    }
+    
+    fn conversation(&self) -> Conversation {
+        self.conversation.clone()
+    }
+    
+    fn set_conversation(&mut self, conversation: Conversation) {
+        self.set_conversation(conversation)
+    }
+
+    fn last_usage(&self) -> Option<TokenUsage> {
+        self.last_usage()
+    }
+
+    fn generation_options(&self) -> &GenerationOptions {
+        self.generation_options()
+    }
+
+    fn set_generation_options(&mut self, options: GenerationOptions) {
+        self.set_generation_options(options)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::function_calling::{FunctionCall, FunctionResponse};
+
+    #[test]
+    fn test_generation_config_is_sent_only_when_options_are_set() {
+        assert!(GenerationConfig::from_options(&GenerationOptions::new()).is_none());
+
+        let mut options = GenerationOptions::new();
+        options.set("max_tokens", "8192").unwrap();
+        options.set("reasoning_effort", "low").unwrap();
+        let config = serde_json::to_value(GenerationConfig::from_options(&options)).unwrap();
+        assert_eq!(config, serde_json::json!({
+            "maxOutputTokens": 8192,
+            "thinkingConfig": { "thinkingBudget": 1024 },
+        }));
+    }
+
+    #[test]
+    fn test_conversation_translates_to_contents() {
+        let mut client = GeminiClient::new("key".to_string(), "gemini-2.0-flash".to_string());
+        client.add_user_message("List files");
+        client.add_model_response("", &[FunctionCall {
+            id: None,
+            name: "list_directory".to_string(),
+            args: serde_json::json!({}),
+        }]);
+        client.add_function_response(&FunctionResponse {
+            id: "list_directory-1".to_string(),
+            name: "list_directory".to_string(),
+            response: serde_json::json!({"entries": []}),
+        });
+
+        let contents = serde_json::to_value(client.build_contents("")).unwrap();
+
+        assert_eq!(contents, serde_json::json!([
+            {"role": "user", "parts": [{"text": "List files"}]},
+            {"role": "model", "parts": [{"functionCall": {"name": "list_directory", "args": {}}}]},
+            {"role": "user", "parts": [{"functionResponse": {
+                "id": "list_directory-1", "name": "list_directory", "response": {"entries": []}
+            }}]}
+        ]));
+    }
+
+    #[test]
+    fn test_parallel_tool_results_share_one_content() {
+        let mut client = GeminiClient::new("key".to_string(), "gemini-2.0-flash".to_string());
+        client.add_user_message("Read both files");
+        let calls: Vec<FunctionCall> = ["a.txt", "b.txt"].iter()
+            .map(|path| FunctionCall {
+                id: None,
+                name: "read_file".to_string(),
+                args: serde_json::json!({ "path": path }),
+            })
+            .collect();
+        client.add_model_response("", &calls);
+        for (i, call) in calls.iter().enumerate() {
+            client.add_function_response(&FunctionResponse {
+                id: format!("read_file-{}", i),
+                name: call.name.clone(),
+                response: serde_json::json!({ "content": "" }),
+            });
+        }
+
+        let contents = client.build_contents("");
+
+        assert_eq!(contents.len(), 3);
+        assert_eq!(contents[1].role, "model");
+        assert_eq!(contents[1].parts.len(), 2);
+        assert_eq!(contents[2].role, "user");
+        assert_eq!(contents[2].parts.len(), 2);
+        assert!(contents[2].parts.iter().all(|part| part.function_response.is_some()));
+    }
+}
@@ -0,0 +1,221 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Names accepted by `GenerationOptions::set`, in display order
+pub const GENERATION_OPTION_NAMES: &[&str] = &[
+    "temperature",
+    "top_p",
+    "top_k",
+    "max_tokens",
+    "stop",
+    "seed",
+    "presence_penalty",
+    "frequency_penalty",
+    "reasoning_effort",
+];
+
+/// How much a reasoning model thinks before it answers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningEffort {
+    Minimal,
+    Low,
+    Medium,
+    High,
+}
+
+impl ReasoningEffort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReasoningEffort::Minimal => "minimal",
+            ReasoningEffort::Low => "low",
+            ReasoningEffort::Medium => "medium",
+            ReasoningEffort::High => "high",
+        }
+    }
+
+    /// A thinking budget in tokens, for APIs that take one instead of a level
+    pub fn thinking_budget(&self) -> u32 {
+        match self {
+            ReasoningEffort::Minimal => 0,
+            ReasoningEffort::Low => 1024,
+            ReasoningEffort::Medium => 8192,
+            ReasoningEffort::High => 24576,
+        }
+    }
+}
+
+impl FromStr for ReasoningEffort {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "minimal" => Ok(ReasoningEffort::Minimal),
+            "low" => Ok(ReasoningEffort::Low),
+            "medium" => Ok(ReasoningEffort::Medium),
+            "high" => Ok(ReasoningEffort::High),
+            other => Err(anyhow!("Unknown reasoning effort '{}'; use minimal, low, medium or high", other)),
+        }
+    }
+}
+
+/// Sampling and length settings sent with every request. Unset options are left to the
+/// provider's defaults, and each client leaves out the ones its API does not support.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GenerationOptions {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub top_k: Option<u32>,
+    /// Longest answer in tokens
+    pub max_tokens: Option<u32>,
+    pub stop: Vec<String>,
+    pub seed: Option<i64>,
+    pub presence_penalty: Option<f32>,
+    pub frequency_penalty: Option<f32>,
+    pub reasoning_effort: Option<ReasoningEffort>,
+}
+
+fn parse<T: FromStr>(name: &str, value: &str) -> Result<T>
+where
+    T::Err: std::fmt::Display,
+{
+    value.parse().map_err(|e| anyhow!("Invalid {} '{}': {}", name, value, e))
+}
+
+fn in_range(name: &str, value: f32, min: f32, max: f32) -> Result<f32> {
+    if (min..=max).contains(&value) {
+        Ok(value)
+    } else {
+        Err(anyhow!("{} must be between {} and {}", name, min, max))
+    }
+}
+
+impl GenerationOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Options from `CHAT_CLI_<NAME>` environment variables, e.g. `CHAT_CLI_MAX_TOKENS=8192`
+    pub fn from_env() -> Result<Self> {
+        let mut options = Self::new();
+        for name in GENERATION_OPTION_NAMES {
+            let variable = format!("CHAT_CLI_{}", name.to_uppercase());
+            if let Ok(value) = std::env::var(&variable) {
+                options.set(name, &value).with_context(|| format!("Invalid {}", variable))?;
+            }
+        }
+        Ok(options)
+    }
+
+    /// Set one option by name, as in `/set temperature 0.2`. `default` unsets it.
+    /// Stop sequences are a JSON array of strings or a single sequence.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        let value = value.trim();
+        let unset = value.is_empty() || value.eq_ignore_ascii_case("default");
+        match name.replace('-', "_").as_str() {
+            "temperature" if unset => self.temperature = None,
+            "temperature" => self.temperature = Some(in_range(name, parse(name, value)?, 0.0, 2.0)?),
+            "top_p" if unset => self.top_p = None,
+            "top_p" => self.top_p = Some(in_range(name, parse(name, value)?, 0.0, 1.0)?),
+            "top_k" if unset => self.top_k = None,
+            "top_k" => self.top_k = Some(parse(name, value)?),
+            "max_tokens" if unset => self.max_tokens = None,
+            "max_tokens" => match parse(name, value)? {
+                0 => return Err(anyhow!("max_tokens must be at least 1")),
+                tokens => self.max_tokens = Some(tokens),
+            },
+            "stop" if unset => self.stop.clear(),
+            "stop" if value.starts_with('[') => {
+                self.stop = serde_json::from_str(value).map_err(|e| anyhow!("Invalid stop sequences: {}", e))?;
+            }
+            "stop" => self.stop = vec![value.to_string()],
+            "seed" if unset => self.seed = None,
+            "seed" => self.seed = Some(parse(name, value)?),
+            "presence_penalty" if unset => self.presence_penalty = None,
+            "presence_penalty" => self.presence_penalty = Some(in_range(name, parse(name, value)?, -2.0, 2.0)?),
+            "frequency_penalty" if unset => self.frequency_penalty = None,
+            "frequency_penalty" => self.frequency_penalty = Some(in_range(name, parse(name, value)?, -2.0, 2.0)?),
+            "reasoning_effort" if unset => self.reasoning_effort = None,
+            "reasoning_effort" => self.reasoning_effort = Some(value.parse()?),
+            _ => return Err(anyhow!("Unknown option '{}'; choose one of: {}", name, GENERATION_OPTION_NAMES.join(", "))),
+        }
+        Ok(())
+    }
+
+    /// Let the options `other` sets replace these
+    pub fn merge(&mut self, other: GenerationOptions) {
+        self.temperature = other.temperature.or(self.temperature);
+        self.top_p = other.top_p.or(self.top_p);
+        self.top_k = other.top_k.or(self.top_k);
+        self.max_tokens = other.max_tokens.or(self.max_tokens);
+        if !other.stop.is_empty() {
+            self.stop = other.stop;
+        }
+        self.seed = other.seed.or(self.seed);
+        self.presence_penalty = other.presence_penalty.or(self.presence_penalty);
+        self.frequency_penalty = other.frequency_penalty.or(self.frequency_penalty);
+        self.reasoning_effort = other.reasoning_effort.or(self.reasoning_effort);
+    }
+
+    /// Check the ranges `set` enforces, for options read from a config file
+    pub fn validate(&self) -> Result<()> {
+        let check = |name: &str, value: Option<f32>, min: f32, max: f32| value.map_or(Ok(()), |v| in_range(name, v, min, max).map(|_| ()));
+        check("temperature", self.temperature, 0.0, 2.0)?;
+        check("top_p", self.top_p, 0.0, 1.0)?;
+        check("presence_penalty", self.presence_penalty, -2.0, 2.0)?;
+        check("frequency_penalty", self.frequency_penalty, -2.0, 2.0)?;
+        if self.max_tokens == Some(0) {
+            return Err(anyhow!("max_tokens must be at least 1"));
+        }
+        Ok(())
+    }
+
+    /// Options that are set, as name and value, for display
+    pub fn describe(&self) -> Vec<(&'static str, String)> {
+        let mut set = Vec::new();
+        let mut add = |name: &'static str, value: Option<String>| {
+            if let Some(value) = value {
+                set.push((name, value));
+            }
+        };
+        add("temperature", self.temperature.map(|v| v.to_string()));
+        add("top_p", self.top_p.map(|v| v.to_string()));
+        add("top_k", self.top_k.map(|v| v.to_string()));
+        add("max_tokens", self.max_tokens.map(|v| v.to_string()));
+        add("stop", (!self.stop.is_empty()).then(|| serde_json::to_string(&self.stop).unwrap_or_default()));
+        add("seed", self.seed.map(|v| v.to_string()));
+        add("presence_penalty", self.presence_penalty.map(|v| v.to_string()));
+        add("frequency_penalty", self.frequency_penalty.map(|v| v.to_string()));
+        add("reasoning_effort", self.reasoning_effort.map(|v| v.as_str().to_string()));
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_parses_validates_and_unsets() {
+        let mut options = GenerationOptions::new();
+        options.set("temperature", "0.2").unwrap();
+        options.set("max-tokens", "8192").unwrap();
+        options.set("stop", r#"["STOP", "END"]"#).unwrap();
+        options.set("reasoning_effort", "High").unwrap();
+
+        assert_eq!(options.temperature, Some(0.2));
+        assert_eq!(options.max_tokens, Some(8192));
+        assert_eq!(options.stop, ["STOP", "END"]);
+        assert_eq!(options.reasoning_effort, Some(ReasoningEffort::High));
+
+        assert!(options.set("temperature", "3").is_err());
+        assert!(options.set("top_p", "lots").is_err());
+        assert!(options.set("warmth", "1").is_err());
+
+        options.set("temperature", "default").unwrap();
+        assert_eq!(options.temperature, None);
+        assert_eq!(options.describe()[0], ("max_tokens", "8192".to_string()));
+    }
+}
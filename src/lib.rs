@@ -1,10 +1,37 @@
+pub mod agent;
+pub mod anthropic;
 pub mod chat_client;
+pub mod config;
+pub mod context;
+pub mod conversation;
 pub mod function_calling;
 pub mod gemini;
+pub mod generation;
+pub mod markdown;
+pub mod ollama;
+pub mod one_shot;
 pub mod openai;
+pub mod permissions;
 pub mod mock_llm;
+pub mod response_card;
+pub mod retry;
+pub mod session;
+pub mod usage;
+pub mod prompt_input;
+pub mod loading_animation;
+#[cfg(test)]
+mod test_support;
 
 // Re-export commonly used types
-pub use chat_client::{ChatClient, AnyChatClient};
-pub use function_calling::{FunctionCall, FunctionResponse, FunctionExecutor};
-pub use mock_llm::MockLLMClient;
\ No newline at end of file
+pub use agent::{Agent, AgentObserver, StopHandle, StopReason, TurnResult};
+pub use chat_client::{ChatClient, AnyChatClient, StreamEvent, TokenUsage};
+pub use config::Config;
+pub use context::{CompactionStrategy, ContextManager};
+pub use conversation::{ChatMessage, Conversation, MessagePart, Role};
+pub use generation::{GenerationOptions, ReasoningEffort};
+pub use function_calling::{FunctionCall, FunctionResponse, FunctionExecutor, Tool, ToolOutput, ToolRegistry};
+pub use mock_llm::MockLLMClient;
+pub use permissions::{PermissionDecision, PermissionPolicy};
+pub use retry::RetryPolicy;
+pub use session::{Session, SessionStore};
+pub use usage::{PriceTable, SessionUsage};
@@ -150,21 +150,21 @@ where
     result
 }
 
-/// Show loading animation in a bordered response box (matches target design)
-pub async fn show_loading_in_response_box<F, T>(future: F) -> T
+/// Show loading animation in a bordered response box `width` columns wide (matches target design)
+pub async fn show_loading_in_response_box<F, T>(width: usize, future: F) -> T
 where
     F: std::future::Future<Output = T>,
 {
-    let width: usize = 120;
+    let bottom_border = format!("╰{}╯", "─".repeat(width.saturating_sub(2)));
     
     // Draw response box header
-    println!("╭─ Response ───────────────────────────────────────────────────────────────────────────────────────────────────────────╮");
+    println!("╭─ Response {}╮", "─".repeat(width.saturating_sub(13)));
     print!("│ ");
     io::stdout().flush().ok();
     
     // Show interrupt hint during loading
     println!();
-    println!("╰──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────╯");
+    println!("{}", bottom_border);
     println!(" ctrl+c to interrupt");
     
     // Move cursor back up to the spinner position
@@ -200,8 +200,8 @@ where
     // Clear the loading content and interrupt hint
     print!("\r│ ");
     println!("{} │", " ".repeat(width.saturating_sub(4))); // Clear the spinner line
-    println!("╰──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────╯");
-    println!("{}", " ".repeat(120)); // Clear the interrupt hint line
+    println!("{}", bottom_border);
+    println!("{}", " ".repeat(width)); // Clear the interrupt hint line
     
     // Move cursor back up to prepare for response content
     execute!(io::stdout(), cursor::MoveUp(2)).ok();
@@ -256,6 +256,28 @@ impl ProgressSteps {
         Ok(())
     }
 
+    /// Redraw a progress bar for the current step on one line, e.g. while downloading.
+    /// `complete_step` or `fail_step` then prints the final status below it.
+    pub fn update_progress(&self, completed: u64, total: u64) -> io::Result<()> {
+        const BAR_WIDTH: usize = 40;
+        let fraction = if total == 0 { 0.0 } else { (completed.min(total) as f64) / (total as f64) };
+        let filled = (fraction * BAR_WIDTH as f64).round() as usize;
+        let megabytes = |bytes: u64| bytes as f64 / 1_000_000.0;
+        execute!(
+            io::stdout(),
+            Print(format!("\r{}   [{}{}] {:>3.0}% {:.1}/{:.1} MB",
+                SetForegroundColor(Color::Cyan),
+                "█".repeat(filled),
+                "░".repeat(BAR_WIDTH - filled),
+                fraction * 100.0,
+                megabytes(completed),
+                megabytes(total)
+            )),
+            ResetColor
+        )?;
+        io::stdout().flush()
+    }
+
     pub fn fail_step(&self, step_index: usize, error: &str) -> io::Result<()> {
         if step_index < self.steps.len() {
             execute!(
@@ -1,23 +1,34 @@
-mod gemini;
-mod openai;
-mod mock_llm;
-mod response_card;
-mod prompt_input;
-mod loading_animation;
-mod function_calling;
-mod chat_client;
-
 use anyhow::Result;
 use dotenv::dotenv;
-use chat_client::{ChatClient, AnyChatClient};
-use response_card::ResponseCard;
-use prompt_input::PromptInput;
-use loading_animation::{LoadingAnimation, AnimationStyle, show_loading_in_response_box};
-use function_calling::FunctionExecutor;
+use async_trait::async_trait;
+use chat_cli::agent::{self, Agent, AgentObserver, AgentStep, StopReason};
+use chat_cli::context::{self, CompactionReport, CompactionStrategy, ContextManager};
+use chat_cli::chat_client::{ChatClient, AnyChatClient, StreamEvent, TokenUsage, PROVIDERS};
+use chat_cli::response_card::ResponseCard;
+use chat_cli::prompt_input::PromptInput;
+use chat_cli::loading_animation::{LoadingAnimation, LoadingHandle, AnimationStyle, ProgressSteps, show_loading_in_response_box};
+use chat_cli::function_calling::{
+    FunctionCall, FunctionExecutor, FunctionResponse, ShellCommandTool, ToolOutput, ToolRegistry,
+    DEFAULT_SHELL_MAX_BYTES, DEFAULT_SHELL_MAX_LINES, DEFAULT_SHELL_TIMEOUT_SECS,
+};
+use chat_cli::permissions::{self, PermissionDecision, PermissionPolicy};
+use chat_cli::session::{Session, SessionStore};
+use chat_cli::generation::{GenerationOptions, GENERATION_OPTION_NAMES};
+use chat_cli::usage::{self, PriceTable, SessionUsage};
+use chat_cli::one_shot::{self, JsonObserver, OutputFormat, PlainObserver};
+use chat_cli::config::Config;
+use clap::Parser;
+use chrono::Datelike;
 use std::{
     env,
-    io::{self, Write},
+    io::{self, IsTerminal, Read, Write},
     fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 use crossterm::{
     execute,
@@ -25,66 +36,297 @@ use crossterm::{
     cursor,
 };
 
+/// Chat with OpenAI, Azure OpenAI, Gemini, Anthropic and Ollama models in the terminal.
+/// Settings come from these flags, then environment variables, then `.chat-cli.toml`,
+/// then `~/.config/chat-cli/config.toml`.
+#[derive(Debug, Parser)]
+#[command(name = "chat-cli", version)]
+struct Cli {
+    /// Send this message, print the answer and exit
+    #[arg(short = 'p', long = "print", value_name = "MESSAGE")]
+    print: Option<String>,
+    
+    /// Message to send, as with -p; piped input is added after it
+    #[arg(value_name = "PROMPT")]
+    prompt: Vec<String>,
+    
+    /// One of openai, azure, gemini, anthropic, ollama or mock
+    #[arg(long)]
+    provider: Option<String>,
+    
+    /// Model name, or the deployment on Azure
+    #[arg(short, long)]
+    model: Option<String>,
+    
+    /// API base URL; the endpoint on Azure and the host for Ollama
+    #[arg(long, value_name = "URL")]
+    base_url: Option<String>,
+    
+    /// System prompt file [default: system_prompt.md]
+    #[arg(long, value_name = "PATH")]
+    system_prompt: Option<PathBuf>,
+    
+    /// Stream responses as they are written
+    #[arg(long, value_name = "BOOL")]
+    stream: Option<bool>,
+    
+    /// Offer the model no tools
+    #[arg(long)]
+    no_tools: bool,
+    
+    /// Run tool calls without asking for approval
+    #[arg(long)]
+    yolo: bool,
+    
+    /// Width of the input box and response cards [default: 120]
+    #[arg(long, value_name = "COLUMNS")]
+    width: Option<usize>,
+    
+    /// Print answers as written instead of rendering their Markdown
+    #[arg(long)]
+    raw: bool,
+    
+    /// Use a profile from the config files
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+    
+    /// Continue the most recent session
+    #[arg(short = 'c', long = "continue")]
+    continue_session: bool,
+    
+    /// How one-shot answers are printed: text, json or stream-json
+    #[arg(long, value_name = "FORMAT", default_value = "text")]
+    output_format: OutputFormat,
+    
+    /// Sampling temperature
+    #[arg(long, help_heading = "Generation")]
+    temperature: Option<String>,
+    /// Nucleus sampling probability mass
+    #[arg(long, help_heading = "Generation")]
+    top_p: Option<String>,
+    /// Sample from the k most likely tokens (Gemini, Anthropic and Ollama)
+    #[arg(long, help_heading = "Generation")]
+    top_k: Option<String>,
+    /// Longest answer, in tokens
+    #[arg(long, help_heading = "Generation")]
+    max_tokens: Option<String>,
+    /// Stop sequence, or a JSON array of them
+    #[arg(long, help_heading = "Generation")]
+    stop: Option<String>,
+    /// Seed for repeatable sampling
+    #[arg(long, help_heading = "Generation")]
+    seed: Option<String>,
+    /// Penalty for tokens that already appeared
+    #[arg(long, help_heading = "Generation")]
+    presence_penalty: Option<String>,
+    /// Penalty for tokens by how often they appeared
+    #[arg(long, help_heading = "Generation")]
+    frequency_penalty: Option<String>,
+    /// minimal, low, medium or high
+    #[arg(long, help_heading = "Generation")]
+    reasoning_effort: Option<String>,
+}
+
+impl Cli {
+    /// The settings given as flags, as the top configuration layer
+    fn config(&self) -> Result<Config> {
+        let mut generation = GenerationOptions::new();
+        for (name, value) in self.generation_flags() {
+            generation.set(name, value).map_err(|e| anyhow::anyhow!("--{}: {}", name.replace('_', "-"), e))?;
+        }
+        Ok(Config {
+            provider: self.provider.clone(),
+            model: self.model.clone(),
+            base_url: self.base_url.clone(),
+            system_prompt: self.system_prompt.clone(),
+            stream: self.stream,
+            tools: self.no_tools.then_some(false),
+            yolo: self.yolo.then_some(true),
+            width: self.width,
+            raw: self.raw.then_some(true),
+            generation,
+            ..Config::default()
+        })
+    }
+    
+    /// Generation options given as flags, by their `GenerationOptions::set` names
+    fn generation_flags(&self) -> Vec<(&'static str, &str)> {
+        [
+            ("temperature", &self.temperature),
+            ("top_p", &self.top_p),
+            ("top_k", &self.top_k),
+            ("max_tokens", &self.max_tokens),
+            ("stop", &self.stop),
+            ("seed", &self.seed),
+            ("presence_penalty", &self.presence_penalty),
+            ("frequency_penalty", &self.frequency_penalty),
+            ("reasoning_effort", &self.reasoning_effort),
+        ]
+            .into_iter()
+            .filter_map(|(name, value)| value.as_deref().map(|value| (name, value)))
+            .collect()
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
     // Load environment variables
     dotenv().ok();
+    let cli = Cli::parse();
     
-    // Determine which client to use based on environment variables
-    let mut client: Box<dyn ChatClient> = if let Ok(openai_key) = env::var("OPENAI_API_KEY") {
-        // Check if user wants to use OpenAI specifically
-        let model = env::var("OPENAI_MODEL").unwrap_or_else(|_| "gpt-4".to_string());
-        let base_url = env::var("OPENAI_BASE_URL").unwrap_or_else(|_| "https://api.openai.com/v1".to_string());
-        
-        let mut openai_client = AnyChatClient::new_openai_with_base_url(openai_key, model, base_url);
-        
-        // Load system prompt
-        if let Ok(system_prompt) = fs::read_to_string("system_prompt.md") {
-            openai_client.load_system_prompt(&system_prompt)?;
-            println!("System prompt loaded from system_prompt.md");
-        } else {
-            println!("No system_prompt.md found, continuing without system prompt");
-        }
-        
-        Box::new(openai_client)
-    } else if let Ok(gemini_key) = env::var("GEMINI_API_KEY") {
-        let model = env::var("GEMINI_MODEL").unwrap_or_else(|_| "gemini-2.0-flash-exp".to_string());
-        let mut gemini_client = AnyChatClient::new_gemini(gemini_key, model);
-        
-        // Load system prompt
-        if let Ok(system_prompt) = fs::read_to_string("system_prompt.md") {
-            gemini_client.load_system_prompt(&system_prompt)?;
-            println!("System prompt loaded from system_prompt.md");
-        } else {
-            println!("No system_prompt.md found, continuing without system prompt");
+    // Flags win over environment variables, which win over the project's and then the user's config file
+    let mut files = Config::load()?;
+    if let Some(profile) = &cli.profile {
+        files = files.with_profile(profile)?;
+    }
+    files.fill_env();
+    let config = Config::resolve(files, cli.config()?)?;
+    config.export_env();
+    
+    // `-p "question"`, a bare prompt or piped input sends one message and exits instead of starting the chat
+    let prompt = cli.print.clone().or_else(|| (!cli.prompt.is_empty()).then(|| cli.prompt.join(" ")));
+    let piped = if io::stdin().is_terminal() {
+        None
+    } else {
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input)?;
+        Some(input)
+    };
+    let one_shot_message = if prompt.is_some() || piped.is_some() {
+        match one_shot::compose_message(prompt.as_deref(), piped.as_deref()) {
+            Some(message) => Some(message),
+            None => {
+                eprintln!("Nothing to send: give a message with -p or pipe text in");
+                std::process::exit(one_shot::EXIT_USAGE);
+            }
         }
-        
-        Box::new(gemini_client)
+    } else if cli.output_format != OutputFormat::Text {
+        eprintln!("--output-format {} needs a message from -p or piped input", cli.output_format.as_str());
+        std::process::exit(one_shot::EXIT_USAGE);
     } else {
-        return Err(anyhow::anyhow!(
-            "No API key found. Please set either OPENAI_API_KEY or GEMINI_API_KEY environment variable.\n\
-             You can also set OPENAI_MODEL, GEMINI_MODEL, and OPENAI_BASE_URL for customization."
-        ));
+        None
     };
+    let interactive = one_shot_message.is_none();
     
-    // Initialize function executor
-    let function_executor = FunctionExecutor::new();
+    // The provider comes from --provider, CHAT_CLI_PROVIDER, the config files or the first API key found; /provider switches later
+    let provider = config.provider.clone().ok_or_else(|| anyhow::anyhow!(
+        "No API key found. Please set OPENAI_API_KEY, AZURE_OPENAI_API_KEY, GEMINI_API_KEY or ANTHROPIC_API_KEY environment variable.\n\
+         You can also choose a provider with --provider or in ~/.config/chat-cli/config.toml."
+    ))?;
+    let mut client = AnyChatClient::from_env(&provider, config.model.as_deref())?;
+    
+    // Generation options come from [generation] in the config files, CHAT_CLI_* variables, then flags such as --temperature 0.2
+    client.set_generation_options(config.generation.clone());
+    if interactive {
+        ensure_local_model(&client).await;
+    }
+    
+    // Load system prompt
+    let system_prompt_path = config.system_prompt_path();
+    if let Ok(system_prompt) = fs::read_to_string(&system_prompt_path) {
+        client.load_system_prompt(&system_prompt)?;
+        if interactive {
+            println!("System prompt loaded from {}", system_prompt_path.display());
+        }
+    } else if interactive {
+        println!("No {} found, continuing without system prompt", system_prompt_path.display());
+    }
+    
+    // Register the tools offered to the model; the client declares them and the agent runs them
+    let mut tools = ToolRegistry::new();
+    if config.tools_enabled() {
+        tools = ToolRegistry::with_builtin_tools();
+        let env_number = |name: &str| env::var(name).ok().and_then(|v| v.parse::<u64>().ok());
+        tools.register(
+            ShellCommandTool::default()
+                .with_timeout(Duration::from_secs(env_number("CHAT_CLI_SHELL_TIMEOUT").unwrap_or(DEFAULT_SHELL_TIMEOUT_SECS)))
+                .with_output_limits(
+                    env_number("CHAT_CLI_SHELL_MAX_BYTES").map_or(DEFAULT_SHELL_MAX_BYTES, |n| n as usize),
+                    env_number("CHAT_CLI_SHELL_MAX_LINES").map_or(DEFAULT_SHELL_MAX_LINES, |n| n as usize),
+                ),
+        );
+    }
+    client.set_tools(tools.clone());
+    
+    // Initialize the agent that runs tools on the model's behalf
+    let max_steps = env::var("CHAT_CLI_MAX_STEPS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(agent::DEFAULT_MAX_STEPS);
+    
+    // Tool calls need approval unless the policy files allow them or --yolo is given
+    let permissions = PermissionPolicy::load()?.with_yolo(config.yolo_enabled());
+    
+    // Prices turn token counts into costs; the built-in table can be overridden per model
+    let prices = PriceTable::load()?;
+    // Long conversations are compacted before they overflow the model's context window
+    let mut agent = Agent::new(FunctionExecutor::with_registry(tools.clone()))
+        .with_max_steps(max_steps)
+        .with_permissions(permissions)
+        .with_context(ContextManager::from_env()?);
+    
+    if let Some(message) = one_shot_message {
+        let code = run_one_shot(&agent, &mut client, &message, cli.output_format).await;
+        std::process::exit(code);
+    }
     
     // Clear screen and show welcome
     execute!(io::stdout(), terminal::Clear(ClearType::All), cursor::MoveTo(0, 0))?;
     println!("{} Chat CLI", client.client_name());
     println!("===============");
     println!("Enhanced with fancy input and Docker-style loading animations!");
-    println!("Using: {}", client.client_name());
+    println!("Using: {} ({})", client.client_name(), client.model());
+    if agent.permissions().is_yolo() {
+        println!("[WARN] --yolo: tool calls run without asking for approval");
+    }
+    
+    // Conversations are saved after every turn; --continue picks up the most recent one
+    let store = SessionStore::default_location();
+    let mut session = Session::new(client.client_name(), client.model());
+    if cli.continue_session {
+        match store.latest()? {
+            Some(latest) => {
+                latest.restore_into(&mut client);
+                println!("Resumed session {} ({} messages): {}", latest.id, latest.conversation.len(), latest.title);
+                session = latest;
+            }
+            None => println!("No saved sessions to continue; starting a new one"),
+        }
+    }
     println!();
     
-    let mut streaming_mode = true;
-    let prompt_input = PromptInput::new().with_width(120);
+    // Ctrl+C interrupts the running turn (the response stream or any tool it is running);
+    // at the prompt the first press discards the line and a second one exits
+    let turn_active = Arc::new(AtomicBool::new(false));
+    let exit_armed = Arc::new(AtomicBool::new(false));
+    {
+        let turn_active = turn_active.clone();
+        let exit_armed = exit_armed.clone();
+        let stop_handle = agent.stop_handle();
+        tokio::spawn(async move {
+            while tokio::signal::ctrl_c().await.is_ok() {
+                if turn_active.load(Ordering::Relaxed) {
+                    stop_handle.stop();
+                } else if exit_armed.swap(true, Ordering::Relaxed) {
+                    println!();
+                    std::process::exit(130);
+                } else {
+                    print!("\n  Press Ctrl+C again to exit, or type a message: ");
+                    io::stdout().flush().ok();
+                }
+            }
+        });
+    }
+    
+    let mut streaming_mode = config.stream_enabled();
+    let width = config.display_width();
+    let markdown = !config.raw_enabled();
+    let prompt_input = PromptInput::new().with_width(width);
     
     loop {
         // Get user input with fancy prompt
         let input = prompt_input.get_input()?;
+        exit_armed.store(false, Ordering::Relaxed);
         let input = input.trim();
         
         if input.is_empty() {
@@ -92,7 +334,11 @@ async fn main() -> Result<()> {
         }
         
         // Handle commands
-        match input {
+        let (command, argument) = match input.split_once(char::is_whitespace) {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (input, ""),
+        };
+        match command {
             "/quit" | "/exit" | "/q" => {
                 println!("Goodbye!");
                 break;
@@ -106,15 +352,26 @@ async fn main() -> Result<()> {
                 println!("  /clear   - Clear the screen");
                 println!("  /quit    - Exit the chat");
                 println!("  /stream  - Toggle streaming mode (current: {})", if streaming_mode { "ON" } else { "OFF" });
-                println!("  /switch  - Clear conversation history");
+                println!("  /switch  - Clear conversation history and start a new session");
+                println!("  /model [name] - Show or change the model (current: {})", client.model());
+                println!("  /provider [name] [model] - Show or change the provider: {}", PROVIDERS.join(", "));
+                println!("  /models  - List the models installed on the Ollama server");
+                println!("  /set [name value] - Show or change a generation option ('default' unsets it)");
+                println!("  /cost    - Show the tokens and cost of this session and this month");
+                println!("  /context [strategy] - Show context use, or set the strategy: {}", CompactionStrategy::NAMES.join(", "));
+                println!("  /compact - Replace the conversation so far with a summary written by the model");
+                println!("  /save    - Save the conversation now (it is also saved after every message)");
+                println!("  /sessions - List saved sessions");
+                println!("  /resume <id> - Load a saved session (an id prefix is enough)");
+                println!("  /delete <id> - Delete a saved session");
                 println!();
                 println!("Features:");
                 println!("  * Fancy bordered input interface");
                 println!("  * Docker-style loading animations");
                 println!("  * Real-time streaming responses");
                 println!("  * Beautiful response cards");
-                println!("  * Function calling support");
-                println!("  * Multi-provider support (OpenAI/Gemini)");
+                println!("  * Function calling support with approval prompts (--yolo to skip)");
+                println!("  * Multi-provider support (OpenAI/Gemini/Anthropic/Ollama)");
                 continue;
             }
             "/clear" | "/cls" => {
@@ -131,172 +388,581 @@ async fn main() -> Result<()> {
             }
             "/switch" | "/reset" => {
                 client.clear_conversation();
+                session = Session::new(client.client_name(), client.model());
                 println!("Conversation history cleared!");
                 continue;
             }
-            _ => {}
-        }
-        
-        // Add user message to conversation history
-        client.add_user_message(input);
-        
-        // Send message to Gemini with loading animation
-        if streaming_mode {
-            // Use ResponseCard for proper streaming with loading animation
-            let response_card = ResponseCard::with_title("Response");
-            
-            // Show initial loading in response box with interrupt hint
-            response_card.start_streaming()?;
-            
-            // Show interrupt hint during loading
-            println!();
-            println!();
-            println!(" ctrl+c to interrupt");
-            
-            // Move cursor back up to spinner position
-            execute!(io::stdout(), cursor::MoveUp(3), cursor::MoveToColumn(3))?;
-            
-            // Start spinner for connection
-            let loading = LoadingAnimation::new("Connecting...")
-                .with_style(AnimationStyle::Spinner);
-            let loading_handle = loading.start();
-            
-            match client.send_message_stream(input).await {
-                Ok(mut rx) => {
-                    loading_handle.stop().await;
-                    
-                    // Clear the loading line and interrupt hint, start streaming content
-                    print!("\r│ ");
-                    io::stdout().flush()?;
-                    
-                    let mut response_text = String::new();
-                    let mut function_calls = Vec::new();
-                    let mut is_first_chunk = true;
-                    
-                    while let Some((text_chunk, function_call)) = rx.recv().await {
-                        if is_first_chunk {
-                            // Clear interrupt hint lines
-                            execute!(io::stdout(), cursor::MoveDown(2))?;
-                            println!("{}", " ".repeat(120)); // Clear the interrupt hint line
-                            execute!(io::stdout(), cursor::MoveUp(3), cursor::MoveToColumn(3))?;
-                            is_first_chunk = false;
+            "/model" => {
+                if argument.is_empty() {
+                    println!("Model: {} ({})", client.model(), client.client_name());
+                } else {
+                    client.set_model(argument);
+                    println!("Switched to model {}; the conversation continues", client.model());
+                    ensure_local_model(&client).await;
+                }
+                continue;
+            }
+            "/models" => {
+                match client.list_models().await {
+                    Ok(models) if models.is_empty() => println!("{} has no models installed", client.client_name()),
+                    Ok(models) => {
+                        for model in models {
+                            let marker = if model == client.model() { "*" } else { " " };
+                            println!("{} {}", marker, model);
                         }
-                        
-                        if !text_chunk.is_empty() {
-                            response_card.stream_content(&text_chunk)?;
-                            response_text.push_str(&text_chunk);
+                    }
+                    Err(e) => println!("[ERROR] {}", e),
+                }
+                continue;
+            }
+            "/provider" => {
+                if argument.is_empty() {
+                    println!("Provider: {} ({})  Available: {}", client.client_name(), client.model(), PROVIDERS.join(", "));
+                    continue;
+                }
+                let (provider, model) = match argument.split_once(char::is_whitespace) {
+                    Some((provider, model)) => (provider, Some(model.trim())),
+                    None => (argument, None),
+                };
+                match AnyChatClient::from_env(provider, model) {
+                    Ok(mut next) => {
+                        // The new client takes over the tools and the whole conversation, system prompt included
+                        next.set_tools(tools.clone());
+                        next.set_generation_options(client.generation_options().clone());
+                        next.set_conversation(client.conversation());
+                        client = next;
+                        println!("Switched to {} ({}); the conversation continues", client.client_name(), client.model());
+                        ensure_local_model(&client).await;
+                    }
+                    Err(e) => println!("[ERROR] {}", e),
+                }
+                continue;
+            }
+            "/set" => {
+                if argument.is_empty() {
+                    let set = client.generation_options().describe();
+                    if set.is_empty() {
+                        println!("No generation options set; {} uses its defaults", client.client_name());
+                    }
+                    for (name, value) in set {
+                        println!("  {} = {}", name, value);
+                    }
+                    println!("Options: {}", GENERATION_OPTION_NAMES.join(", "));
+                    continue;
+                }
+                let (name, value) = argument.split_once(char::is_whitespace).unwrap_or((argument, ""));
+                let mut options = client.generation_options().clone();
+                match options.set(name, value) {
+                    Ok(()) => {
+                        client.set_generation_options(options);
+                        println!("{} = {}", name, if value.trim().is_empty() { "default" } else { value.trim() });
+                    }
+                    Err(e) => println!("[ERROR] {}", e),
+                }
+                continue;
+            }
+            "/context" => {
+                let Some(mut manager) = agent.context().cloned() else { continue };
+                if !argument.is_empty() {
+                    match argument.parse() {
+                        Ok(strategy) => {
+                            manager.set_strategy(strategy);
+                            agent.set_context(manager.clone());
                         }
-                        
-                        if let Some(fc) = function_call {
-                            function_calls.push(fc);
+                        Err(e) => {
+                            println!("[ERROR] {}", e);
+                            continue;
                         }
                     }
-                    
-                    if response_text.is_empty() && function_calls.is_empty() {
-                        response_card.stream_content("No response received")?;
-                    }
-                    
-                    // Complete the response box
-                    response_card.end_streaming()?;
-                    
-                    // Add model response to conversation history
-                    let function_call_json = if function_calls.len() == 1 {
-                        Some(function_calls[0].clone())
-                    } else if function_calls.len() > 1 {
-                        Some(serde_json::json!(function_calls))
-                    } else {
-                        None
-                    };
-                    client.add_model_response(&response_text, function_call_json.clone());
-                    
-                    // Handle function calls
-                    for fc in function_calls {
-                        if let Ok(function_call) = serde_json::from_value::<function_calling::FunctionCall>(fc) {
-                            println!("\n🔧 Executing function: {}", function_call.name);
-                            match function_executor.execute_function(&function_call).await {
-                                Ok(function_response) => {
-                                    let result_card = ResponseCard::with_title("Function Result");
-                                    if let Some(output) = function_response.response.get("output") {
-                                        result_card.display_complete(&output.as_str().unwrap_or("No output"))?;
-                                    } else {
-                                        result_card.display_complete(&serde_json::to_string_pretty(&function_response.response)?)?;
-                                    }
-                                    
-                                    // Add function response to conversation history
-                                    client.add_function_response(&function_response);
-                                    
-                                    // CRITICAL: Continue conversation with function result - send back to LLM
-                                    println!("\n[LLM] Getting LLM response to function result...");
-                                    match client.send_message_stream("").await {
-                                        Ok(mut follow_up_rx) => {
-                                            let follow_up_card = ResponseCard::with_title("LLM Response");
-                                            follow_up_card.start_streaming()?;
-                                            
-                                            let mut follow_up_response = String::new();
-                                            
-                                            while let Some((text_chunk, _function_call)) = follow_up_rx.recv().await {
-                                                if !text_chunk.is_empty() {
-                                                    follow_up_card.stream_content(&text_chunk)?;
-                                                    follow_up_response.push_str(&text_chunk);
-                                                }
-                                            }
-                                            
-                                            if follow_up_response.is_empty() {
-                                                follow_up_card.stream_content("No response received")?;
-                                            }
-                                            
-                                            follow_up_card.end_streaming()?;
-                                            
-                                            // Add the follow-up response to conversation history
-                                            client.add_model_response(&follow_up_response, None);
-                                        }
-                                        Err(e) => {
-                                            println!("\n[ERROR] Failed to get LLM response: {}", e);
-                                        }
-                                    }
-                                }
-                                Err(e) => {
-                                    let error_card = ResponseCard::with_title("Function Error");
-                                    error_card.display_complete(&format!("Failed to execute function: {}", e))?;
-                                }
-                            }
+                }
+                println!(
+                    "Context: ~{} of {} tokens ({} messages); strategy: {}",
+                    usage::format_tokens(context::estimate_conversation_tokens(&client.conversation()) as u64),
+                    manager.limit_for(&client).map_or("an unknown number of".to_string(), |limit| usage::format_tokens(limit as u64)),
+                    client.conversation().len(),
+                    manager.strategy().as_str()
+                );
+                continue;
+            }
+            "/compact" => {
+                match show_loading_in_response_box(width, context::summarize(&mut client, 0)).await {
+                    Ok(report) => print_compaction(&report),
+                    Err(e) => println!("[ERROR] Failed to compact the conversation: {}", e),
+                }
+                continue;
+            }
+            "/cost" => {
+                print_cost(&session, &store, &prices);
+                continue;
+            }
+            "/save" => {
+                session.update_from(&client);
+                match store.save(&session) {
+                    Ok(path) => println!("Session {} saved to {}", session.id, path.display()),
+                    Err(e) => println!("[ERROR] Failed to save session: {}", e),
+                }
+                continue;
+            }
+            "/sessions" => {
+                match store.list() {
+                    Ok(sessions) if sessions.is_empty() => println!("No saved sessions in {}", store.dir().display()),
+                    Ok(sessions) => {
+                        for saved in sessions {
+                            let marker = if saved.id == session.id { "*" } else { " " };
+                            println!(
+                                "{} {}  {}  {:>3} msgs  {}/{}  {}",
+                                marker,
+                                saved.id,
+                                saved.updated_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+                                saved.conversation.len(),
+                                saved.provider,
+                                saved.model,
+                                saved.title
+                            );
                         }
                     }
+                    Err(e) => println!("[ERROR] Failed to list sessions: {}", e),
                 }
-                Err(e) => {
-                    loading_handle.stop().await;
-                    // Clear interrupt hint lines
-                    execute!(io::stdout(), cursor::MoveDown(2))?;
-                    println!("{}", " ".repeat(120)); // Clear the interrupt hint line
-                    execute!(io::stdout(), cursor::MoveUp(3), cursor::MoveToColumn(3))?;
-                    
-                    response_card.stream_content(&format!("Error: Failed to get response: {}", e))?;
-                    response_card.end_streaming()?;
+                continue;
+            }
+            "/resume" => {
+                if argument.is_empty() {
+                    println!("Usage: /resume <id>  (see /sessions)");
+                    continue;
+                }
+                match store.load(argument) {
+                    Ok(saved) => {
+                        saved.restore_into(&mut client);
+                        println!("Resumed session {} ({} messages): {}", saved.id, saved.conversation.len(), saved.title);
+                        session = saved;
+                    }
+                    Err(e) => println!("[ERROR] {}", e),
+                }
+                continue;
+            }
+            "/delete" => {
+                if argument.is_empty() {
+                    println!("Usage: /delete <id>  (see /sessions)");
+                    continue;
+                }
+                match store.delete(argument) {
+                    Ok(id) => println!("Deleted session {}", id),
+                    Err(e) => println!("[ERROR] {}", e),
+                }
+                continue;
+            }
+            _ => {}
+        }
+        
+        // Send message with loading animation
+        if streaming_mode {
+            // The agent keeps calling tools and re-querying until the model gives a final answer
+            let mut observer = ReplObserver::new(client.model(), &prices, width, markdown, agent.stop_handle());
+            turn_active.store(true, Ordering::Relaxed);
+            let turn = agent.run_turn(&mut client, input, &mut observer).await;
+            turn_active.store(false, Ordering::Relaxed);
+            session.usage.merge(&observer.usage);
+            // Errors have already been shown in the response card by the observer
+            if let Ok(result) = turn {
+                match result.stop_reason {
+                    StopReason::Completed => {}
+                    StopReason::MaxStepsReached => {
+                        println!("\n[WARN] Stopped after {} steps without a final answer. Send another message to continue.", result.steps);
+                    }
+                    StopReason::Stopped => {
+                        println!("\n[INFO] Interrupted; what the model said so far is kept in the conversation.");
+                    }
                 }
             }
         } else {
-            // Non-streaming response with boxed loading animation
-            let response_result = show_loading_in_response_box(
-                client.send_message(input)
-            ).await;
+            if let Some(manager) = agent.context() {
+                if let Some(report) = manager.fit(&mut client).await {
+                    print_compaction(&report);
+                }
+            }
+            
+            // Add user message to conversation history; the request sends the history as it is
+            client.add_user_message(input);
+            
+            // Non-streaming response with boxed loading animation; Ctrl+C abandons the request
+            let stop_handle = agent.stop_handle();
+            stop_handle.reset();
+            turn_active.store(true, Ordering::Relaxed);
+            let response_result = show_loading_in_response_box(width, async {
+                tokio::select! {
+                    response = client.send_message("") => Some(response),
+                    _ = stop_handle.stopped() => None,
+                }
+            }).await;
+            turn_active.store(false, Ordering::Relaxed);
             
             match response_result {
-                Ok(response) => {
-                    let card = ResponseCard::with_title(&format!("{} Response", client.client_name()));
+                None => println!("[INFO] Interrupted before the response arrived."),
+                Some(Ok(response)) => {
+                    let mut card = ResponseCard::with_title(&format!("{} Response", client.client_name()))
+                        .with_width(width)
+                        .with_markdown(markdown);
+                    if let Some(used) = client.last_usage() {
+                        session.usage.add(client.model(), &used);
+                        card.set_footer(&usage::usage_summary(&used, prices.cost(client.model(), &used)));
+                    }
                     card.display_complete(&response)?;
                     
                     // Add model response to conversation history (non-streaming doesn't support function calls yet)
-                    client.add_model_response(&response, None);
+                    client.add_model_response(&response, &[]);
                 }
-                Err(e) => {
-                    let error_card = ResponseCard::with_title("Error");
+                Some(Err(e)) => {
+                    let error_card = ResponseCard::with_title("Error").with_width(width);
                     error_card.display_complete(&format!("Failed to get response: {}", e))?;
                 }
             }
         }
         
+        session.update_from(&client);
+        if let Err(e) = store.save(&session) {
+            println!("[WARN] Failed to save session: {}", e);
+        }
+        
         println!(); // Extra line for spacing
     }
     
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Send one message, write the answer to stdout in `format` and return the exit status.
+/// Tool calls that need approval are denied unless the policy allows them or --yolo is given.
+async fn run_one_shot(agent: &Agent, client: &mut AnyChatClient, message: &str, format: OutputFormat) -> i32 {
+    let stop_handle = agent.stop_handle();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            stop_handle.stop();
+        }
+    });
+    
+    let (result, stream_failed) = match format {
+        OutputFormat::Text => {
+            let mut observer = PlainObserver::new(io::stdout());
+            let result = agent.run_turn(client, message, &mut observer).await;
+            (result, observer.stream_failed)
+        }
+        OutputFormat::Json | OutputFormat::StreamJson => {
+            let mut observer = JsonObserver::new(io::stdout(), format == OutputFormat::StreamJson);
+            let result = agent.run_turn(client, message, &mut observer).await;
+            if let Err(e) = observer.finish(&result) {
+                eprintln!("Error: Failed to write the result: {}", e);
+            }
+            (result, observer.stream_failed())
+        }
+    };
+    match &result {
+        Err(e) => eprintln!("Error: {}", e),
+        Ok(turn) if turn.stop_reason == StopReason::MaxStepsReached => {
+            eprintln!("[WARN] Stopped after {} steps without a final answer", turn.steps);
+        }
+        Ok(_) => {}
+    }
+    one_shot::exit_code(&result, stream_failed)
+}
+
+/// Tell the user how the conversation was shortened
+fn print_compaction(report: &CompactionReport) {
+    println!("[INFO] {}", report);
+}
+
+/// Print the session's usage per model and the month's total over all saved sessions
+fn print_cost(session: &Session, store: &SessionStore, prices: &PriceTable) {
+    let line = |label: &str, used: &usage::ModelUsage, cost: Option<f64>| {
+        println!(
+            "  {:<28} {:>4} req  {:>12} in  {:>10} out  {}",
+            label,
+            used.requests,
+            usage::format_tokens(used.prompt_tokens),
+            usage::format_tokens(used.completion_tokens),
+            cost.map_or("no price".to_string(), usage::format_cost)
+        );
+    };
+
+    if session.usage.is_empty() {
+        println!("No tokens used in this session yet");
+    } else {
+        println!("This session:");
+        for (model, used) in &session.usage.models {
+            line(model, used, session.usage.model_cost(model, prices));
+        }
+        let (cost, unpriced) = session.usage.cost(prices);
+        line("total", &session.usage.total(), Some(cost));
+        if !unpriced.is_empty() {
+            println!("  No price for {}; add it to {}", unpriced.join(", "), usage::PROJECT_PRICE_FILE);
+        }
+    }
+
+    // Saved sessions last updated this month, with this one as it is now
+    let month_start = chrono::Local::now()
+        .date_naive()
+        .with_day(1)
+        .and_then(|day| day.and_hms_opt(0, 0, 0))
+        .and_then(|start| start.and_local_timezone(chrono::Local).earliest())
+        .map(|start| start.with_timezone(&chrono::Utc));
+    let Some(month_start) = month_start else { return };
+    let mut month = match store.usage_since(month_start, &session.id) {
+        Ok(month) => month,
+        Err(e) => {
+            println!("[WARN] Failed to read saved sessions: {}", e);
+            SessionUsage::new()
+        }
+    };
+    month.merge(&session.usage);
+    if !month.is_empty() {
+        let (cost, unpriced) = month.cost(prices);
+        println!("This month:");
+        line("total", &month.total(), Some(cost));
+        if !unpriced.is_empty() {
+            println!("  No price for {}; add it to {}", unpriced.join(", "), usage::PROJECT_PRICE_FILE);
+        }
+    }
+}
+
+/// Pull the Ollama model if the server does not have it yet, with a progress bar.
+/// Other providers need nothing; failures are reported and the chat carries on.
+async fn ensure_local_model(client: &AnyChatClient) {
+    let AnyChatClient::Ollama(ollama) = client else { return };
+    let model = ChatClient::model(ollama).to_string();
+    match ollama.has_model().await {
+        Ok(true) => return,
+        Ok(false) => {}
+        Err(e) => {
+            println!("[WARN] {}", e);
+            return;
+        }
+    }
+
+    let mut steps = ProgressSteps::new(vec![format!("Pull {}", model)]);
+    steps.start_step(0).ok();
+    let result = ollama.pull_model(|progress| {
+        if let (Some(completed), Some(total)) = (progress.completed, progress.total) {
+            steps.update_progress(completed, total).ok();
+        }
+    }).await;
+    println!();
+    match result {
+        Ok(()) => steps.complete_step(0).ok(),
+        Err(e) => steps.fail_step(0, &e.to_string()).ok(),
+    };
+}
+
+/// Draws each agent step as a streaming response card, with tool results in their own cards
+struct ReplObserver<'a> {
+    card: ResponseCard,
+    model: String,
+    prices: &'a PriceTable,
+    /// Tokens used by the turn's requests
+    usage: SessionUsage,
+    loading: Option<LoadingHandle>,
+    /// Card showing a running tool's output as it is printed
+    live_output: Option<ResponseCard>,
+    /// Width of every card drawn
+    width: usize,
+    /// Render answers as Markdown
+    markdown: bool,
+    /// Cancels a permission prompt when the turn is interrupted
+    stop_handle: agent::StopHandle,
+}
+
+impl<'a> ReplObserver<'a> {
+    fn new(model: &str, prices: &'a PriceTable, width: usize, markdown: bool, stop_handle: agent::StopHandle) -> Self {
+        Self {
+            card: ResponseCard::with_title("Response").with_width(width).with_markdown(markdown),
+            model: model.to_string(),
+            prices,
+            usage: SessionUsage::new(),
+            loading: None,
+            live_output: None,
+            width,
+            markdown,
+            stop_handle,
+        }
+    }
+
+    /// Count a request's tokens and show them under its response
+    fn record_usage(&mut self, used: &TokenUsage) {
+        self.usage.add(&self.model, used);
+        self.card.set_footer(&usage::usage_summary(used, self.prices.cost(&self.model, used)));
+    }
+
+    /// Stop the connection spinner and clear the interrupt hint below it
+    async fn stop_loading(&mut self) -> Result<()> {
+        if let Some(loading) = self.loading.take() {
+            loading.stop().await;
+            
+            // Clear the loading line and interrupt hint, start streaming content
+            print!("\r│ ");
+            io::stdout().flush()?;
+            execute!(io::stdout(), cursor::MoveDown(2))?;
+            println!("{}", " ".repeat(self.width)); // Clear the interrupt hint line
+            execute!(io::stdout(), cursor::MoveUp(3), cursor::MoveToColumn(3))?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AgentObserver for ReplObserver<'_> {
+    async fn on_step_start(&mut self, step: usize) -> Result<()> {
+        if step > 1 {
+            println!("\n[LLM] Getting LLM response to function results...");
+        }
+        self.card = ResponseCard::with_title(if step == 1 { "Response" } else { "LLM Response" })
+            .with_width(self.width)
+            .with_markdown(self.markdown);
+        
+        // Show initial loading in response box with interrupt hint
+        self.card.start_streaming()?;
+        println!();
+        println!();
+        println!(" ctrl+c to interrupt");
+        
+        // Move cursor back up to spinner position
+        execute!(io::stdout(), cursor::MoveUp(3), cursor::MoveToColumn(3))?;
+        
+        // Start spinner for connection
+        let loading = LoadingAnimation::new("Connecting...")
+            .with_style(AnimationStyle::Spinner);
+        self.loading = Some(loading.start());
+        Ok(())
+    }
+
+    async fn on_stream_event(&mut self, event: &StreamEvent) -> Result<()> {
+        // Keep the loading animation up while the model is still reasoning
+        if let StreamEvent::ReasoningDelta(_) = event {
+            return Ok(());
+        }
+        self.stop_loading().await?;
+        match event {
+            StreamEvent::TextDelta(text_chunk) => self.card.stream_content(text_chunk)?,
+            StreamEvent::Usage(used) => self.record_usage(used),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    async fn on_step_end(&mut self, step: &AgentStep) -> Result<()> {
+        self.stop_loading().await?;
+        if step.interrupted {
+            self.card.set_footer("interrupted");
+        } else if step.text.is_empty() && step.tool_calls.is_empty() && step.errors.is_empty() {
+            self.card.stream_content("No response received")?;
+        }
+        
+        // Complete the response box
+        self.card.end_streaming()?;
+        
+        // Stream failures are shown separately and never saved as model output
+        if !step.errors.is_empty() {
+            let error_card = ResponseCard::with_title("Stream Error").with_width(self.width);
+            error_card.display_complete(&step.errors.join("\n"))?;
+        }
+        Ok(())
+    }
+
+    async fn on_context_compacted(&mut self, report: &CompactionReport) -> Result<()> {
+        print_compaction(report);
+        Ok(())
+    }
+
+    async fn on_step_failed(&mut self, error: &anyhow::Error) -> Result<()> {
+        self.stop_loading().await?;
+        self.card.stream_content(&format!("Error: Failed to get response: {}", error))?;
+        self.card.end_streaming()?;
+        Ok(())
+    }
+
+    async fn on_permission_request(&mut self, call: &FunctionCall) -> Result<PermissionDecision> {
+        let card = ResponseCard::with_title("Permission Required").with_width(self.width);
+        card.display_complete(&format!("{} wants to run:\n{}", call.name, permissions::approval_subject(call)))?;
+        
+        loop {
+            print!("Allow? [y]es / [n]o / [a]lways allow: ");
+            io::stdout().flush()?;
+            // Read on a blocking thread so Ctrl+C can still stop the turn; an interrupted read
+            // keeps waiting and takes the next line typed
+            let read = tokio::task::spawn_blocking(|| {
+                let mut answer = String::new();
+                io::stdin().read_line(&mut answer).map(|bytes| (bytes, answer))
+            });
+            let (bytes, answer) = tokio::select! {
+                result = read => result??,
+                _ = self.stop_handle.stopped() => {
+                    println!("\n[INFO] Permission prompt cancelled; press Enter to continue.");
+                    return Ok(PermissionDecision::Deny);
+                }
+            };
+            if bytes == 0 {
+                return Ok(PermissionDecision::Deny);
+            }
+            match answer.trim().to_lowercase().as_str() {
+                "y" | "yes" => return Ok(PermissionDecision::Allow),
+                "n" | "no" => return Ok(PermissionDecision::Deny),
+                "a" | "always" => return Ok(PermissionDecision::AlwaysAllow),
+                _ => continue,
+            }
+        }
+    }
+
+    async fn on_tool_denied(&mut self, call: &FunctionCall, reason: &str) -> Result<()> {
+        println!("\n⛔ Not running {}: {}", call.name, reason);
+        Ok(())
+    }
+
+    async fn on_tool_call(&mut self, call: &FunctionCall) -> Result<()> {
+        println!("\n🔧 Executing function: {} ({})", call.name, permissions::approval_subject(call));
+        Ok(())
+    }
+
+    async fn on_tool_output(&mut self, _call: &FunctionCall, output: &ToolOutput) -> Result<()> {
+        let line = match output {
+            ToolOutput::Stdout(line) | ToolOutput::Stderr(line) => line,
+        };
+        match &mut self.live_output {
+            Some(card) => card.stream_content(&format!("\n{}", line))?,
+            None => {
+                let mut card = ResponseCard::with_title("Output").with_width(self.width);
+                card.start_streaming()?;
+                card.stream_content(line)?;
+                self.live_output = Some(card);
+            }
+        }
+        Ok(())
+    }
+
+    async fn on_tool_result(&mut self, _call: &FunctionCall, result: &Result<FunctionResponse>) -> Result<()> {
+        let streamed = match self.live_output.take() {
+            Some(mut card) => {
+                card.end_streaming()?;
+                true
+            }
+            None => false,
+        };
+        
+        match result {
+            Ok(function_response) => {
+                let result_card = ResponseCard::with_title("Function Result").with_width(self.width);
+                let output = function_response.response.get("output").and_then(|o| o.as_str());
+                if let (true, Some(output)) = (streamed, output) {
+                    // The output was just shown live; only repeat the status line
+                    result_card.display_complete(output.lines().next().unwrap_or_default())?;
+                } else if let Some(output) = function_response.response.get("output") {
+                    result_card.display_complete(output.as_str().unwrap_or("No output"))?;
+                } else if let Some(diff) = function_response.response.get("diff").and_then(|d| d.as_str()) {
+                    result_card.display_complete(diff)?;
+                } else {
+                    result_card.display_complete(&serde_json::to_string_pretty(&function_response.response)?)?;
+                }
+            }
+            Err(e) => {
+                let error_card = ResponseCard::with_title("Function Error").with_width(self.width);
+                error_card.display_complete(&format!("Failed to execute function: {}", e))?;
+            }
+        }
+        Ok(())
+    }
+}
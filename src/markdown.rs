@@ -0,0 +1,650 @@
+use crossterm::style::{Attribute, Color, SetAttribute, SetForegroundColor};
+
+/// Renders Markdown to styled terminal text as it streams in.
+///
+/// Text is written as soon as its meaning is known: the start of a line is held back
+/// only until its kind (heading, list item, quote, fence...) is clear, and emphasis
+/// markers only until the next character. Tables are held until their last row,
+/// since their columns cannot be sized before that. Every output line ends with its
+/// styles reset, so a card's border is never coloured.
+pub struct MarkdownRenderer {
+    /// Columns available, for rules and code block frames
+    width: usize,
+    /// The current line's text that is not rendered yet
+    line: String,
+    /// How the rest of the current line renders, once known
+    kind: Option<LineKind>,
+    /// Marker of the code fence we are in, e.g. "```"
+    fence: Option<String>,
+    /// Rows of the table being read
+    table: Vec<String>,
+    inline: Inline,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LineKind {
+    /// Headings, list items, quotes and paragraphs, with inline Markdown
+    Text,
+    /// A line of a code block, written as is
+    Code,
+    /// A table row, rendered with the rest of its table
+    TableRow,
+    /// A line already rendered whole, such as a fence or a rule
+    Done,
+}
+
+/// What the start of a line turned out to be
+enum LineStart {
+    /// Write `prefix`, then the line from byte `skip` on as inline text in `style`
+    Text { skip: usize, prefix: String, style: String },
+    Code,
+    TableRow,
+    Blank,
+    Rule,
+    /// A fence opening a code block in `language`, or closing the one we are in
+    Fence { marker: String, language: String },
+}
+
+impl MarkdownRenderer {
+    pub fn new(width: usize) -> Self {
+        Self {
+            width,
+            line: String::new(),
+            kind: None,
+            fence: None,
+            table: Vec::new(),
+            inline: Inline::default(),
+        }
+    }
+
+    /// Render a complete document
+    pub fn render(mut self, markdown: &str) -> String {
+        let mut out = self.push(markdown);
+        out.push_str(&self.finish());
+        out
+    }
+
+    /// Render the next streamed chunk, returning the text that can be shown so far
+    pub fn push(&mut self, chunk: &str) -> String {
+        let mut out = String::new();
+        for c in chunk.chars() {
+            if c == '\n' {
+                self.end_line(&mut out);
+                continue;
+            }
+            match self.kind {
+                Some(LineKind::Text) => self.inline.push(c, &mut out),
+                Some(LineKind::Code) => out.push(c),
+                Some(LineKind::TableRow) | Some(LineKind::Done) => self.line.push(c),
+                None => {
+                    self.line.push(c);
+                    self.start_line(false, &mut out);
+                }
+            }
+        }
+        out
+    }
+
+    /// Render whatever is held back at the end of the stream, closing any open code block
+    pub fn finish(&mut self) -> String {
+        let mut out = String::new();
+        if !self.line.is_empty() || self.kind.is_some() {
+            self.end_line(&mut out);
+        }
+        self.flush_table(&mut out);
+        if self.fence.take().is_some() {
+            out.push_str(&self.frame('└', ""));
+            out.push('\n');
+        }
+        if out.ends_with('\n') {
+            out.pop();
+        }
+        out
+    }
+
+    /// Decide what the current line is, if its start says enough, and render that start
+    fn start_line(&mut self, complete: bool, out: &mut String) {
+        let Some(start) = self.classify(complete) else { return };
+        if !matches!(start, LineStart::TableRow) {
+            self.flush_table(out);
+        }
+        let line = std::mem::take(&mut self.line);
+        self.kind = Some(match start {
+            LineStart::Text { skip, prefix, style } => {
+                out.push_str(&prefix);
+                self.inline.begin_line(style, out);
+                for c in line[skip..].chars() {
+                    self.inline.push(c, out);
+                }
+                LineKind::Text
+            }
+            LineStart::Code => {
+                out.push_str(&format!("{}│{} ", SetForegroundColor(Color::DarkGrey), SetAttribute(Attribute::Reset)));
+                out.push_str(&line);
+                LineKind::Code
+            }
+            LineStart::TableRow => {
+                self.line = line;
+                LineKind::TableRow
+            }
+            LineStart::Blank => {
+                out.push('\n');
+                LineKind::Done
+            }
+            LineStart::Rule => {
+                out.push_str(&format!(
+                    "{}{}{}\n",
+                    SetForegroundColor(Color::DarkGrey),
+                    "─".repeat(self.width),
+                    SetAttribute(Attribute::Reset)
+                ));
+                LineKind::Done
+            }
+            LineStart::Fence { marker, language } => {
+                if self.fence.take().is_some() {
+                    out.push_str(&self.frame('└', ""));
+                } else {
+                    out.push_str(&self.frame('┌', &language));
+                    self.fence = Some(marker);
+                }
+                out.push('\n');
+                LineKind::Done
+            }
+        });
+    }
+
+    fn end_line(&mut self, out: &mut String) {
+        if self.kind.is_none() {
+            self.start_line(true, out);
+        }
+        match self.kind.take() {
+            Some(LineKind::Text) => {
+                self.inline.end_line(out);
+                out.push('\n');
+            }
+            Some(LineKind::Code) => out.push('\n'),
+            Some(LineKind::TableRow) => self.table.push(std::mem::take(&mut self.line)),
+            Some(LineKind::Done) | None => {}
+        }
+        self.line.clear();
+    }
+
+    /// The kind of the current line, or `None` while more of it is needed to tell
+    fn classify(&self, complete: bool) -> Option<LineStart> {
+        let line = self.line.as_str();
+        let rest = line.trim_start();
+        let indent = line.len() - rest.len();
+
+        if let Some(fence) = &self.fence {
+            let closing = rest.trim_end();
+            return if closing.starts_with(fence.as_str()) && closing.chars().all(|c| fence.starts_with(c)) {
+                complete.then(|| LineStart::Fence { marker: fence.clone(), language: String::new() })
+            } else if !complete && fence.starts_with(rest) {
+                None
+            } else {
+                Some(LineStart::Code)
+            };
+        }
+        if rest.is_empty() {
+            return complete.then_some(LineStart::Blank);
+        }
+        if rest.starts_with('|') {
+            return Some(LineStart::TableRow);
+        }
+        for fence in ["```", "~~~"] {
+            if rest.starts_with(fence) {
+                let marker: String = rest.chars().take_while(|&c| fence.starts_with(c)).collect();
+                return complete.then(|| LineStart::Fence {
+                    language: rest[marker.len()..].trim().to_string(),
+                    marker,
+                });
+            }
+            if !complete && fence.starts_with(rest) {
+                return None;
+            }
+        }
+
+        let text = |skip: usize, prefix: String, style: String| Some(LineStart::Text { skip, prefix, style });
+        let marker_style = SetForegroundColor(Color::Cyan).to_string();
+        let reset = SetAttribute(Attribute::Reset).to_string();
+        let first = rest.chars().next()?;
+        let after = rest[first.len_utf8()..].chars().next();
+        if matches!(first, '-' | '*' | '_') && rest.chars().all(|c| c == first || c == ' ') {
+            if !complete {
+                return None;
+            }
+            if rest.chars().filter(|&c| c == first).count() >= 3 {
+                return Some(LineStart::Rule);
+            }
+        }
+        match first {
+            '#' => {
+                let level = rest.chars().take_while(|&c| c == '#').count();
+                match rest[level..].chars().next() {
+                    None if !complete => None,
+                    Some(' ') | None if level <= 6 => {
+                        let mut style = format!("{}{}", SetForegroundColor(Color::Cyan), SetAttribute(Attribute::Bold));
+                        if level == 1 {
+                            style.push_str(&SetAttribute(Attribute::Underlined).to_string());
+                        }
+                        text((indent + level + 1).min(line.len()), String::new(), style)
+                    }
+                    _ => text(0, String::new(), String::new()),
+                }
+            }
+            '-' | '*' | '+' => match after {
+                None if !complete => None,
+                Some(' ') => text(indent + 2, format!("{}{}•{} ", " ".repeat(indent), marker_style, reset), String::new()),
+                _ => text(0, String::new(), String::new()),
+            },
+            '>' => match after {
+                None if !complete => None,
+                _ => {
+                    let skip = indent + 1 + usize::from(after == Some(' '));
+                    let prefix = format!("{}{}▎{} ", " ".repeat(indent), SetForegroundColor(Color::DarkGrey), reset);
+                    text(skip, prefix, format!("{}{}", SetForegroundColor(Color::DarkGrey), SetAttribute(Attribute::Italic)))
+                }
+            },
+            '0'..='9' => {
+                let digits = rest.chars().take_while(char::is_ascii_digit).count();
+                let mut tail = rest[digits..].chars();
+                match (tail.next(), tail.next()) {
+                    (None, _) | (Some('.' | ')'), None) if !complete => None,
+                    (Some(delimiter @ ('.' | ')')), Some(' ')) => {
+                        let number = &rest[..digits];
+                        let prefix = format!("{}{}{}{}{} ", " ".repeat(indent), marker_style, number, delimiter, reset);
+                        text(indent + digits + 2, prefix, String::new())
+                    }
+                    _ => text(0, String::new(), String::new()),
+                }
+            }
+            _ => text(0, String::new(), String::new()),
+        }
+    }
+
+    /// Render the table read so far, if any
+    fn flush_table(&mut self, out: &mut String) {
+        if self.table.is_empty() {
+            return;
+        }
+        let mut rows: Vec<Vec<String>> = self.table.drain(..).map(|row| split_row(&row)).collect();
+        let has_header = rows.get(1).is_some_and(|row| row.iter().all(|cell| is_delimiter_cell(cell)));
+        if has_header {
+            rows.remove(1);
+        }
+        let rendered: Vec<Vec<String>> = rows
+            .iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let style = if has_header && i == 0 { SetAttribute(Attribute::Bold).to_string() } else { String::new() };
+                row.iter().map(|cell| render_inline(cell, &style)).collect()
+            })
+            .collect();
+        let columns = rendered.iter().map(Vec::len).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns)
+            .map(|column| rendered.iter().filter_map(|row| row.get(column)).map(|cell| visible_width(cell)).max().unwrap_or(0))
+            .collect();
+
+        let grey = SetForegroundColor(Color::DarkGrey);
+        let reset = SetAttribute(Attribute::Reset);
+        for (i, row) in rendered.iter().enumerate() {
+            let cells: Vec<String> = widths
+                .iter()
+                .enumerate()
+                .map(|(column, width)| {
+                    let cell = row.get(column).map(String::as_str).unwrap_or("");
+                    format!("{}{}", cell, " ".repeat(width - visible_width(cell)))
+                })
+                .collect();
+            out.push_str(&cells.join(&format!(" {}│{} ", grey, reset)));
+            out.push('\n');
+            if has_header && i == 0 {
+                let rule: Vec<String> = widths.iter().map(|width| "─".repeat(*width)).collect();
+                out.push_str(&format!("{}{}{}\n", grey, rule.join("─┼─"), reset));
+            }
+        }
+    }
+
+    /// A code block's top or bottom border, with the language set into the top one
+    fn frame(&self, corner: char, language: &str) -> String {
+        let label = if language.is_empty() { String::new() } else { format!(" {} ", language) };
+        let fill = self.width.saturating_sub(2 + label.chars().count());
+        format!(
+            "{}{}─{}{}{}",
+            SetForegroundColor(Color::DarkGrey),
+            corner,
+            label,
+            "─".repeat(fill),
+            SetAttribute(Attribute::Reset)
+        )
+    }
+}
+
+/// Emphasis, code spans and links within one line
+#[derive(Default)]
+struct Inline {
+    /// Style of the whole line, e.g. a heading's, restored after each inline style
+    base: String,
+    bold: bool,
+    italic: bool,
+    strike: bool,
+    code: bool,
+    /// Marker characters whose meaning depends on the next one: `*`, `_`, `~` or `\`
+    held: String,
+    link: Option<Link>,
+    /// The last character written, to tell opening emphasis from closing
+    last: Option<char>,
+}
+
+#[derive(Default)]
+struct Link {
+    text: String,
+    /// The text's `]` has been read
+    closed: bool,
+    /// Read after `](`
+    url: Option<String>,
+}
+
+impl Inline {
+    fn begin_line(&mut self, base: String, out: &mut String) {
+        *self = Self { base, ..Self::default() };
+        out.push_str(&self.base);
+    }
+
+    fn push(&mut self, c: char, out: &mut String) {
+        if self.link.is_some() {
+            self.push_link(c, out);
+            return;
+        }
+        if self.code {
+            if c == '`' {
+                self.code = false;
+                self.restyle(out);
+            } else {
+                self.write(c, out);
+            }
+            return;
+        }
+        if let Some(marker) = self.held.chars().next() {
+            if marker == '\\' {
+                self.held.clear();
+                if !c.is_ascii_punctuation() {
+                    self.write('\\', out);
+                }
+                self.write(c, out);
+                return;
+            }
+            if c == marker && self.held.len() < 3 {
+                self.held.push(c);
+                return;
+            }
+            self.resolve_held(Some(c), out);
+        }
+        match c {
+            '*' | '_' | '~' | '\\' => self.held.push(c),
+            '`' => {
+                self.code = true;
+                self.restyle(out);
+            }
+            '[' => self.link = Some(Link::default()),
+            _ => self.write(c, out),
+        }
+    }
+
+    /// Write what is still held at the end of the line and reset its styles
+    fn end_line(&mut self, out: &mut String) {
+        if let Some(link) = self.link.take() {
+            self.write_unfinished_link(link, out);
+        }
+        self.resolve_held(None, out);
+        out.push_str(&SetAttribute(Attribute::Reset).to_string());
+        *self = Self::default();
+    }
+
+    /// Read `[text](url)`, writing it once the `)` arrives
+    fn push_link(&mut self, c: char, out: &mut String) {
+        let Some(link) = self.link.as_mut() else { return };
+        if let Some(url) = link.url.as_mut() {
+            if c != ')' {
+                url.push(c);
+                return;
+            }
+        } else if !link.closed {
+            match c {
+                ']' => link.closed = true,
+                _ => link.text.push(c),
+            }
+            return;
+        } else if c == '(' {
+            link.url = Some(String::new());
+            return;
+        }
+        let link = self.link.take().unwrap_or_default();
+        match &link.url {
+            Some(url) => self.write_link(&link.text, url, out),
+            None => {
+                self.write_unfinished_link(link, out);
+                self.push(c, out);
+            }
+        }
+    }
+
+    fn write_link(&mut self, text: &str, url: &str, out: &mut String) {
+        out.push_str(&format!("{}{}", SetForegroundColor(Color::Blue), SetAttribute(Attribute::Underlined)));
+        for c in text.chars() {
+            self.write(c, out);
+        }
+        self.restyle(out);
+        if url != text {
+            out.push_str(&format!("{} ({}){}", SetForegroundColor(Color::DarkGrey), url, SetAttribute(Attribute::Reset)));
+            self.restyle(out);
+        }
+    }
+
+    /// Write a link that never completed as the text it was
+    fn write_unfinished_link(&mut self, link: Link, out: &mut String) {
+        let mut raw = format!("[{}", link.text);
+        if link.closed {
+            raw.push(']');
+        }
+        if let Some(url) = link.url {
+            raw.push_str(&format!("({}", url));
+        }
+        for c in raw.chars() {
+            self.write(c, out);
+        }
+    }
+
+    /// Turn held markers into a style change, or into text when they cannot open or close one
+    fn resolve_held(&mut self, next: Option<char>, out: &mut String) {
+        let held = std::mem::take(&mut self.held);
+        let Some(marker) = held.chars().next() else { return };
+        let count = held.chars().count();
+        let after_text = self.last.is_some_and(|c| !c.is_whitespace());
+        let before_text = next.is_some_and(|c| !c.is_whitespace());
+        let intraword = marker == '_' && self.last.is_some_and(char::is_alphanumeric) && next.is_some_and(char::is_alphanumeric);
+
+        let toggled = match (marker, count) {
+            ('~', 2) => toggle(&mut self.strike, after_text, before_text),
+            ('*' | '_', _) if intraword => false,
+            ('*' | '_', 1) => toggle(&mut self.italic, after_text, before_text),
+            ('*' | '_', 2) => toggle(&mut self.bold, after_text, before_text),
+            ('*' | '_', _) => {
+                let mut both = self.bold && self.italic;
+                let toggled = toggle(&mut both, after_text, before_text);
+                if toggled {
+                    self.bold = both;
+                    self.italic = both;
+                }
+                toggled
+            }
+            _ => false,
+        };
+        if toggled {
+            self.restyle(out);
+        } else {
+            for c in held.chars() {
+                self.write(c, out);
+            }
+        }
+    }
+
+    /// Write the escape codes for the current styles
+    fn restyle(&self, out: &mut String) {
+        out.push_str(&SetAttribute(Attribute::Reset).to_string());
+        out.push_str(&self.base);
+        if self.bold {
+            out.push_str(&SetAttribute(Attribute::Bold).to_string());
+        }
+        if self.italic {
+            out.push_str(&SetAttribute(Attribute::Italic).to_string());
+        }
+        if self.strike {
+            out.push_str(&SetAttribute(Attribute::CrossedOut).to_string());
+        }
+        if self.code {
+            out.push_str(&SetForegroundColor(Color::Yellow).to_string());
+        }
+    }
+
+    fn write(&mut self, c: char, out: &mut String) {
+        out.push(c);
+        self.last = Some(c);
+    }
+}
+
+/// Close an open style after text, or open a closed one before text
+fn toggle(style: &mut bool, after_text: bool, before_text: bool) -> bool {
+    if (*style && after_text) || (!*style && before_text) {
+        *style = !*style;
+        true
+    } else {
+        false
+    }
+}
+
+/// Inline Markdown of a single line, such as a table cell
+fn render_inline(text: &str, base: &str) -> String {
+    let mut inline = Inline::default();
+    let mut out = String::new();
+    inline.begin_line(base.to_string(), &mut out);
+    for c in text.chars() {
+        inline.push(c, &mut out);
+    }
+    inline.end_line(&mut out);
+    out
+}
+
+fn split_row(row: &str) -> Vec<String> {
+    let row = row.trim();
+    let row = row.strip_prefix('|').unwrap_or(row);
+    let row = row.strip_suffix('|').unwrap_or(row);
+    row.split('|').map(|cell| cell.trim().to_string()).collect()
+}
+
+/// A cell of the row between a table's header and body, like `---` or `:-:`
+fn is_delimiter_cell(cell: &str) -> bool {
+    let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+    !dashes.is_empty() && dashes.chars().all(|c| c == '-')
+}
+
+/// `text` without its terminal escape codes
+pub fn strip_styles(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            if chars.next() == Some('[') {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+/// The escape codes still in effect at the end of `text`, to carry its styles onto another line
+pub fn open_styles(text: &str) -> String {
+    let reset = SetAttribute(Attribute::Reset).to_string();
+    let tail = text.rsplit_once(&reset).map_or(text, |(_, tail)| tail);
+    let mut codes = String::new();
+    let mut chars = tail.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            codes.push(c);
+            for c in chars.by_ref() {
+                codes.push(c);
+                if ('@'..='~').contains(&c) && c != '[' {
+                    break;
+                }
+            }
+        }
+    }
+    codes
+}
+
+/// Columns `text` takes up on screen
+pub fn visible_width(text: &str) -> usize {
+    strip_styles(text).chars().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ANSWER: &str = "# Plan\n\nSome **bold**, *italic* and `code` with a [link](https://example.com).\n\n- first\n2. second\n> quoted\n\n```rust\nfn main() {}\n```\n\n| Name | Size |\n|------|-----:|\n| a | 10 |\n| long name | 2 |\n\nsnake_case stays 2 * 3\n";
+
+    #[test]
+    fn test_markers_become_styles() {
+        let rendered = MarkdownRenderer::new(20).render(ANSWER);
+        let lines: Vec<String> = strip_styles(&rendered).lines().map(str::to_string).collect();
+
+        assert_eq!(lines[0], "Plan");
+        assert_eq!(lines[2], "Some bold, italic and code with a link (https://example.com).");
+        assert_eq!(lines[4], "• first");
+        assert_eq!(lines[5], "2. second");
+        assert_eq!(lines[6], "▎ quoted");
+        assert_eq!(lines[8], "┌─ rust ────────────");
+        assert_eq!(lines[9], "│ fn main() {}");
+        assert_eq!(lines[10], "└───────────────────");
+        assert_eq!(lines[12], "Name      │ Size");
+        assert_eq!(lines[13], "──────────┼─────");
+        assert_eq!(lines[14], "a         │ 10  ");
+        assert_eq!(lines[15], "long name │ 2   ");
+        assert_eq!(lines[17], "snake_case stays 2 * 3");
+        assert!(rendered.contains(&format!("{}Plan", SetAttribute(Attribute::Underlined))));
+        assert!(rendered.contains(&format!("{}bold", SetAttribute(Attribute::Bold))));
+    }
+
+    #[test]
+    fn test_streaming_in_any_chunks_matches_whole_render() {
+        let whole = MarkdownRenderer::new(20).render(ANSWER);
+
+        for size in [1, 2, 3, 7] {
+            let mut renderer = MarkdownRenderer::new(20);
+            let chars: Vec<char> = ANSWER.chars().collect();
+            let mut streamed: String = chars
+                .chunks(size)
+                .map(|chunk| renderer.push(&chunk.iter().collect::<String>()))
+                .collect();
+            streamed.push_str(&renderer.finish());
+            assert_eq!(streamed, whole, "chunks of {}", size);
+        }
+    }
+
+    #[test]
+    fn test_unfinished_markup_is_shown_as_written() {
+        let mut renderer = MarkdownRenderer::new(20);
+        let mut out = renderer.push("see [the docs](http");
+        out.push_str(&renderer.finish());
+        assert_eq!(strip_styles(&out), "see [the docs](http");
+
+        let out = MarkdownRenderer::new(20).render("```\nlet a = 1;");
+        assert_eq!(strip_styles(&out), "┌───────────────────\n│ let a = 1;\n└───────────────────");
+    }
+}
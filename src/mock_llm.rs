@@ -2,32 +2,37 @@ use anyhow::Result;
 use async_trait::async_trait;
 use tokio::sync::mpsc;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-
+use crate::chat_client::{spawn_stream, StreamEvent};
+use crate::conversation::{ChatMessage, Conversation, Role};
+use crate::function_calling::{FunctionCall, FunctionResponse};
+use crate::generation::GenerationOptions;
+
+/// Mock client for tests and offline development.
+///
+/// History and the response cursor are shared between clones, so the `&self`
+/// methods of `ChatClient` still record what was sent and advance the script.
 #[derive(Clone)]
 pub struct MockLLMClient {
-    conversation_history: Vec<MockMessage>,
-    system_prompt: Option<String>,
+    conversation: Arc<Mutex<Conversation>>,
+    model: String,
+    options: GenerationOptions,
     responses: Vec<String>,
-    response_index: usize,
+    response_index: Arc<AtomicUsize>,
     streaming_enabled: bool,
     delay_ms: u64,
     function_calls: HashMap<String, serde_json::Value>,
 }
 
-#[derive(Debug, Clone)]
-struct MockMessage {
-    role: String,
-    content: String,
-    function_call: Option<serde_json::Value>,
-}
-
 impl MockLLMClient {
     /// Create a new mock LLM client with default responses
     pub fn new() -> Self {
         Self {
-            conversation_history: Vec::new(),
-            system_prompt: None,
+            conversation: Arc::new(Mutex::new(Conversation::new())),
+            model: "mock".to_string(),
+            options: GenerationOptions::default(),
             responses: vec![
                 "Hello! I'm a mock LLM for testing purposes.".to_string(),
                 "This is a simulated response from the mock LLM.".to_string(),
@@ -35,7 +40,7 @@ impl MockLLMClient {
                 "Mock response: Your request has been processed successfully.".to_string(),
                 "Testing mode: This is an automated response.".to_string(),
             ],
-            response_index: 0,
+            response_index: Arc::new(AtomicUsize::new(0)),
             streaming_enabled: true,
             delay_ms: 50, // Small delay to simulate network latency
             function_calls: HashMap::new(),
@@ -61,19 +66,20 @@ impl MockLLMClient {
         self
     }
 
-    /// Add a predefined function call response
+    /// Add a predefined function call response.
+    /// `function_call` is a `{"name", "args"}` object, or an array of them for several calls in one turn.
     pub fn add_function_call_response(&mut self, trigger_text: &str, function_call: serde_json::Value) {
         self.function_calls.insert(trigger_text.to_lowercase(), function_call);
     }
 
     /// Get the next response (cycles through available responses)
-    fn get_next_response(&mut self, message: &str) -> (String, Option<serde_json::Value>) {
+    fn get_next_response(&self, message: &str) -> (String, Vec<FunctionCall>) {
         // Check if message should trigger a function call
         let message_lower = message.to_lowercase();
         for (trigger, function_call) in &self.function_calls {
             if message_lower.contains(trigger) {
                 let response = format!("I need to call a function to help with: {}", message);
-                return (response, Some(function_call.clone()));
+                return (response, Self::to_tool_calls(function_call));
             }
         }
 
@@ -81,8 +87,8 @@ impl MockLLMClient {
         let response = if self.responses.is_empty() {
             "Mock LLM: No responses configured".to_string()
         } else {
-            let response = self.responses[self.response_index % self.responses.len()].clone();
-            self.response_index += 1;
+            let index = self.response_index.fetch_add(1, Ordering::SeqCst);
+            let response = self.responses[index % self.responses.len()].clone();
             
             // Add some context based on the message
             if message.to_lowercase().contains("test") {
@@ -94,11 +100,34 @@ impl MockLLMClient {
             }
         };
 
-        (response, None)
+        (response, Vec::new())
+    }
+
+    /// Turn a configured function call (or array of them) into calls with unique ids
+    fn to_tool_calls(function_call: &serde_json::Value) -> Vec<FunctionCall> {
+        let calls = match function_call {
+            serde_json::Value::Array(calls) => calls.clone(),
+            call => vec![call.clone()],
+        };
+        let timestamp = chrono::Utc::now().timestamp_millis();
+
+        calls
+            .iter()
+            .enumerate()
+            .map(|(i, fc)| FunctionCall {
+                id: Some(format!("mock-call-{}-{}", timestamp, i)),
+                name: fc.get("name").and_then(|v| v.as_str()).unwrap_or("unknown").to_string(),
+                args: fc.get("args").cloned().unwrap_or_else(|| serde_json::json!({})),
+            })
+            .collect()
     }
 
     /// Split response into chunks for streaming
     fn split_into_chunks(&self, text: &str) -> Vec<String> {
+        if !self.streaming_enabled {
+            return vec![text.to_string()];
+        }
+
         // Split by words to simulate realistic streaming
         let words: Vec<&str> = text.split_whitespace().collect();
         let mut chunks = Vec::new();
@@ -117,99 +146,162 @@ impl MockLLMClient {
     }
 
     pub fn load_system_prompt(&mut self, prompt_content: &str) -> Result<()> {
-        self.system_prompt = Some(prompt_content.to_string());
+        self.conversation.lock().unwrap().system_prompt = Some(prompt_content.to_string());
         Ok(())
     }
 
     pub fn add_user_message(&mut self, message: &str) {
-        self.conversation_history.push(MockMessage {
-            role: "user".to_string(),
-            content: message.to_string(),
-            function_call: None,
-        });
+        self.record(ChatMessage::user(message));
     }
 
-    pub fn add_function_response(&mut self, function_response: &crate::function_calling::FunctionResponse) {
-        self.conversation_history.push(MockMessage {
-            role: "function".to_string(),
-            content: function_response.response.to_string(),
-            function_call: None,
-        });
+    pub fn add_function_response(&mut self, function_response: &FunctionResponse) {
+        self.record(ChatMessage::tool_result(function_response));
     }
 
-    pub fn add_model_response(&mut self, response: &str, function_call: Option<serde_json::Value>) {
-        self.conversation_history.push(MockMessage {
-            role: "assistant".to_string(),
-            content: response.to_string(),
-            function_call,
-        });
+    pub fn add_model_response(&mut self, response: &str, tool_calls: &[FunctionCall]) {
+        self.record(ChatMessage::assistant(response, tool_calls).with_model(self.model()));
     }
 
     pub fn clear_conversation(&mut self) {
-        self.conversation_history.clear();
-        self.response_index = 0;
+        self.conversation.lock().unwrap().clear();
+        self.response_index.store(0, Ordering::SeqCst);
     }
 
-    pub async fn send_message(&mut self, message: &str) -> Result<String> {
-        // Add user message to history
-        self.add_user_message(message);
+    fn record(&self, message: ChatMessage) {
+        self.conversation.lock().unwrap().push(message);
+    }
+
+    pub async fn send_message(&self, message: &str) -> Result<String> {
+        // An empty message answers the history the caller has built, as in `send_message_stream`
+        let caller_managed = message.is_empty();
+        let prompt = if caller_managed {
+            self.get_conversation_history()
+                .last()
+                .filter(|m| m.role == Role::User)
+                .map(|m| m.text())
+                .unwrap_or_default()
+        } else {
+            // Add user message to history
+            self.record(ChatMessage::user(message));
+            message.to_string()
+        };
 
         // Simulate some processing delay
         tokio::time::sleep(Duration::from_millis(self.delay_ms * 2)).await;
 
         // Get response
-        let (response, function_call) = self.get_next_response(message);
+        let (response, tool_calls) = self.get_next_response(&prompt);
 
         // Add model response to history
-        self.add_model_response(&response, function_call);
+        if !caller_managed {
+            self.record(ChatMessage::assistant(&response, &tool_calls).with_model(self.model()));
+        }
 
         Ok(response)
     }
 
-    pub async fn send_message_stream(&mut self, message: &str) -> Result<mpsc::Receiver<(String, Option<serde_json::Value>)>> {
-        // Add user message to history
-        self.add_user_message(message);
+    /// Stream a response to `message`.
+    ///
+    /// An empty message continues the conversation the caller has been building with the
+    /// `add_*` methods, as the real clients do: the mock answers the last history entry and
+    /// leaves recording the response to the caller. Tool results always get a plain text answer.
+    pub async fn send_message_stream(&self, message: &str) -> Result<mpsc::Receiver<StreamEvent>> {
+        let caller_managed = message.is_empty();
+        let prompt = if caller_managed {
+            self.get_conversation_history()
+                .last()
+                .filter(|m| m.role == Role::User)
+                .map(|m| m.text())
+                .unwrap_or_default()
+        } else {
+            // Add user message to history
+            self.record(ChatMessage::user(message));
+            message.to_string()
+        };
 
-        let (response, function_call) = self.get_next_response(message);
+        let (response, tool_calls) = self.get_next_response(&prompt);
         let chunks = self.split_into_chunks(&response);
         let delay_ms = self.delay_ms;
 
         let (tx, rx) = mpsc::channel(100);
 
-        // Clone function_call for the async task
-        let function_call_for_task = function_call.clone();
+        // Clone tool_calls for the async task
+        let tool_calls_for_task = tool_calls.clone();
 
         // Spawn task to send chunks
-        tokio::spawn(async move {
+        spawn_stream(tx, move |tx| async move {
             for (i, chunk) in chunks.iter().enumerate() {
                 // Add delay between chunks
                 if i > 0 {
                     tokio::time::sleep(Duration::from_millis(delay_ms)).await;
                 }
 
-                // Send function call with the last chunk
-                let fc = if i == chunks.len() - 1 { function_call_for_task.clone() } else { None };
+                if tx.send(StreamEvent::TextDelta(chunk.clone())).await.is_err() {
+                    return; // Receiver dropped
+                }
+            }
 
-                if tx.send((chunk.clone(), fc)).await.is_err() {
-                    break; // Receiver dropped
+            // Send function call after the text, like a real provider would
+            let finish_reason = if tool_calls_for_task.is_empty() { "stop" } else { "tool_calls" };
+            for (index, fc) in tool_calls_for_task.into_iter().enumerate() {
+                let id = fc.id.unwrap_or_default();
+                let events = [
+                    StreamEvent::ToolCallStart { index, id: id.clone(), name: fc.name.clone() },
+                    StreamEvent::ToolCallComplete { index, id, name: fc.name, args: fc.args },
+                ];
+                for event in events {
+                    if tx.send(event).await.is_err() {
+                        return;
+                    }
                 }
             }
+
+            let _ = tx.send(StreamEvent::FinishReason(finish_reason.to_string())).await;
         });
 
         // Add the complete response to history
-        self.add_model_response(&response, function_call);
+        if !caller_managed {
+            self.record(ChatMessage::assistant(&response, &tool_calls).with_model(self.model()));
+        }
 
         Ok(rx)
     }
 
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// The mock answers the same way whatever the model; the name is only recorded
+    pub fn set_model(&mut self, model: &str) {
+        self.model = model.to_string();
+    }
+
+    /// A copy of the conversation; the mock shares it between clones, so it cannot lend it out
+    pub fn conversation(&self) -> Conversation {
+        self.conversation.lock().unwrap().clone()
+    }
+
+    pub fn set_conversation(&mut self, conversation: Conversation) {
+        *self.conversation.lock().unwrap() = conversation;
+    }
+
+    /// Options are recorded but do not change the scripted responses
+    pub fn generation_options(&self) -> &GenerationOptions {
+        &self.options
+    }
+
+    pub fn set_generation_options(&mut self, options: GenerationOptions) {
+        self.options = options;
+    }
+
     /// Get conversation history for debugging
-    pub fn get_conversation_history(&self) -> &[MockMessage] {
-        &self.conversation_history
+    pub fn get_conversation_history(&self) -> Vec<ChatMessage> {
+        self.conversation.lock().unwrap().messages().to_vec()
     }
 
     /// Get current system prompt
-    pub fn get_system_prompt(&self) -> Option<&String> {
-        self.system_prompt.as_ref()
+    pub fn get_system_prompt(&self) -> Option<String> {
+        self.conversation.lock().unwrap().system_prompt.clone()
     }
 }
 
@@ -229,85 +321,62 @@ impl crate::chat_client::ChatClient for MockLLMClient {
         self.add_user_message(message)
     }
 
-    fn add_function_response(&mut self, function_response: &crate::function_calling::FunctionResponse) {
+    fn add_function_response(&mut self, function_response: &FunctionResponse) {
         self.add_function_response(function_response)
     }
 
-    fn add_model_response(&mut self, response: &str, function_call: Option<serde_json::Value>) {
-        self.add_model_response(response, function_call)
+    fn add_model_response(&mut self, response: &str, tool_calls: &[FunctionCall]) {
+        self.add_model_response(response, tool_calls)
     }
 
     fn clear_conversation(&mut self) {
         self.clear_conversation()
     }
 
-    async fn send_message(&self, message: &str) -> Result<String> {
-        // Clone self to make it mutable for the mock
-        let mut mock_self = self.clone();
-        
-        // Add user message to history
-        mock_self.add_user_message(message);
-
-        // Simulate some processing delay
-        tokio::time::sleep(Duration::from_millis(mock_self.delay_ms * 2)).await;
-
-        // Get response
-        let (response, function_call) = mock_self.get_next_response(message);
-
-        // Add model response to history
-        mock_self.add_model_response(&response, function_call);
-
-        Ok(response)
+    fn set_tools(&mut self, _tools: crate::function_calling::ToolRegistry) {
+        // The mock decides on tool calls from its configured triggers, not from a registry
     }
 
-    async fn send_message_stream(&self, message: &str) -> Result<mpsc::Receiver<(String, Option<serde_json::Value>)>> {
-        // Clone self to make it mutable for the mock
-        let mut mock_self = self.clone();
-        
-        // Add user message to history
-        mock_self.add_user_message(message);
-
-        let (response, function_call) = mock_self.get_next_response(message);
-        let chunks = mock_self.split_into_chunks(&response);
-        let delay_ms = mock_self.delay_ms;
+    async fn send_message(&self, message: &str) -> Result<String> {
+        self.send_message(message).await
+    }
 
-        let (tx, rx) = mpsc::channel(100);
+    async fn send_message_stream(&self, message: &str) -> Result<mpsc::Receiver<StreamEvent>> {
+        self.send_message_stream(message).await
+    }
 
-        // Clone function_call for the async task
-        let function_call_for_task = function_call.clone();
+    fn client_name(&self) -> &str {
+        "MockLLM"
+    }
 
-        // Spawn task to send chunks
-        tokio::spawn(async move {
-            for (i, chunk) in chunks.iter().enumerate() {
-                // Add delay between chunks
-                if i > 0 {
-                    tokio::time::sleep(Duration::from_millis(delay_ms)).await;
-                }
+    fn model(&self) -> &str {
+        self.model()
+    }
 
-                // Send function call with the last chunk
-                let fc = if i == chunks.len() - 1 { function_call_for_task.clone() } else { None };
+    fn set_model(&mut self, model: &str) {
+        self.set_model(model)
+    }
 
-                if tx.send((chunk.clone(), fc)).await.is_err() {
-                    break; // Receiver dropped
-                }
-            }
-        });
+    fn conversation(&self) -> Conversation {
+        self.conversation()
+    }
 
-        // Add the complete response to history
-        mock_self.add_model_response(&response, function_call);
+    fn set_conversation(&mut self, conversation: Conversation) {
+        self.set_conversation(conversation)
+    }
 
-        Ok(rx)
+    fn generation_options(&self) -> &GenerationOptions {
+        self.generation_options()
     }
 
-    fn client_name(&self) -> &str {
-        "MockLLM"
+    fn set_generation_options(&mut self, options: GenerationOptions) {
+        self.set_generation_options(options)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::chat_client::ChatClient;
 
     #[tokio::test]
     async fn test_mock_llm_basic_response() {
@@ -337,8 +406,10 @@ mod tests {
         let mut rx = client.send_message_stream("Hello").await.unwrap();
         
         let mut chunks = Vec::new();
-        while let Some((chunk, _)) = rx.recv().await {
-            chunks.push(chunk);
+        while let Some(event) = rx.recv().await {
+            if let StreamEvent::TextDelta(chunk) = event {
+                chunks.push(chunk);
+            }
         }
         
         assert!(!chunks.is_empty());
@@ -362,8 +433,50 @@ mod tests {
         // Check that the last message in history has the function call
         let history = client.get_conversation_history();
         let last_message = history.last().unwrap();
-        assert_eq!(last_message.role, "assistant");
-        assert!(last_message.function_call.is_some());
+        assert_eq!(last_message.role, Role::Assistant);
+        assert!(last_message.tool_calls().next().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_mock_llm_streaming_function_call_events() {
+        let mut client = MockLLMClient::new().with_delay(1);
+        client.add_function_call_response("weather", serde_json::json!({
+            "name": "get_weather",
+            "args": {"location": "San Francisco"}
+        }));
+
+        let mut rx = client.send_message_stream("What's the weather like?").await.unwrap();
+        let mut events = Vec::new();
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+
+        assert!(matches!(events.first(), Some(StreamEvent::TextDelta(_))));
+        assert!(events.iter().any(|e| matches!(e,
+            StreamEvent::ToolCallComplete { name, args, .. }
+                if name == "get_weather" && args["location"] == "San Francisco")));
+        assert_eq!(events.last(), Some(&StreamEvent::FinishReason("tool_calls".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_mock_llm_multiple_function_calls() {
+        let mut client = MockLLMClient::new().with_delay(1);
+        client.add_function_call_response("both", serde_json::json!([
+            {"name": "read_file", "args": {"path": "a.txt"}},
+            {"name": "read_file", "args": {"path": "b.txt"}}
+        ]));
+
+        let mut rx = client.send_message_stream("Read both files").await.unwrap();
+        let mut completed = Vec::new();
+        while let Some(event) = rx.recv().await {
+            if let StreamEvent::ToolCallComplete { id, args, .. } = event {
+                completed.push((id, args));
+            }
+        }
+
+        assert_eq!(completed.len(), 2);
+        assert_ne!(completed[0].0, completed[1].0);
+        assert_eq!(completed[1].1["path"], "b.txt");
     }
 
     #[tokio::test]
@@ -376,8 +489,8 @@ mod tests {
         
         let history = client_mut.get_conversation_history();
         assert_eq!(history.len(), 3); // "Hello", "How are you?", and response
-        assert_eq!(history[0].role, "user");
-        assert_eq!(history[1].role, "user");
-        assert_eq!(history[2].role, "assistant");
+        assert_eq!(history[0].role, Role::User);
+        assert_eq!(history[1].role, Role::User);
+        assert_eq!(history[2].role, Role::Assistant);
     }
 }
\ No newline at end of file
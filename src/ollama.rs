@@ -0,0 +1,684 @@
+use anyhow::{anyhow, Result};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use futures::stream::StreamExt;
+use tokio::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use crate::chat_client::{spawn_stream, take_until, StreamEvent, TokenUsage};
+use crate::function_calling::ToolRegistry;
+use crate::conversation::{ChatMessage, Conversation, MessagePart, Role};
+use crate::generation::GenerationOptions;
+use crate::retry::RetryPolicy;
+
+/// Where a local Ollama server listens unless `OLLAMA_HOST` says otherwise
+pub const DEFAULT_OLLAMA_HOST: &str = "http://localhost:11434";
+
+/// Client for a local Ollama server's native API (`/api/chat`, `/api/tags`, `/api/pull`)
+#[derive(Clone)]
+pub struct OllamaClient {
+    client: Client,
+    model: String,
+    base_url: String,
+    conversation: Conversation,
+    tools: ToolRegistry,
+    options: GenerationOptions,
+    last_usage: Arc<Mutex<Option<TokenUsage>>>,
+    retry: RetryPolicy,
+    num_ctx: Option<u32>,
+    keep_alive: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Message {
+    pub role: String,
+    #[serde(default)]
+    pub content: String,
+    /// Base64-encoded images, for multimodal models
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// Names the tool a "tool" message answers; Ollama has no call ids
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ToolCall {
+    pub function: ToolCallFunction,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ToolCallFunction {
+    pub name: String,
+    /// A JSON object, not a string as in the OpenAI API
+    #[serde(default)]
+    pub arguments: serde_json::Value,
+}
+
+/// Translate a conversation message to Ollama chat messages. Each tool result
+/// becomes its own "tool" message.
+fn to_wire_messages(message: &ChatMessage) -> Vec<Message> {
+    match message.role {
+        Role::Tool => message.tool_results()
+            .map(|result| Message {
+                role: "tool".to_string(),
+                content: serde_json::to_string(&result.response).unwrap_or_else(|_| "Error serializing response".to_string()),
+                tool_name: Some(result.name.clone()),
+                ..Message::default()
+            })
+            .collect(),
+        role => {
+            let role = match role {
+                Role::System => "system",
+                Role::Assistant => "assistant",
+                _ => "user",
+            };
+            vec![Message {
+                role: role.to_string(),
+                content: message.text(),
+                images: message.parts.iter()
+                    .filter_map(|part| match part {
+                        MessagePart::Image { data, .. } => Some(data.clone()),
+                        _ => None,
+                    })
+                    .collect(),
+                tool_calls: message.tool_calls()
+                    .map(|call| ToolCall {
+                        function: ToolCallFunction { name: call.name.clone(), arguments: call.args.clone() },
+                    })
+                    .collect(),
+                tool_name: None,
+            }]
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ChatRequest {
+    model: String,
+    messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<Tool>>,
+    stream: bool,
+    options: Options,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Options {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_ctx: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+struct Tool {
+    #[serde(rename = "type")]
+    tool_type: String,
+    function: ToolFunction,
+}
+
+#[derive(Debug, Serialize)]
+struct ToolFunction {
+    name: String,
+    description: String,
+    parameters: serde_json::Value,
+}
+
+/// One line of a `/api/chat` response; a non-streaming response is a single `done` line
+#[derive(Debug, Deserialize)]
+struct ChatChunk {
+    #[serde(default)]
+    message: Option<Message>,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    done_reason: Option<String>,
+    #[serde(default)]
+    prompt_eval_count: u32,
+    #[serde(default)]
+    eval_count: u32,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// A model installed on the Ollama server
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct LocalModel {
+    pub name: String,
+    /// Size on disk in bytes
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub modified_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TagsResponse {
+    #[serde(default)]
+    models: Vec<LocalModel>,
+}
+
+/// A progress line from `/api/pull`. `total` and `completed` are bytes of the layer being downloaded.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+pub struct PullProgress {
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub digest: Option<String>,
+    #[serde(default)]
+    pub total: Option<u64>,
+    #[serde(default)]
+    pub completed: Option<u64>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Whether `wanted` names `installed`; a name without a tag means `:latest`
+fn same_model(installed: &str, wanted: &str) -> bool {
+    let with_tag = |name: &str| if name.contains(':') { name.to_string() } else { format!("{}:latest", name) };
+    with_tag(installed) == with_tag(wanted)
+}
+
+/// Turn a `/api/chat` line into stream events. Ollama sends each tool call whole, so it
+/// is started and completed at once; ids are made up because the API has none.
+fn chunk_events(chunk: ChatChunk, tool_count: &mut usize) -> Vec<StreamEvent> {
+    if let Some(error) = chunk.error {
+        return vec![StreamEvent::Error(error)];
+    }
+
+    let mut events = Vec::new();
+    if let Some(message) = chunk.message {
+        if !message.content.is_empty() {
+            events.push(StreamEvent::TextDelta(message.content));
+        }
+        for call in message.tool_calls {
+            let index = *tool_count;
+            *tool_count += 1;
+            let id = format!("call_{}_{}", chrono::Utc::now().timestamp_millis(), index);
+            let args = if call.function.arguments.is_null() { serde_json::json!({}) } else { call.function.arguments };
+            events.push(StreamEvent::ToolCallStart { index, id: id.clone(), name: call.function.name.clone() });
+            events.push(StreamEvent::ToolCallComplete { index, id, name: call.function.name, args });
+        }
+    }
+    if chunk.done {
+        events.push(StreamEvent::Usage(TokenUsage {
+            prompt_tokens: chunk.prompt_eval_count,
+            completion_tokens: chunk.eval_count,
+            total_tokens: chunk.prompt_eval_count + chunk.eval_count,
+        }));
+        events.push(StreamEvent::FinishReason(chunk.done_reason.unwrap_or_else(|| "stop".to_string())));
+    }
+    events
+}
+
+impl OllamaClient {
+    pub fn new(model: String) -> Self {
+        Self {
+            client: Client::new(),
+            model,
+            base_url: DEFAULT_OLLAMA_HOST.to_string(),
+            conversation: Conversation::new(),
+            tools: ToolRegistry::with_builtin_tools(),
+            options: GenerationOptions::default(),
+            last_usage: Arc::new(Mutex::new(None)),
+            retry: RetryPolicy::default(),
+            num_ctx: None,
+            keep_alive: None,
+        }
+    }
+
+    /// Use another server; a bare `host:port` as in `OLLAMA_HOST` is accepted
+    pub fn with_base_url(mut self, base_url: String) -> Self {
+        let base_url = base_url.trim_end_matches('/');
+        self.base_url = if base_url.contains("://") {
+            base_url.to_string()
+        } else {
+            format!("http://{}", base_url)
+        };
+        self
+    }
+
+    /// Context window in tokens; Ollama's default is small for long tool sessions
+    pub fn with_num_ctx(mut self, num_ctx: u32) -> Self {
+        self.num_ctx = Some(num_ctx);
+        self
+    }
+
+    /// How long the server keeps the model loaded after a request, e.g. "10m" or "-1" for ever
+    pub fn with_keep_alive(mut self, keep_alive: String) -> Self {
+        self.keep_alive = Some(keep_alive);
+        self
+    }
+
+    /// Set the tools offered to the model
+    pub fn with_tools(mut self, tools: ToolRegistry) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    pub fn set_tools(&mut self, tools: ToolRegistry) {
+        self.tools = tools;
+    }
+
+    /// Token usage of the last non-streaming request
+    pub fn last_usage(&self) -> Option<TokenUsage> {
+        self.last_usage.lock().unwrap().clone()
+    }
+
+    /// Retry failed requests according to `retry`
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Set the sampling and length options sent with each request
+    pub fn with_generation_options(mut self, options: GenerationOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn generation_options(&self) -> &GenerationOptions {
+        &self.options
+    }
+
+    pub fn set_generation_options(&mut self, options: GenerationOptions) {
+        self.options = options;
+    }
+
+    pub fn set_model(&mut self, model: &str) {
+        self.model = model.to_string();
+    }
+
+    pub fn load_system_prompt(&mut self, prompt_content: &str) -> Result<()> {
+        self.conversation.system_prompt = Some(prompt_content.to_string());
+        Ok(())
+    }
+
+    pub fn add_user_message(&mut self, message: &str) {
+        self.conversation.push(ChatMessage::user(message));
+    }
+
+    pub fn add_function_response(&mut self, function_response: &crate::function_calling::FunctionResponse) {
+        self.conversation.push(ChatMessage::tool_result(function_response));
+    }
+
+    pub fn add_model_response(&mut self, response: &str, tool_calls: &[crate::function_calling::FunctionCall]) {
+        self.conversation.push(ChatMessage::assistant(response, tool_calls).with_model(&self.model));
+    }
+
+    pub fn clear_conversation(&mut self) {
+        self.conversation.clear();
+    }
+
+    pub fn conversation(&self) -> &Conversation {
+        &self.conversation
+    }
+
+    pub fn set_conversation(&mut self, conversation: Conversation) {
+        self.conversation = conversation;
+    }
+
+    fn build_messages(&self, user_message: Option<&str>) -> Vec<Message> {
+        let mut messages = Vec::new();
+
+        if let Some(system_prompt) = &self.conversation.system_prompt {
+            messages.push(Message { role: "system".to_string(), content: system_prompt.clone(), ..Message::default() });
+        }
+        for message in self.conversation.messages() {
+            messages.extend(to_wire_messages(message));
+        }
+        if let Some(msg) = user_message {
+            messages.push(Message { role: "user".to_string(), content: msg.to_string(), ..Message::default() });
+        }
+
+        messages
+    }
+
+    fn build_tools(&self) -> Option<Vec<Tool>> {
+        if self.tools.is_empty() {
+            return None;
+        }
+
+        Some(
+            self.tools
+                .definitions()
+                .into_iter()
+                .map(|tool| Tool {
+                    tool_type: "function".to_string(),
+                    function: ToolFunction {
+                        name: tool.name,
+                        description: tool.description,
+                        parameters: tool.parameters,
+                    },
+                })
+                .collect(),
+        )
+    }
+
+    fn build_request(&self, message: &str, stream: bool) -> ChatRequest {
+        ChatRequest {
+            model: self.model.clone(),
+            messages: self.build_messages(if message.is_empty() { None } else { Some(message) }),
+            tools: self.build_tools(),
+            stream,
+            // Reasoning effort is not mapped: only some models accept a thinking level
+            options: Options {
+                temperature: self.options.temperature,
+                top_p: self.options.top_p,
+                top_k: self.options.top_k,
+                num_predict: self.options.max_tokens,
+                stop: self.options.stop.clone(),
+                seed: self.options.seed,
+                presence_penalty: self.options.presence_penalty,
+                frequency_penalty: self.options.frequency_penalty,
+                num_ctx: self.num_ctx,
+            },
+            keep_alive: self.keep_alive.clone(),
+        }
+    }
+
+    async fn post<T: Serialize>(&self, path: &str, body: &T) -> Result<reqwest::Response> {
+        self.retry
+            .send(|| self.client.post(format!("{}{}", self.base_url, path)).json(body))
+            .await
+            .map_err(|e| match e.downcast::<reqwest::Error>() {
+                Ok(e) => anyhow!("Could not reach Ollama at {}: {}", self.base_url, e),
+                Err(e) => e,
+            })
+    }
+
+    pub async fn send_message(&self, message: &str) -> Result<String> {
+        let response = self.post("/api/chat", &self.build_request(message, false)).await?;
+        let chunk: ChatChunk = response.json().await?;
+        if let Some(error) = chunk.error {
+            return Err(anyhow!("API request failed: {}", error));
+        }
+        *self.last_usage.lock().unwrap() = Some(TokenUsage {
+            prompt_tokens: chunk.prompt_eval_count,
+            completion_tokens: chunk.eval_count,
+            total_tokens: chunk.prompt_eval_count + chunk.eval_count,
+        });
+        Ok(chunk.message.map(|m| m.content).unwrap_or_default())
+    }
+
+    pub async fn send_message_stream(&self, message: &str) -> Result<mpsc::Receiver<StreamEvent>> {
+        let response = self.post("/api/chat", &self.build_request(message, true)).await?;
+        let (tx, rx) = mpsc::channel::<StreamEvent>(1000);
+
+        spawn_stream(tx, move |tx| async move {
+            let mut stream = response.bytes_stream();
+            let mut buffer = Vec::new();
+            let mut tool_count = 0;
+
+            while let Some(chunk_result) = stream.next().await {
+                let chunk = match chunk_result {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        let _ = tx.send(StreamEvent::Error(format!("Stream error: {}", e))).await;
+                        return;
+                    }
+                };
+                buffer.extend_from_slice(&chunk);
+
+                // The response is newline-delimited JSON, one object per line
+                while let Some(line) = take_until(&mut buffer, b"\n") {
+                    let line = line.trim();
+                    if line.is_empty() {
+                        continue;
+                    }
+
+                    let events = match serde_json::from_str::<ChatChunk>(line) {
+                        Ok(chunk) => chunk_events(chunk, &mut tool_count),
+                        Err(e) => vec![StreamEvent::Error(format!("JSON parse error: {} - Data: {}", e, line))],
+                    };
+                    for event in events {
+                        if tx.send(event).await.is_err() {
+                            return; // Receiver dropped
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(rx)
+    }
+
+    /// The models installed on the server
+    pub async fn list_models(&self) -> Result<Vec<LocalModel>> {
+        let response = self
+            .client
+            .get(format!("{}/api/tags", self.base_url))
+            .send()
+            .await
+            .map_err(|e| anyhow!("Could not reach Ollama at {}: {}", self.base_url, e))?;
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(anyhow!("API request failed: {}", error_text));
+        }
+        Ok(response.json::<TagsResponse>().await?.models)
+    }
+
+    /// Whether the current model is installed on the server
+    pub async fn has_model(&self) -> Result<bool> {
+        Ok(self.list_models().await?.iter().any(|m| same_model(&m.name, &self.model)))
+    }
+
+    /// Download the current model, reporting each progress line from the server
+    pub async fn pull_model(&self, mut on_progress: impl FnMut(&PullProgress)) -> Result<()> {
+        let request = serde_json::json!({ "model": self.model, "stream": true });
+        let response = self.post("/api/pull", &request).await?;
+        let mut stream = response.bytes_stream();
+        let mut buffer = Vec::new();
+
+        while let Some(chunk) = stream.next().await {
+            buffer.extend_from_slice(&chunk?);
+            while let Some(line) = take_until(&mut buffer, b"\n") {
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+
+                let progress: PullProgress = serde_json::from_str(line)?;
+                if let Some(error) = progress.error {
+                    return Err(anyhow!("Pulling {} failed: {}", self.model, error));
+                }
+                on_progress(&progress);
+                if progress.status == "success" {
+                    return Ok(());
+                }
+            }
+        }
+        Err(anyhow!("Pulling {} ended before it finished", self.model))
+    }
+}
+
+#[async_trait]
+impl crate::chat_client::ChatClient for OllamaClient {
+    fn load_system_prompt(&mut self, prompt_content: &str) -> Result<()> {
+        self.load_system_prompt(prompt_content)
+    }
+
+    fn add_user_message(&mut self, message: &str) {
+        self.add_user_message(message)
+    }
+
+    fn add_function_response(&mut self, function_response: &crate::function_calling::FunctionResponse) {
+        self.add_function_response(function_response)
+    }
+
+    fn add_model_response(&mut self, response: &str, tool_calls: &[crate::function_calling::FunctionCall]) {
+        self.add_model_response(response, tool_calls)
+    }
+
+    fn clear_conversation(&mut self) {
+        self.clear_conversation()
+    }
+
+    fn set_tools(&mut self, tools: ToolRegistry) {
+        self.set_tools(tools)
+    }
+
+    async fn send_message(&self, message: &str) -> Result<String> {
+        self.send_message(message).await
+    }
+
+    async fn send_message_stream(&self, message: &str) -> Result<mpsc::Receiver<StreamEvent>> {
+        self.send_message_stream(message).await
+    }
+
+    fn client_name(&self) -> &str {
+        "Ollama"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn set_model(&mut self, model: &str) {
+        self.set_model(model)
+    }
+
+    fn conversation(&self) -> Conversation {
+        self.conversation.clone()
+    }
+
+    fn set_conversation(&mut self, conversation: Conversation) {
+        self.set_conversation(conversation)
+    }
+
+    fn last_usage(&self) -> Option<TokenUsage> {
+        self.last_usage()
+    }
+
+    /// The server cuts the prompt to `num_ctx` whatever the model supports
+    fn context_limit(&self) -> Option<usize> {
+        self.num_ctx.map(|tokens| tokens as usize)
+    }
+
+    fn generation_options(&self) -> &GenerationOptions {
+        self.generation_options()
+    }
+
+    fn set_generation_options(&mut self, options: GenerationOptions) {
+        self.set_generation_options(options)
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        Ok(self.list_models().await?.into_iter().map(|m| m.name).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::function_calling::{FunctionCall, FunctionResponse};
+    use crate::test_support::serve_once;
+
+    fn ndjson(lines: &[serde_json::Value]) -> String {
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    #[test]
+    fn test_conversation_translates_to_messages() {
+        let mut client = OllamaClient::new("llama3.2".to_string()).with_num_ctx(8192).with_keep_alive("10m".to_string());
+        client.load_system_prompt("Be brief.").unwrap();
+        client.add_user_message("List the files");
+        let call = FunctionCall { id: Some("call-1".to_string()), name: "list_directory".to_string(), args: serde_json::json!({"path": "."}) };
+        client.add_model_response("", std::slice::from_ref(&call));
+        client.add_function_response(&FunctionResponse {
+            id: call.call_id(),
+            name: call.name.clone(),
+            response: serde_json::json!({"success": true}),
+        });
+
+        let request = serde_json::to_value(client.build_request("", true)).unwrap();
+        assert_eq!(request["messages"], serde_json::json!([
+            {"role": "system", "content": "Be brief."},
+            {"role": "user", "content": "List the files"},
+            {"role": "assistant", "content": "", "tool_calls": [{"function": {"name": "list_directory", "arguments": {"path": "."}}}]},
+            {"role": "tool", "content": "{\"success\":true}", "tool_name": "list_directory"}
+        ]));
+        assert_eq!(request["options"]["num_ctx"], 8192);
+        assert_eq!(request["keep_alive"], "10m");
+    }
+
+    #[tokio::test]
+    async fn test_streams_ndjson_chat_from_server() {
+        let body = ndjson(&[
+            serde_json::json!({"model": "llama3.2", "message": {"role": "assistant", "content": "Let me"}, "done": false}),
+            serde_json::json!({"model": "llama3.2", "message": {"role": "assistant", "content": " check.", "tool_calls": [
+                {"function": {"name": "read_file", "arguments": {"path": "a.txt"}}}
+            ]}, "done": false}),
+            serde_json::json!({"model": "llama3.2", "message": {"role": "assistant", "content": ""}, "done": true, "done_reason": "stop", "prompt_eval_count": 30, "eval_count": 12}),
+        ]);
+        let (url, server) = serve_once("application/x-ndjson", body).await;
+        let client = OllamaClient::new("llama3.2".to_string()).with_base_url(url);
+
+        let mut rx = client.send_message_stream("What is in a.txt?").await.unwrap();
+        let mut events = Vec::new();
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+
+        assert_eq!(events[..2], [StreamEvent::TextDelta("Let me".to_string()), StreamEvent::TextDelta(" check.".to_string())]);
+        assert!(matches!(&events[2], StreamEvent::ToolCallStart { index: 0, name, .. } if name == "read_file"));
+        assert!(matches!(&events[3], StreamEvent::ToolCallComplete { index: 0, args, .. } if args["path"] == "a.txt"));
+        assert_eq!(events[4..], [
+            StreamEvent::Usage(TokenUsage { prompt_tokens: 30, completion_tokens: 12, total_tokens: 42 }),
+            StreamEvent::FinishReason("stop".to_string()),
+        ]);
+        assert!(server.await.unwrap().starts_with("POST /api/chat "));
+    }
+
+    #[tokio::test]
+    async fn test_lists_models_from_tags() {
+        let body = serde_json::json!({"models": [
+            {"name": "llama3.2:latest", "size": 2019393189u64, "modified_at": "2024-10-01T12:00:00Z"},
+            {"name": "qwen2.5-coder:7b", "size": 4683087332u64, "modified_at": "2024-10-02T12:00:00Z"}
+        ]}).to_string();
+        let (url, server) = serve_once("application/json", body).await;
+        let client = OllamaClient::new("llama3.2".to_string()).with_base_url(url);
+
+        assert!(client.has_model().await.unwrap());
+        assert!(server.await.unwrap().starts_with("GET /api/tags "));
+        assert!(!same_model("qwen2.5-coder:7b", "qwen2.5-coder"));
+    }
+
+    #[tokio::test]
+    async fn test_pull_reports_progress_until_success() {
+        let body = ndjson(&[
+            serde_json::json!({"status": "pulling manifest"}),
+            serde_json::json!({"status": "pulling 6a0746a1ec1a", "digest": "sha256:6a07", "total": 2000, "completed": 1000}),
+            serde_json::json!({"status": "pulling 6a0746a1ec1a", "digest": "sha256:6a07", "total": 2000, "completed": 2000}),
+            serde_json::json!({"status": "success"}),
+        ]);
+        let (url, server) = serve_once("application/x-ndjson", body).await;
+        let client = OllamaClient::new("llama3.2".to_string()).with_base_url(url);
+
+        let mut seen = Vec::new();
+        client.pull_model(|progress| seen.push((progress.status.clone(), progress.completed))).await.unwrap();
+
+        assert_eq!(seen.len(), 4);
+        assert_eq!(seen[1], ("pulling 6a0746a1ec1a".to_string(), Some(1000)));
+        assert!(server.await.unwrap().contains("\"model\":\"llama3.2\""));
+    }
+}
@@ -0,0 +1,386 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::io::Write;
+use std::str::FromStr;
+use crate::agent::{AgentObserver, AgentStep, StopReason, TurnResult};
+use crate::chat_client::{StreamEvent, TokenUsage};
+use crate::context::CompactionReport;
+use crate::function_calling::{FunctionCall, FunctionResponse, ToolOutput};
+use crate::permissions;
+
+/// The model answered
+pub const EXIT_SUCCESS: i32 = 0;
+/// The request failed or the response stream reported an error
+pub const EXIT_FAILURE: i32 = 1;
+/// Bad arguments, or nothing to send
+pub const EXIT_USAGE: i32 = 2;
+/// The turn hit the step limit while the model still wanted tools
+pub const EXIT_MAX_STEPS: i32 = 3;
+/// Stopped with Ctrl+C, following the shell's 128 + SIGINT convention
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+/// How a one-shot answer is written to stdout
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The answer as plain text
+    #[default]
+    Text,
+    /// One JSON object once the turn is over
+    Json,
+    /// One JSON object per line as events arrive, ending with the `json` object
+    StreamJson,
+}
+
+impl OutputFormat {
+    pub const NAMES: &'static [&'static str] = &["text", "json", "stream-json"];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Json => "json",
+            OutputFormat::StreamJson => "stream-json",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().replace('_', "-").as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "stream-json" | "jsonl" => Ok(OutputFormat::StreamJson),
+            other => Err(anyhow!("Unknown output format '{}'; use {}", other, OutputFormat::NAMES.join(", "))),
+        }
+    }
+}
+
+/// The message to send: the prompt, followed by whatever was piped in on stdin
+pub fn compose_message(prompt: Option<&str>, piped: Option<&str>) -> Option<String> {
+    let prompt = prompt.map(str::trim).filter(|p| !p.is_empty());
+    let piped = piped.map(str::trim_end).filter(|p| !p.trim().is_empty());
+    match (prompt, piped) {
+        (Some(prompt), Some(piped)) => Some(format!("{}\n\n{}", prompt, piped)),
+        (Some(text), None) | (None, Some(text)) => Some(text.to_string()),
+        (None, None) => None,
+    }
+}
+
+/// Exit status for a finished turn; `stream_failed` is set when a response stream reported errors
+pub fn exit_code(result: &Result<TurnResult>, stream_failed: bool) -> i32 {
+    match result {
+        Err(_) => EXIT_FAILURE,
+        Ok(_) if stream_failed => EXIT_FAILURE,
+        Ok(turn) => match turn.stop_reason {
+            StopReason::Completed => EXIT_SUCCESS,
+            StopReason::MaxStepsReached => EXIT_MAX_STEPS,
+            StopReason::Stopped => EXIT_INTERRUPTED,
+        },
+    }
+}
+
+/// Writes the model's text to `out` as it streams, without cards or colours.
+/// Tool activity and errors go to stderr so `out` holds only the answer.
+pub struct PlainObserver<W: Write + Send> {
+    out: W,
+    /// Whether the last text written ended a line
+    at_line_start: bool,
+    /// A response stream reported an error
+    pub stream_failed: bool,
+}
+
+impl<W: Write + Send> PlainObserver<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            at_line_start: true,
+            stream_failed: false,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+#[async_trait]
+impl<W: Write + Send> AgentObserver for PlainObserver<W> {
+    async fn on_stream_event(&mut self, event: &StreamEvent) -> Result<()> {
+        if let StreamEvent::TextDelta(text) = event {
+            self.out.write_all(text.as_bytes())?;
+            self.out.flush()?;
+            if !text.is_empty() {
+                self.at_line_start = text.ends_with('\n');
+            }
+        }
+        Ok(())
+    }
+
+    async fn on_step_end(&mut self, step: &AgentStep) -> Result<()> {
+        // Text from separate steps, and the end of the answer, get their own line
+        if !self.at_line_start {
+            writeln!(self.out)?;
+            self.at_line_start = true;
+        }
+        if !step.errors.is_empty() {
+            self.stream_failed = true;
+            for error in &step.errors {
+                eprintln!("Error: {}", error);
+            }
+        }
+        Ok(())
+    }
+
+    async fn on_context_compacted(&mut self, report: &CompactionReport) -> Result<()> {
+        eprintln!("[context] {}", report);
+        Ok(())
+    }
+
+    async fn on_tool_denied(&mut self, call: &FunctionCall, reason: &str) -> Result<()> {
+        eprintln!("[denied] {} ({}): {}", call.name, permissions::approval_subject(call), reason);
+        Ok(())
+    }
+
+    async fn on_tool_call(&mut self, call: &FunctionCall) -> Result<()> {
+        eprintln!("[tool] {} ({})", call.name, permissions::approval_subject(call));
+        Ok(())
+    }
+}
+
+/// One line of `stream-json` output; the `json` format prints only the final `Result`
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OutputEvent<'a> {
+    StepStart { step: usize },
+    Text { text: &'a str },
+    Reasoning { text: &'a str },
+    ToolCall { id: &'a str, name: &'a str, args: &'a serde_json::Value },
+    ToolOutput { id: String, stream: &'static str, line: &'a str },
+    ToolResult(&'a FunctionResponse),
+    Usage(&'a TokenUsage),
+    FinishReason { reason: &'a str },
+    Error { message: String },
+    Result(&'a TurnReport),
+}
+
+/// Everything a turn produced, as reported by the `json` format
+#[derive(Debug, Default, Serialize)]
+pub struct TurnReport {
+    /// The model's final answer
+    pub text: String,
+    pub tool_calls: Vec<FunctionCall>,
+    pub tool_results: Vec<FunctionResponse>,
+    /// Tokens used by all of the turn's requests
+    pub usage: TokenUsage,
+    pub finish_reason: Option<String>,
+    /// `completed`, `max_steps`, `stopped` or `error`
+    pub stop_reason: String,
+    pub steps: usize,
+    pub is_error: bool,
+    /// Why the request or its stream failed
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+/// Writes a turn as JSON: a single object at the end, or with `streaming` also one event per line
+pub struct JsonObserver<W: Write + Send> {
+    out: W,
+    streaming: bool,
+    report: TurnReport,
+}
+
+impl<W: Write + Send> JsonObserver<W> {
+    pub fn new(out: W, streaming: bool) -> Self {
+        Self { out, streaming, report: TurnReport::default() }
+    }
+
+    /// A response stream reported an error
+    pub fn stream_failed(&self) -> bool {
+        !self.report.errors.is_empty()
+    }
+
+    /// Write the final object for the turn's `result`
+    pub fn finish(&mut self, result: &Result<TurnResult>) -> Result<&TurnReport> {
+        match result {
+            Ok(turn) => {
+                self.report.text = turn.text.clone();
+                self.report.steps = turn.steps;
+                self.report.stop_reason = match turn.stop_reason {
+                    StopReason::Completed => "completed",
+                    StopReason::MaxStepsReached => "max_steps",
+                    StopReason::Stopped => "stopped",
+                }.to_string();
+            }
+            Err(e) => {
+                self.report.stop_reason = "error".to_string();
+                self.report.errors.push(e.to_string());
+            }
+        }
+        self.report.is_error = !self.report.errors.is_empty();
+        let line = if self.streaming {
+            serde_json::to_string(&OutputEvent::Result(&self.report))?
+        } else {
+            serde_json::to_string(&self.report)?
+        };
+        writeln!(self.out, "{}", line)?;
+        self.out.flush()?;
+        Ok(&self.report)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn emit(&mut self, event: OutputEvent) -> Result<()> {
+        if self.streaming {
+            writeln!(self.out, "{}", serde_json::to_string(&event)?)?;
+            self.out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<W: Write + Send> AgentObserver for JsonObserver<W> {
+    async fn on_step_start(&mut self, step: usize) -> Result<()> {
+        self.emit(OutputEvent::StepStart { step })
+    }
+
+    async fn on_stream_event(&mut self, event: &StreamEvent) -> Result<()> {
+        match event {
+            StreamEvent::TextDelta(text) => self.emit(OutputEvent::Text { text }),
+            StreamEvent::ReasoningDelta(text) => self.emit(OutputEvent::Reasoning { text }),
+            StreamEvent::ToolCallComplete { id, name, args, .. } => {
+                self.report.tool_calls.push(FunctionCall { id: Some(id.clone()), name: name.clone(), args: args.clone() });
+                self.emit(OutputEvent::ToolCall { id, name, args })
+            }
+            StreamEvent::Usage(used) => {
+                self.report.usage.prompt_tokens += used.prompt_tokens;
+                self.report.usage.completion_tokens += used.completion_tokens;
+                self.report.usage.total_tokens += used.total_tokens;
+                self.emit(OutputEvent::Usage(used))
+            }
+            StreamEvent::FinishReason(reason) => {
+                self.report.finish_reason = Some(reason.clone());
+                self.emit(OutputEvent::FinishReason { reason })
+            }
+            StreamEvent::Error(message) => {
+                self.report.errors.push(message.clone());
+                self.emit(OutputEvent::Error { message: message.clone() })
+            }
+            StreamEvent::ToolCallStart { .. } | StreamEvent::ToolCallArgsDelta { .. } => Ok(()),
+        }
+    }
+
+    async fn on_step_failed(&mut self, error: &anyhow::Error) -> Result<()> {
+        self.emit(OutputEvent::Error { message: error.to_string() })
+    }
+
+    async fn on_context_compacted(&mut self, report: &CompactionReport) -> Result<()> {
+        eprintln!("[context] {}", report);
+        Ok(())
+    }
+
+    async fn on_tool_denied(&mut self, call: &FunctionCall, reason: &str) -> Result<()> {
+        let response = FunctionResponse::denied(call, reason);
+        self.emit(OutputEvent::ToolResult(&response))?;
+        self.report.tool_results.push(response);
+        Ok(())
+    }
+
+    async fn on_tool_output(&mut self, call: &FunctionCall, output: &ToolOutput) -> Result<()> {
+        let (stream, line) = match output {
+            ToolOutput::Stdout(line) => ("stdout", line),
+            ToolOutput::Stderr(line) => ("stderr", line),
+        };
+        self.emit(OutputEvent::ToolOutput { id: call.call_id(), stream, line })
+    }
+
+    async fn on_tool_result(&mut self, call: &FunctionCall, result: &Result<FunctionResponse>) -> Result<()> {
+        let response = match result {
+            Ok(response) => response.clone(),
+            Err(e) => FunctionResponse::error(call, &e.to_string()),
+        };
+        self.emit(OutputEvent::ToolResult(&response))?;
+        self.report.tool_results.push(response);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{Agent, NoopObserver};
+    use crate::function_calling::FunctionExecutor;
+    use crate::mock_llm::MockLLMClient;
+
+    #[test]
+    fn test_compose_message_appends_piped_input() {
+        assert_eq!(compose_message(Some("explain this"), Some("panic at line 3\n")).unwrap(), "explain this\n\npanic at line 3");
+        assert_eq!(compose_message(Some(" Why? "), None).unwrap(), "Why?");
+        assert_eq!(compose_message(None, Some("  \n")), None);
+        assert_eq!("stream-json".parse::<OutputFormat>().unwrap(), OutputFormat::StreamJson);
+        assert!("yaml".parse::<OutputFormat>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_plain_observer_writes_only_the_answer() {
+        let mut client = MockLLMClient::with_responses(vec!["Done.".to_string()]).with_delay(1);
+        let agent = Agent::new(FunctionExecutor::new());
+        let mut observer = PlainObserver::new(Vec::new());
+
+        let result = agent.run_turn(&mut client, "Hello", &mut observer).await;
+
+        assert_eq!(exit_code(&result, observer.stream_failed), EXIT_SUCCESS);
+        assert_eq!(String::from_utf8(observer.into_inner()).unwrap(), "Mock LLM: Done.\n");
+
+        let mut client = MockLLMClient::with_responses(vec!["Done.".to_string()]).with_delay(1);
+        client.add_function_call_response("greet", serde_json::json!({
+            "name": "shell_command",
+            "args": {"command": "echo hello"}
+        }));
+        let agent = Agent::new(FunctionExecutor::new()).with_max_steps(1);
+        let result = agent.run_turn(&mut client, "Please greet me", &mut NoopObserver).await;
+        assert_eq!(exit_code(&result, false), EXIT_MAX_STEPS);
+        assert_eq!(exit_code(&Err(anyhow!("offline")), false), EXIT_FAILURE);
+    }
+
+    #[tokio::test]
+    async fn test_json_observer_reports_tools_and_result() {
+        let mut client = MockLLMClient::with_responses(vec!["All done.".to_string()]).with_delay(1);
+        client.add_function_call_response("greet", serde_json::json!({
+            "name": "shell_command",
+            "args": {"command": "echo hello"}
+        }));
+        let agent = Agent::new(FunctionExecutor::new());
+
+        let mut observer = JsonObserver::new(Vec::new(), true);
+        let result = agent.run_turn(&mut client, "Please greet me", &mut observer).await;
+        observer.finish(&result).unwrap();
+        let output = String::from_utf8(observer.into_inner()).unwrap();
+        let events: Vec<serde_json::Value> = output.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        let types: Vec<&str> = events.iter().map(|event| event["type"].as_str().unwrap()).collect();
+        assert_eq!(types.first(), Some(&"step_start"));
+        assert!(types.contains(&"tool_call") && types.contains(&"tool_result"), "{:?}", types);
+        assert_eq!(types.last(), Some(&"result"));
+
+        let mut client = MockLLMClient::with_responses(vec!["All done.".to_string()]).with_delay(1);
+        client.add_function_call_response("greet", serde_json::json!({
+            "name": "shell_command",
+            "args": {"command": "echo hello"}
+        }));
+        let mut observer = JsonObserver::new(Vec::new(), false);
+        let result = agent.run_turn(&mut client, "Please greet me", &mut observer).await;
+        observer.finish(&result).unwrap();
+        let output = String::from_utf8(observer.into_inner()).unwrap();
+        assert_eq!(output.lines().count(), 1);
+        let report: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(report["text"], "Mock LLM: All done.");
+        assert_eq!(report["stop_reason"], "completed");
+        assert_eq!(report["tool_calls"][0]["name"], "shell_command");
+        assert_eq!(report["tool_results"][0]["name"], "shell_command");
+        assert_eq!(report["is_error"], false);
+    }
+}
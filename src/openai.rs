@@ -5,7 +5,17 @@ use futures::stream::StreamExt;
 use tokio::sync::mpsc;
 use std::fs::OpenOptions;
 use std::io::Write;
+use std::sync::{Arc, Mutex};
 use async_trait::async_trait;
+use crate::chat_client::{spawn_stream, StreamEvent, TokenUsage};
+use crate::function_calling::ToolRegistry;
+use crate::conversation::{ChatMessage, Conversation, MessagePart, Role};
+use crate::generation::GenerationOptions;
+use crate::retry::RetryPolicy;
+
+mod responses;
+pub use responses::DEFAULT_RESPONSES_MODELS;
+use responses::PreviousResponse;
 
 #[derive(Clone)]
 pub struct OpenAIClient {
@@ -13,10 +23,30 @@ pub struct OpenAIClient {
     api_key: String,
     model: String,
     base_url: String,
-    conversation_history: Vec<Message>,
-    system_message: Option<String>,
+    conversation: Conversation,
+    tools: ToolRegistry,
+    options: GenerationOptions,
+    last_usage: Arc<Mutex<Option<TokenUsage>>>,
+    retry: RetryPolicy,
+    /// Set when talking to an Azure OpenAI resource instead of the OpenAI API
+    azure: Option<AzureConfig>,
+    /// Models whose requests go to the Responses API instead of Chat Completions; unset
+    /// means `DEFAULT_RESPONSES_MODELS`, applied only on the OpenAI API itself
+    responses_models: Option<Vec<glob::Pattern>>,
+    /// The Responses API response the next request can be chained onto
+    previous_response: Arc<Mutex<Option<PreviousResponse>>>,
+}
+
+/// Azure OpenAI routes requests by deployment and API version, and authenticates with an
+/// `api-key` header. The client's model is used as the deployment name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AzureConfig {
+    pub api_version: String,
 }
 
+/// Azure OpenAI API version used unless `AZURE_OPENAI_API_VERSION` is set
+pub const DEFAULT_AZURE_API_VERSION: &str = "2024-10-21";
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Message {
     pub role: String,
@@ -40,7 +70,89 @@ pub enum MessageContent {
 pub struct ContentPart {
     #[serde(rename = "type")]
     pub content_type: String,
-    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<ImageUrl>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImageUrl {
+    pub url: String,
+}
+
+impl Message {
+    fn text(role: &str, text: &str) -> Self {
+        Self {
+            role: role.to_string(),
+            content: MessageContent::Text(text.to_string()),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        }
+    }
+}
+
+/// Translate a conversation message to Chat Completions messages. Each tool result
+/// becomes its own "tool" message.
+fn to_wire_messages(message: &ChatMessage) -> Vec<Message> {
+    match message.role {
+        Role::Tool => message.tool_results()
+            .map(|result| Message {
+                role: "tool".to_string(),
+                content: MessageContent::Text(serde_json::to_string(&result.response).unwrap_or_else(|_| "Error serializing response".to_string())),
+                name: Some(result.name.clone()),
+                tool_calls: None,
+                tool_call_id: Some(result.id.clone()),
+            })
+            .collect(),
+        Role::Assistant => {
+            let tool_calls: Vec<ToolCall> = message.tool_calls()
+                .enumerate()
+                .map(|(i, fc)| ToolCall {
+                    id: fc.id.clone().unwrap_or_else(|| {
+                        format!("call_{}_{}", chrono::Utc::now().timestamp_millis(), i)
+                    }),
+                    call_type: "function".to_string(),
+                    function: FunctionCall {
+                        name: fc.name.clone(),
+                        arguments: fc.args.to_string(),
+                    },
+                })
+                .collect();
+            vec![Message {
+                tool_calls: if tool_calls.is_empty() { None } else { Some(tool_calls) },
+                ..Message::text("assistant", &message.text())
+            }]
+        }
+        Role::System | Role::User => {
+            let role = if message.role == Role::System { "system" } else { "user" };
+            let has_images = message.parts.iter().any(|p| matches!(p, MessagePart::Image { .. }));
+            if !has_images {
+                return vec![Message::text(role, &message.text())];
+            }
+
+            let parts = message.parts.iter()
+                .filter_map(|part| match part {
+                    MessagePart::Text { text } => Some(ContentPart {
+                        content_type: "text".to_string(),
+                        text: Some(text.clone()),
+                        image_url: None,
+                    }),
+                    MessagePart::Image { mime_type, data } => Some(ContentPart {
+                        content_type: "image_url".to_string(),
+                        text: None,
+                        image_url: Some(ImageUrl { url: format!("data:{};base64,{}", mime_type, data) }),
+                    }),
+                    _ => None,
+                })
+                .collect();
+            vec![Message {
+                content: MessageContent::Array(parts),
+                ..Message::text(role, "")
+            }]
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -66,10 +178,22 @@ struct ChatCompletionRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     top_p: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    max_tokens: Option<i32>,
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning_effort: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     stream: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     tools: Option<Vec<Tool>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tool_choice: Option<String>,
@@ -89,6 +213,12 @@ struct ToolFunction {
     parameters: serde_json::Value,
 }
 
+/// Asks for a final chunk carrying the usage of the whole request
+#[derive(Debug, Serialize, Deserialize)]
+struct StreamOptions {
+    include_usage: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct ChatCompletionResponse {
     id: String,
@@ -110,9 +240,19 @@ struct Choice {
 
 #[derive(Debug, Serialize, Deserialize)]
 struct Usage {
-    prompt_tokens: i32,
-    completion_tokens: i32,
-    total_tokens: i32,
+    prompt_tokens: u32,
+    completion_tokens: u32,
+    total_tokens: u32,
+}
+
+impl From<&Usage> for TokenUsage {
+    fn from(usage: &Usage) -> Self {
+        TokenUsage {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            total_tokens: usage.total_tokens,
+        }
+    }
 }
 
 // Streaming response structures
@@ -123,6 +263,8 @@ struct ChatCompletionChunk {
     created: i64,
     model: String,
     choices: Vec<ChunkChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    usage: Option<Usage>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -163,6 +305,98 @@ struct DeltaFunction {
     arguments: Option<String>,
 }
 
+/// Assembles streamed tool call deltas into complete calls.
+///
+/// The API spreads each call over many chunks keyed by `index`; the id and name
+/// arrive first and the JSON arguments follow in fragments.
+#[derive(Default)]
+struct ToolCallAccumulator {
+    calls: Vec<ToolCall>,
+    finished: bool,
+}
+
+impl ToolCallAccumulator {
+    fn apply_delta(&mut self, delta: &DeltaToolCall) -> Vec<StreamEvent> {
+        let mut events = Vec::new();
+        let index = delta.index.unwrap_or(0) as usize;
+
+        // Ensure we have enough space in the vector
+        while self.calls.len() <= index {
+            self.calls.push(ToolCall {
+                id: format!("call_{}_{}", chrono::Utc::now().timestamp_millis(), self.calls.len()),
+                call_type: "function".to_string(),
+                function: FunctionCall {
+                    name: String::new(),
+                    arguments: String::new(),
+                },
+            });
+        }
+
+        let tool_call = &mut self.calls[index];
+
+        if let Some(id) = &delta.id {
+            if !id.is_empty() {
+                tool_call.id = id.clone();
+            }
+        }
+
+        if let Some(call_type) = &delta.call_type {
+            tool_call.call_type = call_type.clone();
+        }
+
+        if let Some(function) = &delta.function {
+            if let Some(name) = &function.name {
+                tool_call.function.name = name.clone();
+                events.push(StreamEvent::ToolCallStart {
+                    index,
+                    id: tool_call.id.clone(),
+                    name: name.clone(),
+                });
+            }
+            if let Some(arguments) = &function.arguments {
+                tool_call.function.arguments.push_str(arguments);
+                if !arguments.is_empty() {
+                    events.push(StreamEvent::ToolCallArgsDelta {
+                        index,
+                        delta: arguments.clone(),
+                    });
+                }
+            }
+        }
+
+        events
+    }
+
+    /// Emit a `ToolCallComplete` for every named call. Only the first call to this has any effect.
+    fn finish(&mut self) -> Vec<StreamEvent> {
+        if self.finished {
+            return Vec::new();
+        }
+        self.finished = true;
+
+        self.calls
+            .iter()
+            .enumerate()
+            .filter(|(_, tool_call)| !tool_call.function.name.is_empty())
+            .map(|(index, tool_call)| {
+                // Parse the arguments JSON string
+                let args = if tool_call.function.arguments.trim().is_empty() {
+                    serde_json::json!({})
+                } else {
+                    serde_json::from_str::<serde_json::Value>(&tool_call.function.arguments)
+                        .unwrap_or_else(|_| serde_json::json!({}))
+                };
+                StreamEvent::ToolCallComplete {
+                    index,
+                    id: tool_call.id.clone(),
+                    name: tool_call.function.name.clone(),
+                    args,
+                }
+            })
+            .collect()
+    }
+}
+
 impl OpenAIClient {
     pub fn new(api_key: String, model: String) -> Self {
         Self {
@@ -170,8 +404,14 @@ impl OpenAIClient {
             api_key,
             model,
             base_url: "https://api.openai.com/v1".to_string(),
-            conversation_history: Vec::new(),
-            system_message: None,
+            conversation: Conversation::new(),
+            tools: ToolRegistry::with_builtin_tools(),
+            options: GenerationOptions::default(),
+            last_usage: Arc::new(Mutex::new(None)),
+            retry: RetryPolicy::default(),
+            azure: None,
+            responses_models: None,
+            previous_response: Arc::new(Mutex::new(None)),
         }
     }
 
@@ -180,78 +420,139 @@ impl OpenAIClient {
         self
     }
 
+    /// Talk to an Azure OpenAI resource. `base_url` is then the resource endpoint,
+    /// e.g. `https://my-resource.openai.azure.com`, and the model is the deployment name.
+    pub fn with_azure(mut self, api_version: String) -> Self {
+        self.base_url = self.base_url.trim_end_matches('/').to_string();
+        self.azure = Some(AzureConfig { api_version });
+        self
+    }
+
+    /// Glob patterns over model names that select the Responses API; none keeps every
+    /// model on Chat Completions
+    pub fn with_responses_models(mut self, patterns: &[&str]) -> Result<Self> {
+        self.responses_models = Some(patterns.iter()
+            .map(|pattern| glob::Pattern::new(pattern))
+            .collect::<Result<_, _>>()?);
+        Ok(self)
+    }
+
+    pub fn is_azure(&self) -> bool {
+        self.azure.is_some()
+    }
+
+    fn chat_completions_url(&self) -> String {
+        match &self.azure {
+            Some(azure) => format!(
+                "{}/openai/deployments/{}/chat/completions?api-version={}",
+                self.base_url, self.model, azure.api_version
+            ),
+            None => format!("{}/chat/completions", self.base_url),
+        }
+    }
+
+    /// Add the authentication header the endpoint expects
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        if self.azure.is_some() {
+            request.header("api-key", &self.api_key)
+        } else {
+            request.header("Authorization", format!("Bearer {}", self.api_key))
+        }
+    }
+
+    /// Set the tools offered to the model
+    pub fn with_tools(mut self, tools: ToolRegistry) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    pub fn set_tools(&mut self, tools: ToolRegistry) {
+        self.tools = tools;
+    }
+
+    /// Token usage of the last non-streaming request
+    pub fn last_usage(&self) -> Option<TokenUsage> {
+        self.last_usage.lock().unwrap().clone()
+    }
+
+    /// Retry failed requests according to `retry`
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Set the sampling and length options sent with each request
+    pub fn with_generation_options(mut self, options: GenerationOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn generation_options(&self) -> &GenerationOptions {
+        &self.options
+    }
+
+    pub fn set_generation_options(&mut self, options: GenerationOptions) {
+        self.options = options;
+    }
+
+    pub fn set_model(&mut self, model: &str) {
+        self.model = model.to_string();
+    }
+
     pub fn load_system_prompt(&mut self, prompt_content: &str) -> Result<()> {
-        self.system_message = Some(prompt_content.to_string());
+        self.conversation.system_prompt = Some(prompt_content.to_string());
         Ok(())
     }
 
     pub fn add_user_message(&mut self, message: &str) {
-        self.conversation_history.push(Message {
-            role: "user".to_string(),
-            content: MessageContent::Text(message.to_string()),
-            name: None,
-            tool_calls: None,
-            tool_call_id: None,
-        });
+        self.conversation.push(ChatMessage::user(message));
     }
 
     pub fn add_function_response(&mut self, function_response: &crate::function_calling::FunctionResponse) {
-        self.conversation_history.push(Message {
-            role: "tool".to_string(),
-            content: MessageContent::Text(serde_json::to_string(&function_response.response).unwrap_or_else(|_| "Error serializing response".to_string())),
-            name: Some(function_response.name.clone()),
-            tool_calls: None,
-            tool_call_id: Some(function_response.id.clone()),
-        });
+        self.conversation.push(ChatMessage::tool_result(function_response));
     }
 
-    pub fn add_model_response(&mut self, response: &str, tool_calls: Option<Vec<ToolCall>>) {
-        self.conversation_history.push(Message {
-            role: "assistant".to_string(),
-            content: MessageContent::Text(response.to_string()),
-            name: None,
-            tool_calls,
-            tool_call_id: None,
-        });
+    pub fn add_model_response(&mut self, response: &str, tool_calls: &[crate::function_calling::FunctionCall]) {
+        self.conversation.push(ChatMessage::assistant(response, tool_calls).with_model(&self.model));
     }
 
     pub fn clear_conversation(&mut self) {
-        self.conversation_history.clear();
+        self.conversation.clear();
+        self.previous_response.lock().unwrap().take();
+    }
+
+    pub fn conversation(&self) -> &Conversation {
+        &self.conversation
+    }
+
+    pub fn set_conversation(&mut self, conversation: Conversation) {
+        self.conversation = conversation;
+        self.previous_response.lock().unwrap().take();
     }
 
     fn build_messages(&self, user_message: Option<&str>) -> Vec<Message> {
         let mut messages = Vec::new();
 
         // Add system message if present
-        if let Some(system_msg) = &self.system_message {
-            messages.push(Message {
-                role: "system".to_string(),
-                content: MessageContent::Text(system_msg.clone()),
-                name: None,
-                tool_calls: None,
-                tool_call_id: None,
-            });
+        if let Some(system_msg) = &self.conversation.system_prompt {
+            messages.push(Message::text("system", system_msg));
         }
 
         // Add conversation history
-        messages.extend(self.conversation_history.clone());
+        for message in self.conversation.messages() {
+            messages.extend(to_wire_messages(message));
+        }
 
         // Add new user message if provided
         if let Some(msg) = user_message {
-            messages.push(Message {
-                role: "user".to_string(),
-                content: MessageContent::Text(msg.to_string()),
-                name: None,
-                tool_calls: None,
-                tool_call_id: None,
-            });
+            messages.push(Message::text("user", msg));
         }
 
         messages
     }
 
     fn build_tools(&self) -> Option<Vec<Tool>> {
-        let available_tools = crate::function_calling::FunctionExecutor::get_available_tools();
+        let available_tools = self.tools.definitions();
         if available_tools.is_empty() {
             return None;
         }
@@ -271,23 +572,34 @@ impl OpenAIClient {
         )
     }
 
-    #[allow(dead_code)]
-    pub async fn send_message(&self, message: &str) -> Result<String> {
-        let url = format!("{}/chat/completions", self.base_url);
-
-        let messages = self.build_messages(if message.is_empty() {None} else{Some(message)});
-        let tools = self.build_tools();
-
-        let request = ChatCompletionRequest {
+    /// A Chat Completions request with the generation options mapped onto it; top_k has no equivalent
+    fn build_request(&self, message: &str, stream: bool) -> ChatCompletionRequest {
+        let options = &self.options;
+        ChatCompletionRequest {
             model: self.model.clone(),
-            messages,
-            temperature: Some(0.7),
-            top_p: Some(0.95),
-            max_tokens: Some(2048),
-            stream: Some(false),
-            tools,
+            messages: self.build_messages(if message.is_empty() { None } else { Some(message) }),
+            temperature: options.temperature,
+            top_p: options.top_p,
+            max_tokens: options.max_tokens,
+            stop: options.stop.clone(),
+            seed: options.seed,
+            presence_penalty: options.presence_penalty,
+            frequency_penalty: options.frequency_penalty,
+            reasoning_effort: options.reasoning_effort.map(|effort| effort.as_str().to_string()),
+            stream: Some(stream),
+            stream_options: stream.then_some(StreamOptions { include_usage: true }),
+            tools: self.build_tools(),
             tool_choice: None,
-        };
+        }
+    }
+
+    #[allow(dead_code)]
+    pub async fn send_message(&self, message: &str) -> Result<String> {
+        if self.uses_responses_api() {
+            return self.send_responses_message(message).await;
+        }
+        let url = self.chat_completions_url();
+        let request = self.build_request(message, false);
 
         // Log the request payload
         if let Ok(request_json) = serde_json::to_string_pretty(&request) {
@@ -303,21 +615,14 @@ impl OpenAIClient {
             }
         }
 
-        let response = self
-            .client
-            .post(&url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(anyhow!("API request failed: {}", error_text));
-        }
+        let response = self.retry.send(|| {
+            self.authorize(self.client.post(&url))
+                .header("Content-Type", "application/json")
+                .json(&request)
+        }).await?;
 
         let api_response: ChatCompletionResponse = response.json().await?;
+        *self.last_usage.lock().unwrap() = api_response.usage.as_ref().map(TokenUsage::from);
 
         if api_response.choices.is_empty() {
             return Err(anyhow!("No response from API"));
@@ -330,7 +635,7 @@ impl OpenAIClient {
                 // Concatenate all text parts
                 let text = parts
                     .iter()
-                    .map(|part| part.text.as_str())
+                    .filter_map(|part| part.text.as_deref())
                     .collect::<Vec<_>>()
                     .join("");
                 Ok(text)
@@ -338,22 +643,12 @@ impl OpenAIClient {
         }
     }
 
-    pub async fn send_message_stream(&self, message: &str) -> Result<mpsc::Receiver<(String, Option<serde_json::Value>)>> {
-        let url = format!("{}/chat/completions", self.base_url);
-
-        let messages = self.build_messages(if message.is_empty() {None} else{Some(message)});
-        let tools = self.build_tools();
-
-        let request = ChatCompletionRequest {
-            model: self.model.clone(),
-            messages,
-            temperature: Some(0.7),
-            top_p: Some(0.95),
-            max_tokens: Some(2048),
-            stream: Some(true),
-            tools,
-            tool_choice: None,
-        };
+    pub async fn send_message_stream(&self, message: &str) -> Result<mpsc::Receiver<StreamEvent>> {
+        if self.uses_responses_api() {
+            return self.send_responses_stream(message).await;
+        }
+        let url = self.chat_completions_url();
+        let request = self.build_request(message, true);
 
         // Log the request payload
         if let Ok(request_json) = serde_json::to_string_pretty(&request) {
@@ -369,21 +664,13 @@ impl OpenAIClient {
             }
         }
 
-        let response = self
-            .client
-            .post(&url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(anyhow!("API request failed: {}", error_text));
-        }
+        let response = self.retry.send(|| {
+            self.authorize(self.client.post(&url))
+                .header("Content-Type", "application/json")
+                .json(&request)
+        }).await?;
 
-        let (tx, rx) = mpsc::channel::<(String, Option<serde_json::Value>)>(1000);
+        let (tx, rx) = mpsc::channel::<StreamEvent>(1000);
         
         // Add logging function
         let log_debug = |msg: &str| {
@@ -399,11 +686,11 @@ impl OpenAIClient {
         
         log_debug("Starting OpenAI streaming task");
         
-        tokio::spawn(async move {
+        spawn_stream(tx, move |tx| async move {
             let mut stream = response.bytes_stream();
             let mut buffer = String::new();
             let mut chunk_count = 0;
-            let mut current_tool_calls: Vec<ToolCall> = Vec::new();
+            let mut tool_calls_in_progress = ToolCallAccumulator::default();
             
             log_debug("Stream created, starting to read chunks");
             
@@ -427,8 +714,7 @@ impl OpenAIClient {
                                 // Parse SSE event - look for data lines
                                 for line in event.lines() {
                                     let line = line.trim();
-                                    if line.starts_with("data: ") {
-                                        let json_data = &line[6..]; // Remove "data: " prefix
+                                    if let Some(json_data) = line.strip_prefix("data: ") {
                                         log_debug(&format!("Found data line: {:?}", json_data));
                                         
                                         // Skip empty data or [DONE] messages
@@ -442,108 +728,56 @@ impl OpenAIClient {
                                         match serde_json::from_str::<ChatCompletionChunk>(json_data) {
                                             Ok(chunk_data) => {
                                                 log_debug(&format!("Successfully parsed JSON, choices: {}", chunk_data.choices.len()));
+                                                let mut events = Vec::new();
+                                                
+                                                if let Some(usage) = &chunk_data.usage {
+                                                    events.push(StreamEvent::Usage(usage.into()));
+                                                }
+                                                
                                                 if !chunk_data.choices.is_empty() {
                                                     let choice = &chunk_data.choices[0];
                                                     let delta = &choice.delta;
                                                     
-                                                    let mut text_content = String::new();
-                                                    let mut function_call = None;
-                                                    
                                                     // Handle text content
                                                     if let Some(content) = &delta.content {
-                                                        text_content = content.clone();
+                                                        if !content.is_empty() {
+                                                            events.push(StreamEvent::TextDelta(content.clone()));
+                                                        }
                                                     }
                                                     
                                                     // Handle tool calls
                                                     if let Some(tool_calls) = &delta.tool_calls {
                                                         for delta_tool_call in tool_calls {
-                                                            let index = delta_tool_call.index.unwrap_or(0) as usize;
-                                                            
-                                                            // Ensure we have enough space in the vector
-                                                            while current_tool_calls.len() <= index {
-                                                                current_tool_calls.push(ToolCall {
-                                                                    id: format!("call_{}", chrono::Utc::now().timestamp_millis()),
-                                                                    call_type: "function".to_string(),
-                                                                    function: FunctionCall {
-                                                                        name: String::new(),
-                                                                        arguments: String::new(),
-                                                                    },
-                                                                });
-                                                            }
-                                                            
-                                                            let tool_call = &mut current_tool_calls[index];
-                                                            
-                                                            if let Some(id) = &delta_tool_call.id {
-                                                                if !id.is_empty() {
-                                                                    tool_call.id = id.clone();
-                                                                }
-                                                            }
-                                                            
-                                                            if let Some(call_type) = &delta_tool_call.call_type {
-                                                                tool_call.call_type = call_type.clone();
-                                                            }
-                                                            
-                                                            if let Some(function) = &delta_tool_call.function {
-                                                                if let Some(name) = &function.name {
-                                                                    tool_call.function.name = name.clone();
-                                                                }
-                                                                if let Some(arguments) = &function.arguments {
-                                                                    tool_call.function.arguments.push_str(arguments);
-                                                                }
-                                                            }
+                                                            events.extend(tool_calls_in_progress.apply_delta(delta_tool_call));
                                                         }
                                                     }
                                                     
-                                                    // Check if we have complete tool calls to send
-                                                    if choice.finish_reason.as_deref() == Some("tool_calls") {
-                                                        // Convert to the format expected by the function calling system
-                                                        for tool_call in &current_tool_calls {
-                                                            if !tool_call.function.name.is_empty() {
-                                                                // Parse the arguments JSON string
-                                                                let args = if tool_call.function.arguments.is_empty() {
-                                                                    serde_json::json!({})
-                                                                } else {
-                                                                    match serde_json::from_str::<serde_json::Value>(&tool_call.function.arguments) {
-                                                                        Ok(parsed) => parsed,
-                                                                        Err(_) => {
-                                                                            log_debug(&format!("Failed to parse tool call arguments: {}", tool_call.function.arguments));
-                                                                            serde_json::json!({})
-                                                                        }
-                                                                    }
-                                                                };
-                                                                
-                                                                function_call = Some(serde_json::json!({
-                                                                    "name": tool_call.function.name,
-                                                                    "args": args
-                                                                }));
-                                                                
-                                                                log_debug(&format!("OPENAI: Created function call: {:?}", function_call));
-                                                                break; // Only send the first tool call for now
-                                                            }
-                                                        }
+                                                    // Any finish reason closes the pending tool calls; some
+                                                    // OpenAI-compatible servers report "stop" even after tool calls
+                                                    if choice.finish_reason.is_some() {
+                                                        events.extend(tool_calls_in_progress.finish());
                                                     }
                                                     
-                                                    if !text_content.is_empty() || function_call.is_some() {
-                                                        log_debug(&format!("OPENAI: Sending content to channel: text={:?}, function_call={:?}", text_content, function_call));
-                                                        match tx.send((text_content, function_call)).await {
-                                                            Ok(_) => {
-                                                                log_debug("OPENAI: Content sent successfully to channel");
-                                                            }
-                                                            Err(e) => {
-                                                                log_debug(&format!("OPENAI: Receiver dropped, stopping stream: {}", e));
-                                                                return; // Receiver dropped
-                                                            }
-                                                        }
+                                                    if let Some(reason) = &choice.finish_reason {
+                                                        events.push(StreamEvent::FinishReason(reason.clone()));
                                                     }
                                                 } else {
                                                     log_debug("No choices in chunk");
                                                 }
+                                                
+                                                for event in events {
+                                                    log_debug(&format!("OPENAI: Sending event to channel: {:?}", event));
+                                                    if let Err(e) = tx.send(event).await {
+                                                        log_debug(&format!("OPENAI: Receiver dropped, stopping stream: {}", e));
+                                                        return; // Receiver dropped
+                                                    }
+                                                }
                                             }
                                             Err(e) => {
                                                 // Send error message for debugging
                                                 let error_msg = format!("JSON parse error: {} - Data: {}", e, json_data);
                                                 log_debug(&format!("JSON parse error: {}", error_msg));
-                                                if tx.send((error_msg, None)).await.is_err() {
+                                                if tx.send(StreamEvent::Error(error_msg)).await.is_err() {
                                                     log_debug("Failed to send error message, receiver dropped");
                                                     return;
                                                 }
@@ -558,12 +792,19 @@ impl OpenAIClient {
                         // Send error message
                         let error_msg = format!("Stream error: {}", e);
                         log_debug(&format!("Stream error: {}", error_msg));
-                        let _ = tx.send((error_msg, None)).await;
+                        let _ = tx.send(StreamEvent::Error(error_msg)).await;
                         break;
                     }
                 }
             }
             
+            // Flush tool calls from streams that ended without a finish reason
+            for event in tool_calls_in_progress.finish() {
+                if tx.send(event).await.is_err() {
+                    return;
+                }
+            }
+            
             log_debug("Stream processing completed");
             // Explicitly drop the sender to signal completion
             drop(tx);
@@ -587,18 +828,7 @@ impl crate::chat_client::ChatClient for OpenAIClient {
         self.add_function_response(function_response)
     }
     
-    fn add_model_response(&mut self, response: &str, function_call: Option<serde_json::Value>) {
-        // Convert function call format for OpenAI
-        let tool_calls = function_call.map(|fc| {
-            vec![ToolCall {
-                id: format!("call_{}", chrono::Utc::now().timestamp_millis()),
-                call_type: "function".to_string(),
-                function: FunctionCall {
-                    name: fc.get("name").and_then(|v| v.as_str()).unwrap_or("unknown").to_string(),
-                    arguments: fc.get("args").map(|v| v.to_string()).unwrap_or_default(),
-                },
-            }]
-        });
+    fn add_model_response(&mut self, response: &str, tool_calls: &[crate::function_calling::FunctionCall]) {
         self.add_model_response(response, tool_calls)
     }
     
@@ -606,15 +836,179 @@ impl crate::chat_client::ChatClient for OpenAIClient {
         self.clear_conversation()
     }
     
+    fn set_tools(&mut self, tools: ToolRegistry) {
+        self.set_tools(tools)
+    }
+    
     async fn send_message(&self, message: &str) -> Result<String> {
         self.send_message(message).await
     }
     
-    async fn send_message_stream(&self, message: &str) -> Result<mpsc::Receiver<(String, Option<serde_json::Value>)>> {
+    async fn send_message_stream(&self, message: &str) -> Result<mpsc::Receiver<StreamEvent>> {
         self.send_message_stream(message).await
     }
     
     fn client_name(&self) -> &str {
-        "OpenAI"
+        if self.is_azure() { "Azure OpenAI" } else { "OpenAI" }
+    }
+    
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn set_model(&mut self, model: &str) {
+        self.set_model(model)
+    }
+    
+    fn conversation(&self) -> Conversation {
+        self.conversation.clone()
+    }
+    
+    fn set_conversation(&mut self, conversation: Conversation) {
+        self.set_conversation(conversation)
     }
-}
\ No newline at end of file
+
+    fn last_usage(&self) -> Option<TokenUsage> {
+        self.last_usage()
+    }
+
+    fn generation_options(&self) -> &GenerationOptions {
+        self.generation_options()
+    }
+
+    fn set_generation_options(&mut self, options: GenerationOptions) {
+        self.set_generation_options(options)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(json: &str) -> ChatCompletionChunk {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_generation_options_map_onto_request() {
+        let client = OpenAIClient::new("key".to_string(), "gpt-4o".to_string());
+        let request = serde_json::to_value(client.build_request("hi", false)).unwrap();
+        assert!(request.get("temperature").is_none());
+        assert!(request.get("max_tokens").is_none());
+
+        let mut options = GenerationOptions::new();
+        options.set("temperature", "0.2").unwrap();
+        options.set("max_tokens", "8192").unwrap();
+        options.set("stop", "END").unwrap();
+        options.set("top_k", "40").unwrap();
+        options.set("reasoning_effort", "low").unwrap();
+        let client = client.with_generation_options(options);
+        let request = serde_json::to_value(client.build_request("hi", false)).unwrap();
+        assert_eq!(request["temperature"], serde_json::json!(0.2f32));
+        assert_eq!(request["max_tokens"], 8192);
+        assert_eq!(request["stop"], serde_json::json!(["END"]));
+        assert_eq!(request["reasoning_effort"], "low");
+        assert!(request.get("top_k").is_none());
+    }
+
+    #[test]
+    fn test_accumulates_parallel_tool_calls_with_api_ids() {
+        let chunks = [
+            r#"{"id":"c","object":"chat.completion.chunk","created":0,"model":"gpt-4o","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"id":"call_abc","type":"function","function":{"name":"read_file","arguments":""}}]}}]}"#,
+            r#"{"id":"c","object":"chat.completion.chunk","created":0,"model":"gpt-4o","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"{\"path\":"}}]}}]}"#,
+            r#"{"id":"c","object":"chat.completion.chunk","created":0,"model":"gpt-4o","choices":[{"index":0,"delta":{"tool_calls":[{"index":1,"id":"call_def","type":"function","function":{"name":"list_directory","arguments":"{}"}}]}}]}"#,
+            r#"{"id":"c","object":"chat.completion.chunk","created":0,"model":"gpt-4o","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"\"a.txt\"}"}}]}}]}"#,
+        ];
+
+        let mut accumulator = ToolCallAccumulator::default();
+        for json in chunks {
+            for delta in chunk(json).choices[0].delta.tool_calls.as_ref().unwrap() {
+                accumulator.apply_delta(delta);
+            }
+        }
+
+        let completed = accumulator.finish();
+        assert_eq!(completed, vec![
+            StreamEvent::ToolCallComplete {
+                index: 0,
+                id: "call_abc".to_string(),
+                name: "read_file".to_string(),
+                args: serde_json::json!({"path": "a.txt"}),
+            },
+            StreamEvent::ToolCallComplete {
+                index: 1,
+                id: "call_def".to_string(),
+                name: "list_directory".to_string(),
+                args: serde_json::json!({}),
+            },
+        ]);
+
+        // Completion is only reported once, even if the stream also ends afterwards
+        assert!(accumulator.finish().is_empty());
+    }
+
+    #[test]
+    fn test_model_response_keeps_tool_call_ids() {
+        let mut client = OpenAIClient::new("key".to_string(), "gpt-4o".to_string());
+        let calls = vec![
+            crate::function_calling::FunctionCall {
+                id: Some("call_abc".to_string()),
+                name: "read_file".to_string(),
+                args: serde_json::json!({"path": "a.txt"}),
+            },
+            crate::function_calling::FunctionCall {
+                id: Some("call_def".to_string()),
+                name: "read_file".to_string(),
+                args: serde_json::json!({"path": "b.txt"}),
+            },
+        ];
+        crate::chat_client::ChatClient::add_model_response(&mut client, "", &calls);
+
+        let tool_calls = client.build_messages(None)[0].tool_calls.clone().unwrap();
+        let ids: Vec<_> = tool_calls.iter().map(|tc| tc.id.as_str()).collect();
+        assert_eq!(ids, ["call_abc", "call_def"]);
+    }
+
+    #[test]
+    fn test_conversation_translates_to_chat_messages() {
+        let mut client = OpenAIClient::new("key".to_string(), "gpt-4".to_string());
+        client.load_system_prompt("Be brief.").unwrap();
+        let mut question = ChatMessage::user("What is this?");
+        question.parts.push(MessagePart::Image { mime_type: "image/png".to_string(), data: "aGk=".to_string() });
+        client.conversation.push(question);
+        client.add_function_response(&crate::function_calling::FunctionResponse {
+            id: "call_abc".to_string(),
+            name: "shell_command".to_string(),
+            response: serde_json::json!({"success": true}),
+        });
+
+        let messages = serde_json::to_value(client.build_messages(None)).unwrap();
+
+        assert_eq!(messages, serde_json::json!([
+            {"role": "system", "content": "Be brief."},
+            {"role": "user", "content": [
+                {"type": "text", "text": "What is this?"},
+                {"type": "image_url", "image_url": {"url": "data:image/png;base64,aGk="}}
+            ]},
+            {"role": "tool", "content": "{\"success\":true}", "name": "shell_command", "tool_call_id": "call_abc"}
+        ]));
+    }
+
+    #[tokio::test]
+    async fn test_azure_mode_uses_deployment_url_and_api_key_header() {
+        let body = serde_json::json!({
+            "id": "c", "object": "chat.completion", "created": 0, "model": "gpt-4o",
+            "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hi from Azure"}, "finish_reason": "stop"}]
+        }).to_string();
+        let (url, server) = crate::test_support::serve_once("application/json", body).await;
+        let client = OpenAIClient::new("azure-key".to_string(), "my-gpt4o".to_string())
+            .with_base_url(format!("{}/", url))
+            .with_azure("2024-10-21".to_string());
+
+        assert_eq!(client.send_message("Hello").await.unwrap(), "Hi from Azure");
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with("POST /openai/deployments/my-gpt4o/chat/completions?api-version=2024-10-21 "), "{}", request);
+        assert!(request.contains("api-key: azure-key"));
+        assert!(!request.to_lowercase().contains("authorization:"));
+    }
+}
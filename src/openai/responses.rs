@@ -0,0 +1,547 @@
+//! The Responses API backend (`/v1/responses`) of `OpenAIClient`.
+//!
+//! Requests are chained with `previous_response_id`: the server keeps earlier turns,
+//! including reasoning items, so only the messages added since the last response are sent.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use futures::stream::StreamExt;
+use tokio::sync::mpsc;
+use crate::chat_client::{spawn_stream, take_until, StreamEvent, TokenUsage};
+use crate::conversation::{ChatMessage, MessagePart, Role};
+use super::OpenAIClient;
+
+/// Models sent to the Responses API unless `OPENAI_RESPONSES_MODELS` says otherwise.
+/// Only used against `api.openai.com`: compatible servers rarely serve `/responses`.
+pub const DEFAULT_RESPONSES_MODELS: &[&str] = &["o1*", "o3*", "o4*", "gpt-5*"];
+
+/// The last response and how much of the conversation it covers
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct PreviousResponse {
+    pub id: String,
+    /// Number of messages in the conversation when the request was sent; the response
+    /// itself is expected to be recorded as the next message
+    pub covered: usize,
+}
+
+/// An item of the request's `input`
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum InputItem {
+    Message {
+        role: String,
+        content: Vec<InputContent>,
+    },
+    FunctionCall {
+        call_id: String,
+        name: String,
+        arguments: String,
+    },
+    FunctionCallOutput {
+        call_id: String,
+        output: String,
+    },
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum InputContent {
+    InputText { text: String },
+    InputImage { image_url: String },
+    OutputText { text: String },
+}
+
+/// Translate a conversation message to input items. Tool calls and their results are items
+/// of their own rather than parts of a message; system messages go to `instructions`.
+fn to_input_items(message: &ChatMessage) -> Vec<InputItem> {
+    let mut items = Vec::new();
+    let mut content = Vec::new();
+    for part in &message.parts {
+        match part {
+            MessagePart::Text { text } if text.is_empty() => {}
+            MessagePart::Text { text } if message.role == Role::Assistant => {
+                content.push(InputContent::OutputText { text: text.clone() });
+            }
+            MessagePart::Text { text } => content.push(InputContent::InputText { text: text.clone() }),
+            MessagePart::Image { mime_type, data } => content.push(InputContent::InputImage {
+                image_url: format!("data:{};base64,{}", mime_type, data),
+            }),
+            MessagePart::ToolCall(call) => items.push(InputItem::FunctionCall {
+                call_id: call.call_id(),
+                name: call.name.clone(),
+                arguments: call.args.to_string(),
+            }),
+            MessagePart::ToolResult(result) => items.push(InputItem::FunctionCallOutput {
+                call_id: result.id.clone(),
+                output: serde_json::to_string(&result.response).unwrap_or_else(|_| "Error serializing response".to_string()),
+            }),
+        }
+    }
+
+    let role = match message.role {
+        Role::System | Role::Tool => None,
+        Role::User => Some("user"),
+        Role::Assistant => Some("assistant"),
+    };
+    if let (Some(role), false) = (role, content.is_empty()) {
+        // The message text comes before the calls it announces
+        items.insert(0, InputItem::Message { role: role.to_string(), content });
+    }
+    items
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct ResponsesRequest {
+    model: String,
+    input: Vec<InputItem>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instructions: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous_response_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<Tool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning: Option<Reasoning>,
+    stream: bool,
+}
+
+/// Reasoning settings; asking for a summary streams `response.reasoning_summary_text.delta`
+#[derive(Debug, Serialize)]
+struct Reasoning {
+    effort: String,
+    summary: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Tool {
+    #[serde(rename = "type")]
+    tool_type: String,
+    name: String,
+    description: String,
+    parameters: serde_json::Value,
+}
+
+/// An item of a response's `output`
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OutputItem {
+    Message {
+        #[serde(default)]
+        content: Vec<OutputContent>,
+    },
+    FunctionCall {
+        call_id: String,
+        name: String,
+        #[serde(default)]
+        arguments: String,
+    },
+    /// Reasoning, web search calls and other items this client passes over
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OutputContent {
+    OutputText { text: String },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponseObject {
+    id: String,
+    #[serde(default)]
+    output: Vec<OutputItem>,
+    #[serde(default)]
+    usage: Option<Usage>,
+    #[serde(default)]
+    error: Option<ApiError>,
+    #[serde(default)]
+    incomplete_details: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Usage {
+    #[serde(default)]
+    input_tokens: u32,
+    #[serde(default)]
+    output_tokens: u32,
+    #[serde(default)]
+    total_tokens: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    #[serde(default)]
+    message: String,
+}
+
+// Streaming event structures; every event names its type, as does the SSE `event:` line
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum ResponsesEvent {
+    #[serde(rename = "response.created")]
+    Created { response: ResponseObject },
+    #[serde(rename = "response.output_item.added")]
+    OutputItemAdded { output_index: usize, item: OutputItem },
+    #[serde(rename = "response.output_text.delta")]
+    OutputTextDelta { delta: String },
+    #[serde(rename = "response.function_call_arguments.delta")]
+    FunctionCallArgumentsDelta { output_index: usize, delta: String },
+    #[serde(rename = "response.output_item.done")]
+    OutputItemDone { output_index: usize, item: OutputItem },
+    #[serde(rename = "response.reasoning_summary_text.delta")]
+    ReasoningSummaryTextDelta { delta: String },
+    #[serde(rename = "response.completed")]
+    Completed { response: ResponseObject },
+    #[serde(rename = "response.incomplete")]
+    Incomplete { response: ResponseObject },
+    #[serde(rename = "response.failed")]
+    Failed { response: ResponseObject },
+    #[serde(rename = "error")]
+    Error { message: String },
+    #[serde(other)]
+    Other,
+}
+
+/// Turns Responses API events into `StreamEvent`s. Function calls are numbered from zero
+/// in the order they start, whatever their position among the output items.
+#[derive(Default)]
+struct StreamDecoder {
+    /// Output index of each function call, in order
+    tool_calls: Vec<usize>,
+    response_id: Option<String>,
+}
+
+impl StreamDecoder {
+    fn tool_index(&self, output_index: usize) -> Option<usize> {
+        self.tool_calls.iter().position(|&i| i == output_index)
+    }
+
+    fn decode(&mut self, event: ResponsesEvent) -> Vec<StreamEvent> {
+        match event {
+            ResponsesEvent::Created { response } => {
+                self.response_id = Some(response.id);
+                Vec::new()
+            }
+            ResponsesEvent::OutputItemAdded { output_index, item: OutputItem::FunctionCall { call_id, name, .. } } => {
+                self.tool_calls.push(output_index);
+                vec![StreamEvent::ToolCallStart { index: self.tool_calls.len() - 1, id: call_id, name }]
+            }
+            ResponsesEvent::OutputTextDelta { delta } if !delta.is_empty() => vec![StreamEvent::TextDelta(delta)],
+            ResponsesEvent::FunctionCallArgumentsDelta { output_index, delta } => match self.tool_index(output_index) {
+                Some(index) if !delta.is_empty() => vec![StreamEvent::ToolCallArgsDelta { index, delta }],
+                _ => Vec::new(),
+            },
+            ResponsesEvent::OutputItemDone { output_index, item: OutputItem::FunctionCall { call_id, name, arguments } } => {
+                let index = match self.tool_index(output_index) {
+                    Some(index) => index,
+                    None => {
+                        self.tool_calls.push(output_index);
+                        self.tool_calls.len() - 1
+                    }
+                };
+                let args = serde_json::from_str(&arguments).unwrap_or_else(|_| serde_json::json!({}));
+                vec![StreamEvent::ToolCallComplete { index, id: call_id, name, args }]
+            }
+            ResponsesEvent::ReasoningSummaryTextDelta { delta } if !delta.is_empty() => vec![StreamEvent::ReasoningDelta(delta)],
+            ResponsesEvent::Completed { response } | ResponsesEvent::Incomplete { response } => {
+                self.response_id = Some(response.id.clone());
+                let mut events = Vec::new();
+                if let Some(usage) = response.usage {
+                    events.push(StreamEvent::Usage(TokenUsage {
+                        prompt_tokens: usage.input_tokens,
+                        completion_tokens: usage.output_tokens,
+                        total_tokens: usage.total_tokens,
+                    }));
+                }
+                let reason = match response.incomplete_details {
+                    Some(details) => details.get("reason").and_then(|r| r.as_str()).unwrap_or("incomplete").to_string(),
+                    None if !self.tool_calls.is_empty() => "tool_calls".to_string(),
+                    None => "stop".to_string(),
+                };
+                events.push(StreamEvent::FinishReason(reason));
+                events
+            }
+            ResponsesEvent::Failed { response } => {
+                let message = response.error.map(|e| e.message).unwrap_or_else(|| "Response failed".to_string());
+                vec![StreamEvent::Error(message)]
+            }
+            ResponsesEvent::Error { message } => vec![StreamEvent::Error(message)],
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl OpenAIClient {
+    /// Whether requests for the current model go to the Responses API
+    pub fn uses_responses_api(&self) -> bool {
+        // Azure serves the Responses API under a different route
+        if self.azure.is_some() {
+            return false;
+        }
+        match &self.responses_models {
+            Some(patterns) => patterns.iter().any(|pattern| pattern.matches(&self.model)),
+            None => self.is_openai_api() && DEFAULT_RESPONSES_MODELS.iter()
+                .any(|pattern| glob::Pattern::new(pattern).is_ok_and(|pattern| pattern.matches(&self.model))),
+        }
+    }
+
+    /// Whether the base URL is OpenAI's own API rather than a compatible server
+    fn is_openai_api(&self) -> bool {
+        reqwest::Url::parse(&self.base_url).is_ok_and(|url| url.host_str() == Some("api.openai.com"))
+    }
+
+    /// The conversation since the last response, if it can be chained onto it.
+    /// The message right after the covered ones must be that response's answer.
+    fn unsent_messages(&self) -> Option<(String, &[ChatMessage])> {
+        let previous = self.previous_response.lock().unwrap().clone()?;
+        let messages = self.conversation.messages();
+        match messages.get(previous.covered) {
+            Some(answer) if answer.role == Role::Assistant => Some((previous.id, &messages[previous.covered + 1..])),
+            _ => None,
+        }
+    }
+
+    pub(super) fn build_responses_request(&self, user_message: Option<&str>, stream: bool) -> ResponsesRequest {
+        let (previous_response_id, messages) = match self.unsent_messages() {
+            Some((id, messages)) => (Some(id), messages),
+            None => (None, self.conversation.messages()),
+        };
+
+        let new_message = user_message.map(ChatMessage::user);
+        let input = messages.iter()
+            .chain(new_message.as_ref())
+            .flat_map(to_input_items)
+            .collect();
+
+        // Instructions are not carried over by previous_response_id, so they go with every request
+        let instructions: Vec<String> = self.conversation.system_prompt.iter()
+            .cloned()
+            .chain(self.conversation.messages().iter().filter(|m| m.role == Role::System).map(|m| m.text()))
+            .collect();
+
+        let tools = self.tools.definitions();
+        ResponsesRequest {
+            model: self.model.clone(),
+            input,
+            instructions: if instructions.is_empty() { None } else { Some(instructions.join("\n\n")) },
+            previous_response_id,
+            tools: if tools.is_empty() {
+                None
+            } else {
+                Some(tools.into_iter()
+                    .map(|tool| Tool {
+                        tool_type: "function".to_string(),
+                        name: tool.name,
+                        description: tool.description,
+                        parameters: tool.parameters,
+                    })
+                    .collect())
+            },
+            // The Responses API has no top_k, stop sequences, seed or penalties
+            temperature: self.options.temperature,
+            top_p: self.options.top_p,
+            max_output_tokens: self.options.max_tokens,
+            reasoning: self.options.reasoning_effort.map(|effort| Reasoning {
+                effort: effort.as_str().to_string(),
+                summary: "auto".to_string(),
+            }),
+            stream,
+        }
+    }
+
+    async fn post_responses(&self, request: &ResponsesRequest) -> Result<reqwest::Response> {
+        let response = self.retry.send(|| {
+            self.authorize(self.client.post(format!("{}/responses", self.base_url)))
+                .header("Content-Type", "application/json")
+                .json(request)
+        }).await;
+
+        // A response that expired or was never stored cannot be chained onto
+        if response.is_err() && request.previous_response_id.is_some() {
+            self.previous_response.lock().unwrap().take();
+        }
+        response
+    }
+
+    pub(super) async fn send_responses_message(&self, message: &str) -> Result<String> {
+        let user_message = if message.is_empty() { None } else { Some(message) };
+        let covered = self.conversation.len();
+        let request = self.build_responses_request(user_message, false);
+        let response: ResponseObject = self.post_responses(&request).await?.json().await?;
+
+        *self.previous_response.lock().unwrap() = Some(PreviousResponse { id: response.id, covered });
+        *self.last_usage.lock().unwrap() = response.usage.map(|usage| TokenUsage {
+            prompt_tokens: usage.input_tokens,
+            completion_tokens: usage.output_tokens,
+            total_tokens: usage.total_tokens,
+        });
+        Ok(response.output
+            .iter()
+            .flat_map(|item| match item {
+                OutputItem::Message { content } => content.as_slice(),
+                _ => &[],
+            })
+            .filter_map(|content| match content {
+                OutputContent::OutputText { text } => Some(text.as_str()),
+                OutputContent::Other => None,
+            })
+            .collect())
+    }
+
+    pub(super) async fn send_responses_stream(&self, message: &str) -> Result<mpsc::Receiver<StreamEvent>> {
+        let user_message = if message.is_empty() { None } else { Some(message) };
+        let covered = self.conversation.len();
+        let request = self.build_responses_request(user_message, true);
+        let response = self.post_responses(&request).await?;
+        let previous_response = self.previous_response.clone();
+        let (tx, rx) = mpsc::channel::<StreamEvent>(1000);
+
+        spawn_stream(tx, move |tx| async move {
+            let mut stream = response.bytes_stream();
+            let mut buffer = Vec::new();
+            let mut decoder = StreamDecoder::default();
+
+            while let Some(chunk_result) = stream.next().await {
+                let chunk = match chunk_result {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        let _ = tx.send(StreamEvent::Error(format!("Stream error: {}", e))).await;
+                        return;
+                    }
+                };
+                buffer.extend_from_slice(&chunk);
+
+                while let Some(event) = take_until(&mut buffer, b"\n\n") {
+
+                    for json_data in event.lines().filter_map(|line| line.trim().strip_prefix("data:")) {
+                        let events = match serde_json::from_str::<ResponsesEvent>(json_data.trim()) {
+                            Ok(event) => decoder.decode(event),
+                            Err(e) => vec![StreamEvent::Error(format!("JSON parse error: {} - Data: {}", e, json_data))],
+                        };
+                        // Later requests build on this response once it is finished
+                        let finished = events.iter().any(|e| matches!(e, StreamEvent::FinishReason(_)));
+                        if let (true, Some(id)) = (finished, &decoder.response_id) {
+                            *previous_response.lock().unwrap() = Some(PreviousResponse { id: id.clone(), covered });
+                        }
+                        for event in events {
+                            if tx.send(event).await.is_err() {
+                                return; // Receiver dropped
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::function_calling::{FunctionCall, FunctionResponse};
+    use crate::test_support::serve_once;
+
+    fn sse(events: &[serde_json::Value]) -> String {
+        events.iter()
+            .map(|event| format!("event: {}\ndata: {}\n\n", event["type"].as_str().unwrap(), event))
+            .collect()
+    }
+
+    #[test]
+    fn test_models_select_the_responses_api() {
+        let mut client = OpenAIClient::new("key".to_string(), "o4-mini".to_string());
+        assert!(client.uses_responses_api());
+        client.set_model("gpt-4o");
+        assert!(!client.uses_responses_api());
+
+        let client = client.with_responses_models(&["gpt-4o"]).unwrap();
+        assert!(client.uses_responses_api());
+        assert!(!client.with_azure("2024-10-21".to_string()).uses_responses_api());
+    }
+
+    #[test]
+    fn test_compatible_servers_keep_chat_completions() {
+        let client = OpenAIClient::new("key".to_string(), "gpt-5".to_string())
+            .with_base_url("http://localhost:8000/v1".to_string());
+        assert!(!client.uses_responses_api());
+
+        let client = client.with_responses_models(&["gpt-5*"]).unwrap();
+        assert!(client.uses_responses_api());
+    }
+
+    #[tokio::test]
+    async fn test_streams_typed_events_and_chains_the_next_request() {
+        let body = sse(&[
+            serde_json::json!({"type": "response.created", "response": {"id": "resp_1", "output": []}}),
+            serde_json::json!({"type": "response.output_item.added", "output_index": 0, "item": {"type": "reasoning", "id": "rs_1", "summary": []}}),
+            serde_json::json!({"type": "response.reasoning_summary_text.delta", "item_id": "rs_1", "output_index": 0, "summary_index": 0, "delta": "Need the file."}),
+            serde_json::json!({"type": "response.output_item.added", "output_index": 1, "item": {"type": "message", "id": "msg_1", "role": "assistant", "content": []}}),
+            serde_json::json!({"type": "response.output_text.delta", "item_id": "msg_1", "output_index": 1, "content_index": 0, "delta": "Reading."}),
+            serde_json::json!({"type": "response.output_item.added", "output_index": 2, "item": {"type": "function_call", "id": "fc_1", "call_id": "call_1", "name": "read_file", "arguments": ""}}),
+            serde_json::json!({"type": "response.function_call_arguments.delta", "item_id": "fc_1", "output_index": 2, "delta": "{\"path\":\"a.txt\"}"}),
+            serde_json::json!({"type": "response.output_item.done", "output_index": 2, "item": {"type": "function_call", "id": "fc_1", "call_id": "call_1", "name": "read_file", "arguments": "{\"path\":\"a.txt\"}"}}),
+            serde_json::json!({"type": "response.completed", "response": {"id": "resp_1", "output": [], "usage": {"input_tokens": 50, "output_tokens": 20, "total_tokens": 70}}}),
+        ]);
+        let (url, server) = serve_once("text/event-stream", body).await;
+        let mut client = OpenAIClient::new("key".to_string(), "o4-mini".to_string())
+            .with_base_url(url)
+            .with_responses_models(&["o4*"])
+            .unwrap();
+        client.load_system_prompt("Be brief.").unwrap();
+        client.add_user_message("What is in a.txt?");
+
+        let mut rx = client.send_message_stream("").await.unwrap();
+        let mut events = Vec::new();
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+
+        assert_eq!(events, vec![
+            StreamEvent::ReasoningDelta("Need the file.".to_string()),
+            StreamEvent::TextDelta("Reading.".to_string()),
+            StreamEvent::ToolCallStart { index: 0, id: "call_1".to_string(), name: "read_file".to_string() },
+            StreamEvent::ToolCallArgsDelta { index: 0, delta: "{\"path\":\"a.txt\"}".to_string() },
+            StreamEvent::ToolCallComplete { index: 0, id: "call_1".to_string(), name: "read_file".to_string(), args: serde_json::json!({"path": "a.txt"}) },
+            StreamEvent::Usage(TokenUsage { prompt_tokens: 50, completion_tokens: 20, total_tokens: 70 }),
+            StreamEvent::FinishReason("tool_calls".to_string()),
+        ]);
+        let request = server.await.unwrap();
+        assert!(request.starts_with("POST /responses "), "{}", request);
+        assert!(!request.contains("previous_response_id"));
+
+        // The follow-up only carries the tool output and points at the stored response
+        let call = FunctionCall { id: Some("call_1".to_string()), name: "read_file".to_string(), args: serde_json::json!({"path": "a.txt"}) };
+        client.add_model_response("Reading.", std::slice::from_ref(&call));
+        client.add_function_response(&FunctionResponse {
+            id: call.call_id(),
+            name: call.name.clone(),
+            response: serde_json::json!({"success": true, "content": "hello"}),
+        });
+        let request = serde_json::to_value(client.build_responses_request(None, true)).unwrap();
+        assert_eq!(request["previous_response_id"], "resp_1");
+        assert_eq!(request["instructions"], "Be brief.");
+        assert_eq!(request["input"], serde_json::json!([
+            {"type": "function_call_output", "call_id": "call_1", "output": "{\"content\":\"hello\",\"success\":true}"}
+        ]));
+
+        // A new conversation starts a new chain
+        client.clear_conversation();
+        client.add_user_message("Hi");
+        let request = serde_json::to_value(client.build_responses_request(None, true)).unwrap();
+        assert!(request.get("previous_response_id").is_none());
+        assert_eq!(request["input"], serde_json::json!([
+            {"type": "message", "role": "user", "content": [{"type": "input_text", "text": "Hi"}]}
+        ]));
+    }
+}
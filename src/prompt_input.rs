@@ -1,4 +1,5 @@
-use std::io::{self, Write};
+use std::io;
+use crate::config::DEFAULT_WIDTH;
 use crossterm::{
     cursor,
     execute,
@@ -13,7 +14,7 @@ pub struct PromptInput {
 impl PromptInput {
     pub fn new() -> Self {
         Self {
-            width: 120,
+            width: DEFAULT_WIDTH,
             prompt_text: "> ".to_string(),
         }
     }
@@ -37,7 +38,7 @@ impl PromptInput {
         
         // Clear the help text line after input submission
         execute!(io::stdout(), cursor::MoveDown(1), cursor::MoveToColumn(1))?;
-        println!("{}", " ".repeat(120)); // Clear the help text line
+        println!("{}", " ".repeat(self.width)); // Clear the help text line
         execute!(io::stdout(), cursor::MoveUp(1))?; // Move back up
         
         Ok(input.trim().to_string())
@@ -51,8 +52,8 @@ impl PromptInput {
         println!("╭{}╮", "─".repeat(self.width.saturating_sub(2)));
         
         // Input line with prompt and padding to complete the box
-        let remaining_space = content_width.saturating_sub(2); // Account for "> "
-        println!("│ > {}{} │", " ".repeat(remaining_space), "");
+        let remaining_space = content_width.saturating_sub(self.prompt_text.chars().count());
+        println!("│ {}{} │", self.prompt_text, " ".repeat(remaining_space));
         
         // Bottom border
         println!("╰{}╯", "─".repeat(self.width.saturating_sub(2)));
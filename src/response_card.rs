@@ -1,25 +1,40 @@
+use crate::config::DEFAULT_WIDTH;
+use crate::markdown::{self, MarkdownRenderer};
+use crossterm::style::{Attribute, SetAttribute};
 use std::io::{self, Write};
 
 /// Formats and displays responses in a bordered card format
 pub struct ResponseCard {
     width: usize,
     title: String,
+    /// Text set into the bottom border, e.g. token usage
+    footer: Option<String>,
+    /// Render content as Markdown instead of printing it as is
+    markdown: bool,
+    /// Renderer of the content being streamed
+    renderer: Option<MarkdownRenderer>,
 }
 
 impl ResponseCard {
     /// Create a new response card with default settings
     pub fn new() -> Self {
         Self {
-            width: 120, // Default width that fits most terminals
+            width: DEFAULT_WIDTH,
             title: "Response".to_string(),
+            footer: None,
+            markdown: false,
+            renderer: None,
         }
     }
 
     /// Create a new response card with custom title
     pub fn with_title(title: &str) -> Self {
         Self {
-            width: 120,
+            width: DEFAULT_WIDTH,
             title: title.to_string(),
+            footer: None,
+            markdown: false,
+            renderer: None,
         }
     }
 
@@ -29,6 +44,17 @@ impl ResponseCard {
         self
     }
 
+    /// Render content as Markdown: styled headings, emphasis, lists, tables and framed code
+    pub fn with_markdown(mut self, markdown: bool) -> Self {
+        self.markdown = markdown;
+        self
+    }
+
+    /// Show `footer` in the bottom border, right-aligned
+    pub fn set_footer(&mut self, footer: &str) {
+        self.footer = Some(footer.to_string());
+    }
+
     /// Display a complete response in a bordered card
     pub fn display_complete(&self, content: &str) -> io::Result<()> {
         self.print_header()?;
@@ -39,7 +65,8 @@ impl ResponseCard {
     }
 
     /// Start a streaming response card (prints header only)
-    pub fn start_streaming(&self) -> io::Result<()> {
+    pub fn start_streaming(&mut self) -> io::Result<()> {
+        self.renderer = self.markdown.then(|| MarkdownRenderer::new(self.content_width()));
         self.print_header()?;
         print!("│ ");
         io::stdout().flush()?;
@@ -47,9 +74,19 @@ impl ResponseCard {
     }
 
     /// Add content to a streaming response (no borders, just content)
-    pub fn stream_content(&self, chunk: &str) -> io::Result<()> {
+    pub fn stream_content(&mut self, chunk: &str) -> io::Result<()> {
+        match &mut self.renderer {
+            Some(renderer) => {
+                let rendered = renderer.push(chunk);
+                Self::write_streamed(&rendered)
+            }
+            None => Self::write_streamed(chunk),
+        }
+    }
+
+    fn write_streamed(text: &str) -> io::Result<()> {
         // Handle line breaks in streaming content
-        let lines: Vec<&str> = chunk.split('\n').collect();
+        let lines: Vec<&str> = text.split('\n').collect();
         
         for (i, line) in lines.iter().enumerate() {
             if i > 0 {
@@ -64,7 +101,10 @@ impl ResponseCard {
     }
 
     /// End a streaming response card (prints footer)
-    pub fn end_streaming(&self) -> io::Result<()> {
+    pub fn end_streaming(&mut self) -> io::Result<()> {
+        if let Some(mut renderer) = self.renderer.take() {
+            Self::write_streamed(&renderer.finish())?;
+        }
         // Ensure we're on a new line and add padding to reach the border
         println!();
         self.print_footer()?;
@@ -93,15 +133,33 @@ impl ResponseCard {
 
     /// Print the bottom border
     fn print_footer(&self) -> io::Result<()> {
-        print!("╰");
-        print!("{}", "─".repeat(self.width.saturating_sub(2)));
-        println!("╯");
+        println!("{}", self.footer_line());
         Ok(())
     }
 
+    fn footer_line(&self) -> String {
+        let inner_width = self.width.saturating_sub(2);
+        match &self.footer {
+            Some(footer) if footer.chars().count() + 4 <= inner_width => {
+                let label = format!(" {} ", footer);
+                let left = inner_width - label.chars().count() - 1;
+                format!("╰{}{}─╯", "─".repeat(left), label)
+            }
+            _ => format!("╰{}╯", "─".repeat(inner_width)),
+        }
+    }
+
     /// Print content with proper word wrapping and borders
     fn print_content(&self, content: &str) -> io::Result<()> {
-        let content_width = self.width.saturating_sub(4); // Account for "│ " on both sides
+        let content_width = self.content_width();
+        let reset = if self.markdown { SetAttribute(Attribute::Reset).to_string() } else { String::new() };
+        let rendered;
+        let content = if self.markdown {
+            rendered = MarkdownRenderer::new(content_width).render(content);
+            rendered.as_str()
+        } else {
+            content
+        };
         
         for line in content.lines() {
             if line.is_empty() {
@@ -110,18 +168,27 @@ impl ResponseCard {
             }
             
             // Word wrap long lines
+            // Styles are closed before the border and reopened on the next wrapped line
             let wrapped_lines = self.wrap_text(line, content_width);
+            let mut carried = String::new();
             for wrapped_line in wrapped_lines {
-                let padding = content_width.saturating_sub(wrapped_line.len());
-                println!("│ {}{} │", wrapped_line, " ".repeat(padding));
+                let padding = content_width.saturating_sub(markdown::visible_width(&wrapped_line));
+                println!("│ {}{}{}{} │", carried, wrapped_line, reset, " ".repeat(padding));
+                carried = markdown::open_styles(&format!("{}{}", carried, wrapped_line));
             }
         }
         Ok(())
     }
 
+    /// Columns inside the borders, accounting for "│ " on both sides
+    fn content_width(&self) -> usize {
+        self.width.saturating_sub(4)
+    }
+
     /// Wrap text to fit within the specified width
     fn wrap_text(&self, text: &str, width: usize) -> Vec<String> {
-        if text.len() <= width {
+        let width_of = markdown::visible_width;
+        if width_of(text) <= width {
             return vec![text.to_string()];
         }
 
@@ -131,7 +198,7 @@ impl ResponseCard {
         for word in text.split_whitespace() {
             if current_line.is_empty() {
                 current_line = word.to_string();
-            } else if current_line.len() + 1 + word.len() <= width {
+            } else if width_of(&current_line) + 1 + width_of(word) <= width {
                 current_line.push(' ');
                 current_line.push_str(word);
             } else {
@@ -180,4 +247,15 @@ mod tests {
         assert_eq!(custom_card.title, "Custom");
         assert_eq!(custom_card.width, 80);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_footer_sits_in_the_bottom_border() {
+        let mut card = ResponseCard::new().with_width(30);
+        assert_eq!(card.footer_line().chars().count(), 30);
+
+        card.set_footer("12 in · 3 out");
+        let line = card.footer_line();
+        assert_eq!(line.chars().count(), 30);
+        assert!(line.ends_with(" 12 in · 3 out ─╯"));
+    }
+}
@@ -0,0 +1,226 @@
+use anyhow::{anyhow, Result};
+use reqwest::header::HeaderMap;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// Attempts per request, counting the first, unless `CHAT_CLI_MAX_ATTEMPTS` says otherwise
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 4;
+
+/// When and how often a failed HTTP request is sent again.
+///
+/// Delays grow exponentially from `base_delay` with random jitter, so clients that failed
+/// together do not retry together. A `Retry-After` from the server replaces the computed
+/// delay; one longer than `max_delay` ends the retries instead of stalling the chat.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+
+    /// A policy that sends each request once
+    pub fn no_retries() -> Self {
+        Self::new().with_max_attempts(1)
+    }
+
+    /// Settings from `CHAT_CLI_MAX_ATTEMPTS`
+    pub fn from_env() -> Result<Self> {
+        let mut policy = Self::new();
+        if let Ok(value) = std::env::var("CHAT_CLI_MAX_ATTEMPTS") {
+            let attempts = value.parse().map_err(|e| anyhow!("Invalid CHAT_CLI_MAX_ATTEMPTS '{}': {}", value, e))?;
+            policy = policy.with_max_attempts(attempts);
+        }
+        Ok(policy)
+    }
+
+    /// Total attempts per request, including the first (at least 1)
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Delay before the first retry and the cap on any delay
+    pub fn with_delays(mut self, base_delay: Duration, max_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self.max_delay = max_delay.max(base_delay);
+        self
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// How long to wait after failed attempt number `attempt` (starting at 1), or `None` to give up
+    pub fn delay_for(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        if let Some(retry_after) = retry_after {
+            return (retry_after <= self.max_delay).then_some(retry_after);
+        }
+        let ceiling = self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(self.max_delay);
+        Some(ceiling.mul_f64(jitter()))
+    }
+
+    /// Send the request built by `build`, sending it again after retryable failures.
+    /// Error responses that are not retried become `API request failed (status): body`.
+    ///
+    /// Streaming requests use this too: they are retried only until the server accepts
+    /// them, since repeating a stream that has started would show its text twice.
+    pub async fn send<F>(&self, mut build: F) -> Result<Response>
+    where
+        F: FnMut() -> RequestBuilder,
+    {
+        let mut attempt = 1;
+        loop {
+            let delay = match build().send().await {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response) => {
+                    let status = response.status();
+                    let delay = if is_retryable_status(status) {
+                        self.delay_for(attempt, retry_after(response.headers()))
+                    } else {
+                        None
+                    };
+                    match delay {
+                        Some(delay) => delay,
+                        None => {
+                            let body = response.text().await.unwrap_or_default();
+                            return Err(anyhow!("API request failed ({}): {}", status, body));
+                        }
+                    }
+                }
+                // Gemini puts its API key in the URL, so it is left out of errors
+                Err(e) => match self.delay_for(attempt, None).filter(|_| is_retryable_error(&e)) {
+                    Some(delay) => delay,
+                    None => return Err(e.without_url().into()),
+                },
+            };
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Rate limits, timeouts, overload and server errors; anything else will fail the same way again
+pub fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(status.as_u16(), 408 | 409 | 425 | 429 | 500 | 502 | 503 | 504 | 529)
+}
+
+/// Failures to connect or to get a response in time
+pub fn is_retryable_error(error: &reqwest::Error) -> bool {
+    error.is_timeout() || error.is_connect() || error.is_request()
+}
+
+/// The server's requested delay from `retry-after-ms`, or `Retry-After` in seconds or as an HTTP date
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).map(str::trim);
+    if let Some(millis) = header("retry-after-ms").and_then(|value| value.parse::<f64>().ok()) {
+        return Some(Duration::from_secs_f64(millis.max(0.0) / 1000.0));
+    }
+    let value = header("retry-after")?;
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Some(Duration::from_secs_f64(seconds.max(0.0)));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some((date.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().unwrap_or_default())
+}
+
+/// A random factor in [0.5, 1), so a delay never collapses to nothing
+fn jitter() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    0.5 + (random % 1_000_000) as f64 / 2_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_delays_grow_with_jitter_and_respect_retry_after() {
+        let policy = RetryPolicy::new().with_delays(Duration::from_millis(100), Duration::from_secs(1));
+
+        for attempt in 1..4 {
+            let ceiling = Duration::from_millis(100 * 2u64.pow(attempt - 1));
+            let delay = policy.delay_for(attempt, None).unwrap();
+            assert!(delay >= ceiling / 2 && delay < ceiling, "attempt {}: {:?}", attempt, delay);
+        }
+        assert_eq!(policy.delay_for(4, None), None);
+        assert_eq!(policy.delay_for(1, Some(Duration::from_millis(700))), Some(Duration::from_millis(700)));
+        assert_eq!(policy.delay_for(1, Some(Duration::from_secs(5))), None);
+    }
+
+    #[test]
+    fn test_retry_after_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", HeaderValue::from_static("3"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(3)));
+
+        headers.insert("retry-after-ms", HeaderValue::from_static("250"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_millis(250)));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"));
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+
+        assert!(is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!is_retryable_status(StatusCode::BAD_REQUEST));
+    }
+
+    /// Answer successive connections with `responses`, raw status line and headers included
+    async fn serve_sequence(responses: Vec<&'static str>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 4096];
+                let _ = socket.read(&mut buf).await.unwrap();
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_send_retries_rate_limits_then_succeeds() {
+        let url = serve_sequence(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0\r\nContent-Length: 4\r\nConnection: close\r\n\r\nslow",
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 4\r\nConnection: close\r\n\r\nbusy",
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+        ]).await;
+        let policy = RetryPolicy::new().with_delays(Duration::from_millis(1), Duration::from_millis(10));
+        let client = reqwest::Client::new();
+
+        let response = policy.send(|| client.get(&url)).await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "ok");
+
+        let url = serve_sequence(vec![
+            "HTTP/1.1 400 Bad Request\r\nContent-Length: 3\r\nConnection: close\r\n\r\nbad",
+        ]).await;
+        let error = policy.send(|| client.get(&url)).await.unwrap_err();
+        assert_eq!(error.to_string(), "API request failed (400 Bad Request): bad");
+    }
+}
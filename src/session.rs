@@ -0,0 +1,257 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::chat_client::ChatClient;
+use crate::conversation::{Conversation, Role};
+use crate::usage::SessionUsage;
+
+/// Longest session title shown in listings
+const MAX_TITLE_CHARS: usize = 60;
+
+/// A saved conversation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    /// The first user message, shortened
+    pub title: String,
+    pub provider: String,
+    pub model: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub conversation: Conversation,
+    /// Tokens used by the session's requests, per model
+    #[serde(default)]
+    pub usage: SessionUsage,
+}
+
+impl Session {
+    pub fn new(provider: &str, model: &str) -> Self {
+        let now = Utc::now();
+        Self {
+            id: format!("{}-{:04x}", now.format("%Y%m%d-%H%M%S"), now.timestamp_subsec_nanos() & 0xffff),
+            title: String::new(),
+            provider: provider.to_string(),
+            model: model.to_string(),
+            created_at: now,
+            updated_at: now,
+            conversation: Conversation::new(),
+            usage: SessionUsage::new(),
+        }
+    }
+
+    /// Take the client's current conversation, provider and model
+    pub fn update_from(&mut self, client: &dyn ChatClient) {
+        self.conversation = client.conversation();
+        self.provider = client.client_name().to_string();
+        self.model = client.model().to_string();
+        self.updated_at = Utc::now();
+        if self.title.is_empty() {
+            self.title = self.conversation.messages().iter()
+                .find(|m| m.role == Role::User)
+                .map(|m| shorten(&m.text()))
+                .unwrap_or_default();
+        }
+    }
+
+    /// Replace the client's conversation with this session's
+    pub fn restore_into(&self, client: &mut dyn ChatClient) {
+        client.set_conversation(self.conversation.clone());
+    }
+}
+
+fn shorten(text: &str) -> String {
+    let line = text.lines().next().unwrap_or_default().trim();
+    if line.chars().count() > MAX_TITLE_CHARS {
+        format!("{}...", line.chars().take(MAX_TITLE_CHARS).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+/// Saves sessions as one JSON file each under a directory
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// `<data dir>/chat-cli/sessions`, e.g. `~/.local/share/chat-cli/sessions` on Linux
+    pub fn default_location() -> Self {
+        let data_dir = dirs::data_dir().unwrap_or_else(|| PathBuf::from("."));
+        Self::new(data_dir.join("chat-cli").join("sessions"))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The file of session `id`. Ids are made of letters, digits, `-` and `_` (see `Session::new`),
+    /// so an id can never name a file outside the store.
+    fn path_for(&self, id: &str) -> Result<PathBuf> {
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(anyhow!("Invalid session id '{}'", id));
+        }
+        Ok(self.dir.join(format!("{}.json", id)))
+    }
+
+    pub fn save(&self, session: &Session) -> Result<PathBuf> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path_for(&session.id)?;
+        // Write then rename, so an interrupted save never leaves a truncated session
+        let temp_path = path.with_extension("json.tmp");
+        fs::write(&temp_path, serde_json::to_string_pretty(session)?)?;
+        fs::rename(&temp_path, &path)?;
+        Ok(path)
+    }
+
+    /// Load a session by id, or by a prefix that matches exactly one id
+    pub fn load(&self, id: &str) -> Result<Session> {
+        let id = self.resolve_id(id)?;
+        let content = fs::read_to_string(self.path_for(&id)?)?;
+        serde_json::from_str(&content).with_context(|| format!("Session {} is corrupt", id))
+    }
+
+    pub fn delete(&self, id: &str) -> Result<String> {
+        let id = self.resolve_id(id)?;
+        fs::remove_file(self.path_for(&id)?)?;
+        Ok(id)
+    }
+
+    /// All sessions, most recently updated first
+    pub fn list(&self) -> Result<Vec<Session>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut sessions: Vec<Session> = entries
+            .flatten()
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+            .filter_map(|content| serde_json::from_str(&content).ok())
+            .collect();
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.updated_at));
+        Ok(sessions)
+    }
+
+    /// The most recently updated session, if any
+    pub fn latest(&self) -> Result<Option<Session>> {
+        Ok(self.list()?.into_iter().next())
+    }
+
+    /// Usage of the sessions last updated at or after `since`, e.g. for a monthly total.
+    /// Session `except` is left out, so a running session can add its own, newer usage.
+    pub fn usage_since(&self, since: DateTime<Utc>, except: &str) -> Result<SessionUsage> {
+        let mut usage = SessionUsage::new();
+        for session in self.list()?.iter().filter(|session| session.updated_at >= since && session.id != except) {
+            usage.merge(&session.usage);
+        }
+        Ok(usage)
+    }
+
+    fn resolve_id(&self, id: &str) -> Result<String> {
+        if self.path_for(id)?.exists() {
+            return Ok(id.to_string());
+        }
+        let matches: Vec<String> = self.list()?
+            .into_iter()
+            .map(|session| session.id)
+            .filter(|session_id| session_id.starts_with(id))
+            .collect();
+        match matches.as_slice() {
+            [only] => Ok(only.clone()),
+            [] => Err(anyhow!("No session matches '{}'", id)),
+            _ => Err(anyhow!("'{}' matches {} sessions; give more of the id", id, matches.len())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversation::ChatMessage;
+    use crate::function_calling::{FunctionCall, FunctionResponse};
+    use crate::mock_llm::MockLLMClient;
+    use crate::chat_client::TokenUsage;
+
+    fn conversation() -> Conversation {
+        let call = FunctionCall {
+            id: Some("call-1".to_string()),
+            name: "shell_command".to_string(),
+            args: serde_json::json!({"command": "ls"}),
+        };
+        let mut conversation = Conversation::new();
+        conversation.system_prompt = Some("You are a helpful assistant.".to_string());
+        conversation.push(ChatMessage::user("What is in this directory?"));
+        conversation.push(ChatMessage::assistant("", &[call]).with_model("mock"));
+        conversation.push(ChatMessage::tool_result(&FunctionResponse {
+            id: "call-1".to_string(),
+            name: "shell_command".to_string(),
+            response: serde_json::json!({"success": true, "stdout": "Cargo.toml\n"}),
+        }));
+        conversation.push(ChatMessage::assistant("Just Cargo.toml.", &[]).with_model("mock"));
+        conversation
+    }
+
+    #[test]
+    fn test_session_round_trips_through_a_client() {
+        let mut session = Session::new("Mock", "mock-model");
+        session.conversation = conversation();
+        let mut client = MockLLMClient::new();
+
+        session.restore_into(&mut client);
+        let mut copy = Session::new("Mock", "mock-model");
+        copy.update_from(&client);
+
+        assert_eq!(copy.conversation, session.conversation);
+        assert_eq!(copy.title, "What is in this directory?");
+    }
+
+    #[test]
+    fn test_store_save_list_load_and_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path());
+
+        let mut older = Session::new("Mock", "mock-model");
+        older.id = "20240101-000000-aaaa".to_string();
+        older.updated_at -= chrono::Duration::days(1);
+        older.usage.add("mock-model", &TokenUsage { prompt_tokens: 100, completion_tokens: 10, total_tokens: 110 });
+        let mut newer = Session::new("Mock", "mock-model");
+        newer.id = "20240102-000000-bbbb".to_string();
+        newer.conversation = conversation();
+        newer.usage.add("mock-model", &TokenUsage { prompt_tokens: 20, completion_tokens: 5, total_tokens: 25 });
+        store.save(&older).unwrap();
+        store.save(&newer).unwrap();
+
+        assert_eq!(store.latest().unwrap().unwrap().id, newer.id);
+        assert_eq!(store.load("20240102").unwrap().conversation, newer.conversation);
+        assert!(store.load("2024").is_err());
+        assert_eq!(store.load(&older.id).unwrap().usage, older.usage);
+        assert_eq!(store.usage_since(older.updated_at, "").unwrap().total().prompt_tokens, 120);
+        assert_eq!(store.usage_since(newer.updated_at, "").unwrap().total().prompt_tokens, 20);
+        assert_eq!(store.usage_since(older.updated_at, &newer.id).unwrap().total().prompt_tokens, 100);
+
+        store.delete(&older.id).unwrap();
+        assert_eq!(store.list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_ids_cannot_leave_the_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path().join("sessions"));
+        let victim = dir.path().join("victim.json");
+        std::fs::write(&victim, "{}").unwrap();
+
+        for id in ["../victim", "..", "a/b", "a\\b", ""] {
+            assert!(store.load(id).is_err(), "{}", id);
+            assert!(store.delete(id).is_err(), "{}", id);
+        }
+        assert!(victim.exists());
+    }
+}
@@ -0,0 +1,288 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use crate::chat_client::TokenUsage;
+
+/// Project price file, read after the user's `~/.config/chat-cli/prices.toml`
+pub const PROJECT_PRICE_FILE: &str = ".chat-cli/prices.toml";
+
+/// Built-in prices in USD per million tokens (input, output); price files override them
+const DEFAULT_PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-4", 30.0, 60.0),
+    ("gpt-4-turbo*", 10.0, 30.0),
+    ("gpt-4o*", 2.5, 10.0),
+    ("gpt-4o-mini*", 0.15, 0.6),
+    ("gpt-4.1*", 2.0, 8.0),
+    ("gpt-4.1-mini*", 0.4, 1.6),
+    ("gpt-4.1-nano*", 0.1, 0.4),
+    ("gpt-5*", 1.25, 10.0),
+    ("gpt-5-mini*", 0.25, 2.0),
+    ("gpt-5-nano*", 0.05, 0.4),
+    ("o1*", 15.0, 60.0),
+    ("o3*", 2.0, 8.0),
+    ("o3-mini*", 1.1, 4.4),
+    ("o4-mini*", 1.1, 4.4),
+    ("claude-opus-4*", 15.0, 75.0),
+    ("claude-sonnet-4*", 3.0, 15.0),
+    ("claude-3-5-haiku*", 0.8, 4.0),
+    ("claude-haiku-4*", 1.0, 5.0),
+    ("gemini-2.0-flash*", 0.1, 0.4),
+    ("gemini-2.5-flash*", 0.3, 2.5),
+    ("gemini-2.5-pro*", 1.25, 10.0),
+];
+
+/// What a model costs, in USD per million tokens
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+}
+
+impl ModelPrice {
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        self.cost_of(usage.prompt_tokens as u64, usage.completion_tokens as u64)
+    }
+
+    fn cost_of(&self, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+        (prompt_tokens as f64 * self.input + completion_tokens as f64 * self.output) / 1_000_000.0
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PriceFile {
+    #[serde(default)]
+    models: BTreeMap<String, ModelPrice>,
+}
+
+/// Prices by model name or glob pattern (`gpt-4o*`). An exact name wins, then the longest matching pattern.
+#[derive(Debug, Clone, Default)]
+pub struct PriceTable {
+    prices: BTreeMap<String, ModelPrice>,
+}
+
+impl PriceTable {
+    /// An empty table; every model's cost is unknown
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The built-in prices of common hosted models
+    pub fn with_defaults() -> Self {
+        let mut table = Self::new();
+        for (model, input, output) in DEFAULT_PRICES {
+            table.set(model, ModelPrice { input: *input, output: *output });
+        }
+        table
+    }
+
+    pub fn set(&mut self, model: &str, price: ModelPrice) {
+        self.prices.insert(model.to_string(), price);
+    }
+
+    /// Add the prices of a TOML file with a `[models]` table, e.g. `"gpt-4o*" = { input = 2.5, output = 10.0 }`
+    pub fn merge_toml(mut self, content: &str) -> Result<Self> {
+        let file: PriceFile = toml::from_str(content)?;
+        self.prices.extend(file.models);
+        Ok(self)
+    }
+
+    /// Add the prices of `path` if it exists
+    pub fn merge_file(self, path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => self.merge_toml(&content)
+                .with_context(|| format!("Invalid price table {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(self),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Price files read by `load`: the user's, then the project's
+    pub fn default_paths() -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        if let Some(config_dir) = dirs::config_dir() {
+            paths.push(config_dir.join("chat-cli").join("prices.toml"));
+        }
+        paths.push(PathBuf::from(PROJECT_PRICE_FILE));
+        paths
+    }
+
+    /// The built-in prices overridden by the user and project price files
+    pub fn load() -> Result<Self> {
+        Self::default_paths()
+            .iter()
+            .try_fold(Self::with_defaults(), |table, path| table.merge_file(path))
+    }
+
+    pub fn price(&self, model: &str) -> Option<ModelPrice> {
+        if let Some(price) = self.prices.get(model) {
+            return Some(*price);
+        }
+        self.prices.iter()
+            .filter(|(pattern, _)| glob::Pattern::new(pattern).is_ok_and(|p| p.matches(model)))
+            .max_by_key(|(pattern, _)| pattern.len())
+            .map(|(_, price)| *price)
+    }
+
+    /// Cost of `usage` on `model`, if the model has a price
+    pub fn cost(&self, model: &str, usage: &TokenUsage) -> Option<f64> {
+        self.price(model).map(|price| price.cost(usage))
+    }
+}
+
+/// Token counts added up for one model
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelUsage {
+    pub requests: u32,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl ModelUsage {
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+/// Token usage of a session, per model
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionUsage {
+    pub models: BTreeMap<String, ModelUsage>,
+}
+
+impl SessionUsage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count one request to `model`
+    pub fn add(&mut self, model: &str, usage: &TokenUsage) {
+        let entry = self.models.entry(model.to_string()).or_default();
+        entry.requests += 1;
+        entry.prompt_tokens += usage.prompt_tokens as u64;
+        entry.completion_tokens += usage.completion_tokens as u64;
+    }
+
+    /// Add another session's counts to these
+    pub fn merge(&mut self, other: &SessionUsage) {
+        for (model, usage) in &other.models {
+            let entry = self.models.entry(model.clone()).or_default();
+            entry.requests += usage.requests;
+            entry.prompt_tokens += usage.prompt_tokens;
+            entry.completion_tokens += usage.completion_tokens;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.models.is_empty()
+    }
+
+    /// Total over all models
+    pub fn total(&self) -> ModelUsage {
+        let mut total = ModelUsage::default();
+        for usage in self.models.values() {
+            total.requests += usage.requests;
+            total.prompt_tokens += usage.prompt_tokens;
+            total.completion_tokens += usage.completion_tokens;
+        }
+        total
+    }
+
+    /// Cost of `model`'s usage, if it has a price
+    pub fn model_cost(&self, model: &str, prices: &PriceTable) -> Option<f64> {
+        let usage = self.models.get(model)?;
+        prices.price(model).map(|price| price.cost_of(usage.prompt_tokens, usage.completion_tokens))
+    }
+
+    /// Cost of the models that have a price, and the names of those that do not
+    pub fn cost(&self, prices: &PriceTable) -> (f64, Vec<String>) {
+        let mut cost = 0.0;
+        let mut unpriced = Vec::new();
+        for model in self.models.keys() {
+            match self.model_cost(model, prices) {
+                Some(model_cost) => cost += model_cost,
+                None => unpriced.push(model.clone()),
+            }
+        }
+        (cost, unpriced)
+    }
+}
+
+/// A token count with thousands separators, e.g. `12,345`
+pub fn format_tokens(tokens: u64) -> String {
+    let digits = tokens.to_string();
+    let mut formatted = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// A dollar amount with enough digits to show small costs, e.g. `$0.0042`
+pub fn format_cost(cost: f64) -> String {
+    if cost < 1.0 {
+        format!("${:.4}", cost)
+    } else {
+        format!("${:.2}", cost)
+    }
+}
+
+/// One-line summary of a request's usage, as shown under a response
+pub fn usage_summary(usage: &TokenUsage, cost: Option<f64>) -> String {
+    let mut summary = format!(
+        "{} in · {} out",
+        format_tokens(usage.prompt_tokens as u64),
+        format_tokens(usage.completion_tokens as u64)
+    );
+    if let Some(cost) = cost {
+        summary.push_str(" · ");
+        summary.push_str(&format_cost(cost));
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(prompt_tokens: u32, completion_tokens: u32) -> TokenUsage {
+        TokenUsage { prompt_tokens, completion_tokens, total_tokens: prompt_tokens + completion_tokens }
+    }
+
+    #[test]
+    fn test_price_lookup_prefers_exact_then_longest_pattern() {
+        let prices = PriceTable::with_defaults()
+            .merge_toml("[models]\n\"gpt-4o-2024-08-06\" = { input = 1.0, output = 2.0 }\n")
+            .unwrap();
+
+        assert_eq!(prices.price("gpt-4o-mini-2024-07-18").unwrap().input, 0.15);
+        assert_eq!(prices.price("gpt-4o-2024-11-20").unwrap().input, 2.5);
+        assert_eq!(prices.price("gpt-4o-2024-08-06").unwrap().input, 1.0);
+        assert!(prices.price("llama3.2").is_none());
+        assert!(PriceTable::new().merge_toml("[models]\nx = { input = 1.0 }\n").is_err());
+    }
+
+    #[test]
+    fn test_session_usage_adds_up_and_prices_per_model() {
+        let prices = PriceTable::with_defaults();
+        let mut session = SessionUsage::new();
+        session.add("gpt-4o", &usage(1_000_000, 100_000));
+        session.add("gpt-4o", &usage(500_000, 0));
+        session.add("llama3.2", &usage(2_000, 300));
+
+        let total = session.total();
+        assert_eq!(total.requests, 3);
+        assert_eq!(total.prompt_tokens, 1_502_000);
+
+        let (cost, unpriced) = session.cost(&prices);
+        assert!((cost - (3.75 + 1.0)).abs() < 1e-9);
+        assert_eq!(unpriced, ["llama3.2"]);
+
+        assert_eq!(format_tokens(1_502_000), "1,502,000");
+        assert_eq!(usage_summary(&usage(1234, 56), Some(0.0042)), "1,234 in · 56 out · $0.0042");
+    }
+}